@@ -0,0 +1,180 @@
+//! forwarding between the udp socket and tcp connections, see [`Bridge`]
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use crate::tcp::TcpEvent;
+use crate::udp::UdpEvent;
+
+/*
+    udp datagram  ->  one message to the tcp peers, cut on the stream
+                      by the tcp framing (eg len:2), so the other end
+                      gets the datagram boundaries back
+    tcp message   ->  one datagram to the udp target, a message is a
+                      frame with a framing set or one read() without
+
+    the bridge only decides what crosses and where to, the sends
+    go through the app's own engines so that a bridged message is
+    framed, logged, counted and captured like a typed one
+
+    the app feeds it from a thread of its own, the engines hand that
+    thread a copy of what they receive (their set_tap()), so nothing
+    waits for the GUI frames, which a minimized window may not get
+*/
+
+/// where messages from the tcp side go
+#[derive(Debug, Clone, PartialEq)]
+pub enum UdpTarget {
+    /// the source of the latest datagram received
+    LastSource,
+    /// a fixed host:port
+    Fixed(String),
+}
+
+impl fmt::Display for UdpTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpTarget::LastSource => f.write_str("last UDP source"),
+            UdpTarget::Fixed(addr) => f.write_str(addr),
+        }
+    }
+}
+
+/// picks what crosses between udp and tcp, see the module notes
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    /// where tcp messages go
+    pub target: UdpTarget,
+    last_source: Option<SocketAddr>,
+    /// datagrams sent on to the tcp side
+    pub to_tcp: u64,
+    /// tcp messages sent on as datagrams
+    pub to_udp: u64,
+    /// failed sends, and tcp messages that came before any udp source
+    pub failed: u64,
+}
+
+impl Bridge {
+    /// nothing forwarded yet
+    pub fn new(target: UdpTarget) -> Self {
+        Bridge {
+            target,
+            last_source: None,
+            to_tcp: 0,
+            to_udp: 0,
+            failed: 0,
+        }
+    }
+
+    /// the source of the latest datagram received
+    pub fn last_source(&self) -> Option<SocketAddr> {
+        self.last_source
+    }
+
+    /// the payload to send to the tcp side, for received datagrams only
+    pub fn from_udp<'a>(&mut self, ev: &'a UdpEvent) -> Option<&'a [u8]> {
+        match ev {
+            UdpEvent::Packet { src, data, .. } => {
+                self.last_source = Some(*src);
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+
+    /// (udp destination, payload) for a received tcp message,
+    /// None for sends, or without a udp source to go to yet
+    pub fn from_tcp<'a>(&mut self, ev: &'a TcpEvent) -> Option<(String, &'a [u8])> {
+        let TcpEvent::Packet { peer, data, .. } = ev else {
+            return None;
+        };
+        let dst = match &self.target {
+            UdpTarget::Fixed(addr) => addr.clone(),
+            UdpTarget::LastSource => match self.last_source {
+                Some(src) => src.to_string(),
+                None => {
+                    log::warn!("[BRIDGE] no UDP source seen yet, dropped a message from {peer}");
+                    self.failed += 1;
+                    return None;
+                }
+            },
+        };
+        Some((dst, data))
+    }
+
+    /// count a send to the tcp side (`to_tcp` true) or to udp
+    pub fn record(&mut self, to_tcp: bool, ok: bool) {
+        match (ok, to_tcp) {
+            (false, _) => self.failed += 1,
+            (true, true) => self.to_tcp += 1,
+            (true, false) => self.to_udp += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tcp::fixtures::{addr, event};
+
+    fn udp_packet(src: u16, data: &[u8]) -> UdpEvent {
+        UdpEvent::Packet {
+            src: addr(src),
+            data: data.to_vec(),
+            timestamp: chrono::Local::now(),
+            truncated: None,
+        }
+    }
+
+    #[test]
+    fn test_bridge_last_source() {
+        let mut bridge = Bridge::new(UdpTarget::LastSource);
+        // nowhere to go yet
+        assert_eq!(bridge.from_tcp(&event(false, 502, b"early")), None);
+        assert_eq!(bridge.failed, 1);
+
+        assert_eq!(
+            bridge.from_udp(&udp_packet(9000, b"ping")),
+            Some(&b"ping"[..])
+        );
+        assert_eq!(
+            bridge.from_udp(&udp_packet(9001, b"ping")),
+            Some(&b"ping"[..])
+        );
+        assert_eq!(bridge.last_source(), Some(addr(9001)));
+        assert_eq!(
+            bridge.from_tcp(&event(false, 502, b"pong")),
+            Some(("127.0.0.1:9001".to_string(), &b"pong"[..]))
+        );
+
+        // our own sends never bounce back
+        assert_eq!(bridge.from_tcp(&event(true, 502, b"ping")), None);
+        let sent = UdpEvent::Sent {
+            dst: addr(9001),
+            data: b"pong".to_vec(),
+            timestamp: chrono::Local::now(),
+        };
+        assert_eq!(bridge.from_udp(&sent), None);
+    }
+
+    #[test]
+    fn test_bridge_fixed_target() {
+        let mut bridge = Bridge::new(UdpTarget::Fixed("10.0.0.7:7000".to_string()));
+        assert_eq!(
+            bridge.from_tcp(&event(false, 502, b"x")),
+            Some(("10.0.0.7:7000".to_string(), &b"x"[..]))
+        );
+        bridge.from_udp(&udp_packet(9000, b"ping"));
+        assert_eq!(
+            bridge.from_tcp(&event(false, 502, b"x")).unwrap().0,
+            "10.0.0.7:7000"
+        );
+
+        bridge.record(true, true);
+        bridge.record(false, true);
+        bridge.record(false, false);
+        assert_eq!((bridge.to_tcp, bridge.to_udp, bridge.failed), (1, 1, 1));
+        assert_eq!(bridge.target.to_string(), "10.0.0.7:7000");
+    }
+}
@@ -0,0 +1,593 @@
+//! pcapng capture of the traffic, to open in wireshark
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+use crate::network;
+use crate::tcp::TcpEvent;
+use crate::udp::UdpEvent;
+
+/*
+    the sockets only give us payloads, so every payload is wrapped
+    in made up headers built from the real addresses, which is
+    enough for wireshark to follow the conversations
+
+        ethernet    02:00:00:00:00:01 is us, ..:02 the other end
+        ip          ttl 64, don't fragment, checksums filled in
+        udp         checksum filled in, left 0 for truncated datagrams
+        tcp         PSH ACK, seq / ack counted from 1 per connection
+                    and direction, no handshake, no FIN
+
+    tcp payloads are what the app sees, framing already removed,
+    so one message is one segment (or a few if it is huge), the
+    capture shows the messages, not the bytes on the wire
+
+    a udp socket bound to 0.0.0.0 / :: has no address of its own,
+    the one the OS routes from to each peer is used instead
+
+    the file is a section header, one ethernet interface and then
+    one enhanced packet block per segment, nothing gets patched at
+    the end so a capture cut short by a kill is still readable
+*/
+
+const LOCAL_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const REMOTE_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+// pcapng block types
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+
+/// bigger tcp messages are split so that every segment fits in an ipv4 packet
+const MAX_SEGMENT: usize = 65_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    In,
+    Out,
+}
+
+/// writes the traffic of the engines as pcapng, feed it the same
+/// events the owner gets from poll_events()
+///
+/// writes are buffered, call flush() once in a while (eg every poll)
+/// so that the file is useful while the capture runs
+pub struct Capture<W: Write = BufWriter<File>> {
+    out: W,
+    ip_id: u16,
+
+    // (local, peer) -> next seq of (local to peer, peer to local)
+    tcp_seq: HashMap<(SocketAddr, SocketAddr), (u32, u32)>,
+
+    // peer -> our address towards it, for unspecified udp sockets
+    source_ips: HashMap<IpAddr, IpAddr>,
+
+    packets: u64,
+}
+
+impl Capture {
+    /// creates or truncates `path` and writes the file header
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Capture::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Capture<W> {
+    /// writes the file header to `out`
+    pub fn new(mut out: W) -> io::Result<Self> {
+        write_block(&mut out, SECTION_HEADER, &section_header())?;
+        write_block(&mut out, INTERFACE_DESCRIPTION, &interface_description())?;
+        Ok(Capture {
+            out,
+            ip_id: 0,
+            tcp_seq: HashMap::new(),
+            source_ips: HashMap::new(),
+            packets: 0,
+        })
+    }
+
+    /// packets written so far
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// `local` is the address of the udp socket, see Udp::local_addr(),
+    /// errors and Stopped are not traffic and are skipped
+    pub fn write_udp(&mut self, local: SocketAddr, ev: &UdpEvent) -> io::Result<()> {
+        let local = match ev {
+            UdpEvent::Packet { src: peer, .. } | UdpEvent::Sent { dst: peer, .. } => {
+                self.local_towards(local, *peer)
+            }
+            UdpEvent::Error(_) | UdpEvent::Stopped => local,
+        };
+        match ev {
+            UdpEvent::Packet {
+                src,
+                data,
+                timestamp,
+                truncated,
+            } => self.write_datagram(Direction::In, local, *src, data, *truncated, *timestamp),
+            UdpEvent::Sent {
+                dst,
+                data,
+                timestamp,
+            } => self.write_datagram(Direction::Out, local, *dst, data, None, *timestamp),
+            UdpEvent::Error(_) | UdpEvent::Stopped => Ok(()),
+        }
+    }
+
+    /// `local` as is unless unspecified, see the module notes
+    fn local_towards(&mut self, local: SocketAddr, peer: SocketAddr) -> SocketAddr {
+        if !local.ip().is_unspecified() {
+            return local;
+        }
+        let ip = *self
+            .source_ips
+            .entry(peer.ip())
+            .or_insert_with(|| network::source_ip_for(peer.ip()).unwrap_or(local.ip()));
+        SocketAddr::new(ip, local.port())
+    }
+
+    /// from either TcpServer or TcpClient, the payloads are the
+    /// messages without their framing
+    pub fn write_tcp(&mut self, ev: &TcpEvent) -> io::Result<()> {
+        let (dir, peer, local, data, timestamp) = match ev {
+            TcpEvent::Packet {
+                peer,
+                local,
+                data,
+                timestamp,
+            } => (Direction::In, peer, local, data, timestamp),
+            TcpEvent::Sent {
+                peer,
+                local,
+                data,
+                timestamp,
+            } => (Direction::Out, peer, local, data, timestamp),
+        };
+        for chunk in data.chunks(MAX_SEGMENT) {
+            let seq = self.tcp_seq.entry((*local, *peer)).or_insert((1, 1));
+            let (seq_num, ack_num) = match dir {
+                Direction::Out => (seq.0, seq.1),
+                Direction::In => (seq.1, seq.0),
+            };
+            match dir {
+                Direction::Out => seq.0 = seq.0.wrapping_add(chunk.len() as u32),
+                Direction::In => seq.1 = seq.1.wrapping_add(chunk.len() as u32),
+            }
+
+            let mut segment = vec![];
+            let (src, dst) = ends(dir, *local, *peer);
+            segment.extend(src.port().to_be_bytes());
+            segment.extend(dst.port().to_be_bytes());
+            segment.extend(seq_num.to_be_bytes());
+            segment.extend(ack_num.to_be_bytes());
+            segment.extend([5 << 4, 0x18]); // 20 bytes header, PSH ACK
+            segment.extend(u16::MAX.to_be_bytes()); // window
+            segment.extend([0, 0, 0, 0]); // checksum, urgent pointer
+            segment.extend(chunk);
+            let (src_ip, dst_ip) = ip_pair(src.ip(), dst.ip());
+            let sum = checksum(&[
+                &pseudo_header(src_ip, dst_ip, PROTO_TCP, segment.len()),
+                &segment,
+            ]);
+            segment[16..18].copy_from_slice(&sum.to_be_bytes());
+
+            self.write_ip(
+                dir,
+                src_ip,
+                dst_ip,
+                PROTO_TCP,
+                &segment,
+                segment.len(),
+                *timestamp,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_datagram(
+        &mut self,
+        dir: Direction,
+        local: SocketAddr,
+        peer: SocketAddr,
+        data: &[u8],
+        truncated: Option<usize>,
+        timestamp: DateTime<Local>,
+    ) -> io::Result<()> {
+        let (src, dst) = ends(dir, local, peer);
+        let (src_ip, dst_ip) = ip_pair(src.ip(), dst.ip());
+        let original = 8 + truncated.unwrap_or(data.len());
+
+        let mut datagram = vec![];
+        datagram.extend(src.port().to_be_bytes());
+        datagram.extend(dst.port().to_be_bytes());
+        datagram.extend(len16(original).to_be_bytes());
+        datagram.extend([0, 0]);
+        datagram.extend(data);
+        // the checksum would need the bytes that were cut off
+        if truncated.is_none() {
+            let sum = match checksum(&[
+                &pseudo_header(src_ip, dst_ip, PROTO_UDP, datagram.len()),
+                &datagram,
+            ]) {
+                0 => 0xffff, // 0 means no checksum
+                sum => sum,
+            };
+            datagram[6..8].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        self.write_ip(
+            dir, src_ip, dst_ip, PROTO_UDP, &datagram, original, timestamp,
+        )
+    }
+
+    /// wraps a udp datagram / tcp segment, `original` is its length
+    /// before any truncation
+    #[allow(clippy::too_many_arguments)]
+    fn write_ip(
+        &mut self,
+        dir: Direction,
+        src: IpAddr,
+        dst: IpAddr,
+        proto: u8,
+        payload: &[u8],
+        original: usize,
+        timestamp: DateTime<Local>,
+    ) -> io::Result<()> {
+        let mut frame = vec![];
+        let (src_mac, dst_mac) = match dir {
+            Direction::Out => (LOCAL_MAC, REMOTE_MAC),
+            Direction::In => (REMOTE_MAC, LOCAL_MAC),
+        };
+        frame.extend(dst_mac);
+        frame.extend(src_mac);
+
+        match (src, dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                frame.extend(0x0800u16.to_be_bytes());
+                let mut header = vec![0x45, 0];
+                header.extend(len16(20 + original).to_be_bytes());
+                header.extend(self.ip_id.to_be_bytes());
+                header.extend([0x40, 0, 64, proto, 0, 0]); // DF, ttl, checksum
+                header.extend(src.octets());
+                header.extend(dst.octets());
+                let sum = checksum(&[&header]);
+                header[10..12].copy_from_slice(&sum.to_be_bytes());
+                self.ip_id = self.ip_id.wrapping_add(1);
+                frame.extend(header);
+            }
+            (src, dst) => {
+                // ip_pair() makes both v6 if they differ
+                let (IpAddr::V6(src), IpAddr::V6(dst)) = (src, dst) else {
+                    unreachable!("mixed address families")
+                };
+                frame.extend(0x86DDu16.to_be_bytes());
+                frame.extend([0x60, 0, 0, 0]);
+                frame.extend(len16(original).to_be_bytes());
+                frame.extend([proto, 64]);
+                frame.extend(src.octets());
+                frame.extend(dst.octets());
+            }
+        }
+        frame.extend(payload);
+        let original = frame.len() - payload.len() + original;
+
+        let micros = timestamp.timestamp_micros() as u64;
+        let mut body = vec![];
+        body.extend(0u32.to_le_bytes()); // interface
+        body.extend(((micros >> 32) as u32).to_le_bytes());
+        body.extend((micros as u32).to_le_bytes());
+        body.extend((frame.len() as u32).to_le_bytes());
+        body.extend((original as u32).to_le_bytes());
+        body.extend(&frame);
+        pad(&mut body);
+        write_block(&mut self.out, ENHANCED_PACKET, &body)?;
+        self.packets += 1;
+        Ok(())
+    }
+
+    /// pushes the buffered packets to the file
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// flushes and hands back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// (source, destination) for a payload going `dir`
+fn ends(dir: Direction, local: SocketAddr, peer: SocketAddr) -> (SocketAddr, SocketAddr) {
+    match dir {
+        Direction::Out => (local, peer),
+        Direction::In => (peer, local),
+    }
+}
+
+/// a dual stack socket can pair a v6 local address with a v4 peer,
+/// both go to v6 then, with the v4 one mapped
+fn ip_pair(a: IpAddr, b: IpAddr) -> (IpAddr, IpAddr) {
+    let v6 = |ip: IpAddr| match ip {
+        IpAddr::V4(v4) => IpAddr::V6(v4.to_ipv6_mapped()),
+        v6 => v6,
+    };
+    if a.is_ipv4() == b.is_ipv4() {
+        (a, b)
+    } else {
+        (v6(a), v6(b))
+    }
+}
+
+/// the length fields are 16 bits, only a truncated datagram
+/// can claim more and it is capped
+fn len16(n: usize) -> u16 {
+    u16::try_from(n).unwrap_or(u16::MAX)
+}
+
+fn pseudo_header(src: IpAddr, dst: IpAddr, proto: u8, len: usize) -> Vec<u8> {
+    let mut out = vec![];
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            out.extend(src.octets());
+            out.extend(dst.octets());
+            out.extend([0, proto]);
+            out.extend(len16(len).to_be_bytes());
+        }
+        (src, dst) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+                IpAddr::V6(v6) => v6,
+            };
+            out.extend(v6(src).octets());
+            out.extend(v6(dst).octets());
+            out.extend((len as u32).to_be_bytes());
+            out.extend([0, 0, 0, proto]);
+        }
+    }
+    out
+}
+
+/// internet checksum over the concatenated parts,
+/// only the last part may have an odd length
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u64 = 0;
+    for part in parts {
+        for word in part.chunks(2) {
+            let hi = word[0] as u64;
+            let lo = word.get(1).copied().unwrap_or(0) as u64;
+            sum += (hi << 8) | lo;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// zeroes up to a multiple of 4 bytes
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// one option, `value` gets padded
+fn option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend(code.to_le_bytes());
+    buf.extend((value.len() as u16).to_le_bytes());
+    buf.extend(value);
+    pad(buf);
+}
+
+fn section_header() -> Vec<u8> {
+    let mut body = vec![];
+    body.extend(0x1A2B_3C4Du32.to_le_bytes()); // byte order magic
+    body.extend(1u16.to_le_bytes()); // version 1.0
+    body.extend(0u16.to_le_bytes());
+    body.extend((-1i64).to_le_bytes()); // section length unknown
+    let app = format!("udptcp {}", env!("CARGO_PKG_VERSION"));
+    option(&mut body, 4, app.as_bytes()); // shb_userappl
+    option(&mut body, 0, &[]); // end of options
+    body
+}
+
+fn interface_description() -> Vec<u8> {
+    let mut body = vec![];
+    body.extend(LINKTYPE_ETHERNET.to_le_bytes());
+    body.extend(0u16.to_le_bytes());
+    body.extend(0u32.to_le_bytes()); // no snap length
+    option(&mut body, 2, b"udptcp"); // if_name
+    option(&mut body, 0, &[]);
+    body
+}
+
+/// type, total length, body (already padded), total length again
+fn write_block(out: &mut impl Write, kind: u32, body: &[u8]) -> io::Result<()> {
+    let len = (12 + body.len()) as u32;
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    /// (block type, body) of every block, checking the lengths on the way
+    fn blocks(file: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let u32_at = |i: usize| u32::from_le_bytes(file[i..i + 4].try_into().unwrap());
+        let mut out = vec![];
+        let mut i = 0;
+        while i < file.len() {
+            let len = u32_at(i + 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(u32_at(i + len - 4) as usize, len);
+            out.push((u32_at(i), file[i + 8..i + len - 4].to_vec()));
+            i += len;
+        }
+        out
+    }
+
+    /// the ethernet frame of an enhanced packet block
+    fn frame(body: &[u8]) -> &[u8] {
+        let len = u32::from_le_bytes(body[12..16].try_into().unwrap()) as usize;
+        &body[20..20 + len]
+    }
+
+    #[test]
+    fn test_udp_v4_and_v6() {
+        let mut cap = Capture::new(vec![]).unwrap();
+        let local = addr("192.168.1.10:5000");
+        cap.write_udp(
+            local,
+            &UdpEvent::Packet {
+                src: addr("192.168.1.20:6000"),
+                data: b"hello".to_vec(),
+                timestamp: Local::now(),
+                truncated: None,
+            },
+        )
+        .unwrap();
+        cap.write_udp(
+            addr("[2001:db8::1]:5000"),
+            &UdpEvent::Sent {
+                dst: addr("10.0.0.1:7"),
+                data: b"abc".to_vec(),
+                timestamp: Local::now(),
+            },
+        )
+        .unwrap();
+        cap.write_udp(local, &UdpEvent::Stopped).unwrap();
+        assert_eq!(cap.packets(), 2);
+
+        let blocks = blocks(&cap.finish().unwrap());
+        let kinds: Vec<u32> = blocks.iter().map(|b| b.0).collect();
+        assert_eq!(
+            kinds,
+            [
+                SECTION_HEADER,
+                INTERFACE_DESCRIPTION,
+                ENHANCED_PACKET,
+                ENHANCED_PACKET
+            ]
+        );
+        assert_eq!(&blocks[0].1[..4], &0x1A2B_3C4Du32.to_le_bytes());
+
+        // received, so from the peer's mac and address
+        let f = frame(&blocks[2].1);
+        assert_eq!(f.len(), 14 + 20 + 8 + 5);
+        assert_eq!(&f[6..12], &REMOTE_MAC);
+        assert_eq!(&f[12..14], &[0x08, 0x00]);
+        let ip = &f[14..34];
+        assert_eq!(checksum(&[ip]), 0);
+        assert_eq!(ip[9], PROTO_UDP);
+        assert_eq!(&ip[12..16], &[192, 168, 1, 20]);
+        let udp = &f[34..];
+        assert_eq!(u16::from_be_bytes([udp[0], udp[1]]), 6000);
+        assert_eq!(u16::from_be_bytes([udp[2], udp[3]]), 5000);
+        assert_eq!(
+            checksum(&[
+                &pseudo_header(ip_src(ip), ip_dst(ip), PROTO_UDP, udp.len()),
+                udp
+            ]),
+            0
+        );
+        assert_eq!(&udp[8..], b"hello");
+
+        // a v6 socket sending to a v4 peer
+        let f = frame(&blocks[3].1);
+        assert_eq!(&f[12..14], &[0x86, 0xDD]);
+        assert_eq!(f.len(), 14 + 40 + 8 + 3);
+        assert_eq!(
+            &f[14 + 24..14 + 40],
+            &"::ffff:10.0.0.1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+    }
+
+    #[test]
+    fn test_udp_unspecified_local() {
+        let mut cap = Capture::new(vec![]).unwrap();
+        cap.write_udp(
+            addr("0.0.0.0:5000"),
+            &UdpEvent::Sent {
+                dst: addr("127.0.0.1:7"),
+                data: b"abc".to_vec(),
+                timestamp: Local::now(),
+            },
+        )
+        .unwrap();
+        let blocks = blocks(&cap.finish().unwrap());
+        let ip = &frame(&blocks[2].1)[14..34];
+        assert_eq!(ip_src(ip), addr("127.0.0.1:0").ip());
+    }
+
+    fn ip_src(ip: &[u8]) -> IpAddr {
+        IpAddr::from(<[u8; 4]>::try_from(&ip[12..16]).unwrap())
+    }
+
+    fn ip_dst(ip: &[u8]) -> IpAddr {
+        IpAddr::from(<[u8; 4]>::try_from(&ip[16..20]).unwrap())
+    }
+
+    #[test]
+    fn test_tcp_sequence_numbers() {
+        let local = addr("127.0.0.1:40000");
+        let peer = addr("127.0.0.1:7000");
+        let sent = |data: &[u8]| TcpEvent::Sent {
+            peer,
+            local,
+            data: data.to_vec(),
+            timestamp: Local::now(),
+        };
+        let mut cap = Capture::new(vec![]).unwrap();
+        cap.write_tcp(&sent(b"abc")).unwrap();
+        cap.write_tcp(&sent(b"de")).unwrap();
+        cap.write_tcp(&TcpEvent::Packet {
+            peer,
+            local,
+            data: b"xyz".to_vec(),
+            timestamp: Local::now(),
+        })
+        .unwrap();
+
+        let blocks = blocks(&cap.finish().unwrap());
+        // (seq, ack) of the three segments
+        let seq_ack: Vec<(u32, u32)> = blocks[2..]
+            .iter()
+            .map(|(_, body)| {
+                let tcp = &frame(body)[34..];
+                let ip = &frame(body)[14..34];
+                assert_eq!(
+                    checksum(&[
+                        &pseudo_header(ip_src(ip), ip_dst(ip), PROTO_TCP, tcp.len()),
+                        tcp
+                    ]),
+                    0
+                );
+                (
+                    u32::from_be_bytes(tcp[4..8].try_into().unwrap()),
+                    u32::from_be_bytes(tcp[8..12].try_into().unwrap()),
+                )
+            })
+            .collect();
+        assert_eq!(seq_ack, [(1, 1), (4, 1), (1, 6)]);
+    }
+}
@@ -0,0 +1,1025 @@
+use std::io::{self, BufRead, Write};
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use udptcp::capture::Capture;
+use udptcp::display::DisplayMode;
+use udptcp::framing::{self, Endian, Framing};
+use udptcp::network::{self, Cidr};
+use udptcp::replay::{self, Filter, Protocol, Replay, Timing};
+use udptcp::sockopt::{Keepalive, SocketOptions};
+use udptcp::tcp::{ClientState, Limits, ReconnectPolicy, TcpClient, TcpEvent, TcpServer};
+use udptcp::tls::{TlsClient, TlsServer};
+use udptcp::udp::{Udp, UdpEvent};
+use udptcp::xlogger::Xlogger;
+
+/*
+    headless mode, for scripts and ssh sessions
+
+        stdout      received data, nothing else
+        stderr      the same log lines the GUI shows
+        stdin       one line = one message (udp datagram / tcp frame),
+                    the line ending is not sent, use --framing 'delim:\n'
+                    if the other side expects it
+
+    the engines are the same ones the GUI drives, this is just
+    another loop that polls their events
+*/
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub const USAGE: &str = "\
+usage:
+  udptcp                                          start the GUI
+  udptcp udp [--bind ADDR] [--send HOST:PORT] [MSG]
+  udptcp tcp-server --port PORT [--bind IP] [--tls]
+  udptcp tcp-client HOST:PORT [--local ADDR] [--reconnect] [--tls] [MSG]
+  udptcp help
+  any of the above with --replay FILE instead of MSG
+
+received data goes to stdout, logs go to stderr
+without MSG every line read from stdin is sent as one message,
+tcp-server sends it to all connected clients
+udp without --send and tcp-server run until killed
+
+options:
+  --bind ADDR       udp: local address, default 0.0.0.0:0
+                    tcp-server: local ip, default 0.0.0.0
+  --send HOST:PORT  udp: where MSG and stdin lines go to
+  --local ADDR      tcp-client: bind the client side first, eg 0.0.0.0:5000
+  --hex             MSG and stdin lines are hex, eg \"de ad be ef\"
+  --display MODE    raw (default) | utf8 | hex | hexdump | decimal | escaped
+                    anything but raw prints one line per message with the sender
+  --framing SPEC    tcp only, none | delim:ESCAPED | len:1|2|4[be|le][:MAX]
+                    | fixed:N | slip | cobs, eg 'delim:\\r\\n' or len:2le,
+                    MAX bytes per length prefixed frame, 1 MiB by default
+  --broadcast       udp: allow sending to broadcast addresses
+  --reconnect       tcp-client: reconnect with the default backoff
+  --nodelay         tcp only, TCP_NODELAY (no Nagle)
+  --keepalive SPEC  tcp only, SO_KEEPALIVE as IDLE[,INTERVAL,COUNT] in seconds, eg 30,5,3
+  --linger SECS     tcp only, SO_LINGER, 0 resets (RST) the connection on close
+  --sndbuf BYTES    tcp only, SO_SNDBUF
+  --rcvbuf BYTES    tcp only, SO_RCVBUF
+  --ttl N           tcp only, IP TTL / hop limit
+                    the values the os applied are logged on connect
+  --max-clients N   tcp-server: serve N clients at once, others are closed
+  --queue N         tcp-server: hold up to N of the others until a client leaves
+  --allow LIST      tcp-server: only these IPs / CIDRs, eg 192.168.1.0/24,10.0.0.5
+  --deny LIST       tcp-server: never these IPs / CIDRs
+  --tls             tcp only, tls over the connection, the server uses a
+                    self signed certificate unless --tls-cert and --tls-key
+  --tls-cert FILE   PEM certificate chain, server or client certificate
+  --tls-key FILE    PEM private key of --tls-cert
+  --tls-ca FILE     tcp-client: PEM CAs to trust instead of the public ones
+  --tls-name NAME   tcp-client: server name to verify, default the host
+  --tls-insecure    tcp-client: accept any server certificate
+                    every --tls-* option implies --tls
+  --listen          keep receiving after MSG or the end of stdin
+  --wait MS         keep receiving for MS milliseconds after the last message
+  --capture FILE    write everything sent and received to FILE as pcapng,
+                    TCP payloads are the messages without their framing
+  --replay FILE     send the udp (udp) or tcp (tcp-*) payloads of a pcap / pcapng
+                    file, with the original timing unless --speed or --interval
+  --speed X         replay X times as fast, eg 2 or 0.5
+  --interval MS     replay one message every MS milliseconds
+  --src-port PORT   replay only what was sent from PORT in the capture
+  --dst-port PORT   replay only what was sent to PORT in the capture
+  -q, --quiet       only log warnings and errors
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    Udp,
+    TcpServer,
+    TcpClient,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    pub send_to: Option<String>,
+    pub remote: Option<String>,
+    pub local: Option<SocketAddr>,
+    pub message: Option<String>,
+    pub hex: bool,
+
+    /// None prints the raw bytes
+    pub display: Option<DisplayMode>,
+    pub framing: Framing,
+    pub broadcast: bool,
+    pub reconnect: bool,
+    pub limits: Limits,
+    pub sockopt: SocketOptions,
+
+    /// the server takes only the certificate files from it
+    pub tls: Option<TlsClient>,
+    pub listen: bool,
+    pub wait: Duration,
+    pub capture: Option<String>,
+    pub replay: Option<String>,
+    pub timing: Timing,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub quiet: bool,
+}
+
+impl Options {
+    fn new(mode: Mode) -> Self {
+        Options {
+            mode,
+            bind: None,
+            port: None,
+            send_to: None,
+            remote: None,
+            local: None,
+            message: None,
+            hex: false,
+            display: None,
+            framing: Framing::None,
+            broadcast: false,
+            reconnect: false,
+            limits: Limits::default(),
+            sockopt: SocketOptions::default(),
+            tls: None,
+            listen: false,
+            wait: Duration::ZERO,
+            capture: None,
+            replay: None,
+            timing: Timing::Original,
+            src_port: None,
+            dst_port: None,
+            quiet: false,
+        }
+    }
+}
+
+/// `args` without the program name, Ok(None) means help was asked
+pub fn parse(args: &[String]) -> Result<Option<Options>, String> {
+    let Some(cmd) = args.first() else {
+        return Ok(None);
+    };
+    let mode = match cmd.as_str() {
+        "udp" => Mode::Udp,
+        "tcp-server" => Mode::TcpServer,
+        "tcp-client" => Mode::TcpClient,
+        "help" | "-h" | "--help" => return Ok(None),
+        _ => return Err(format!("unknown command {cmd:?}")),
+    };
+    let mut opts = Options::new(mode);
+    let mut positionals = vec![];
+
+    let mut it = args[1..].iter();
+    while let Some(arg) = it.next() {
+        let mut value = || {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("{arg} needs a value"))
+        };
+        match arg.as_str() {
+            "--bind" => opts.bind = Some(value()?),
+            "--port" => {
+                let v = value()?;
+                opts.port = Some(v.parse().map_err(|e| format!("invalid port {v:?}, {e}"))?);
+            }
+            "--send" => opts.send_to = Some(value()?),
+            "--local" => {
+                let v = value()?;
+                opts.local = Some(
+                    v.parse()
+                        .map_err(|e| format!("invalid local address {v:?}, {e}"))?,
+                );
+            }
+            "--hex" => opts.hex = true,
+            "--display" => opts.display = parse_display(&value()?)?,
+            "--framing" => opts.framing = parse_framing(&value()?)?,
+            "--broadcast" => opts.broadcast = true,
+            "--reconnect" => opts.reconnect = true,
+            "--max-clients" => {
+                opts.limits.max_clients = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid --max-clients, {e}"))?
+            }
+            "--queue" => {
+                opts.limits.queue = value()?
+                    .parse()
+                    .map_err(|e| format!("invalid --queue, {e}"))?
+            }
+            "--nodelay" => opts.sockopt.nodelay = true,
+            "--keepalive" => opts.sockopt.keepalive = Some(parse_keepalive(&value()?)?),
+            "--linger" => {
+                let secs = number(&value()?, "--linger")?;
+                opts.sockopt.linger = Some(Duration::from_secs(secs));
+            }
+            "--sndbuf" => opts.sockopt.send_buffer = Some(number(&value()?, "--sndbuf")?),
+            "--rcvbuf" => opts.sockopt.recv_buffer = Some(number(&value()?, "--rcvbuf")?),
+            "--ttl" => opts.sockopt.ttl = Some(number(&value()?, "--ttl")?),
+            "--allow" => opts.limits.allow.extend(Cidr::parse_list(&value()?)?),
+            "--deny" => opts.limits.deny.extend(Cidr::parse_list(&value()?)?),
+            "--tls" => _ = opts.tls.get_or_insert_default(),
+            "--tls-cert" => opts.tls.get_or_insert_default().cert_file = Some(value()?.into()),
+            "--tls-key" => opts.tls.get_or_insert_default().key_file = Some(value()?.into()),
+            "--tls-ca" => opts.tls.get_or_insert_default().ca_file = Some(value()?.into()),
+            "--tls-name" => opts.tls.get_or_insert_default().server_name = Some(value()?),
+            "--tls-insecure" => opts.tls.get_or_insert_default().accept_invalid = true,
+            "--listen" => opts.listen = true,
+            "--wait" => {
+                let v = value()?;
+                let ms = v.parse().map_err(|e| format!("invalid wait {v:?}, {e}"))?;
+                opts.wait = Duration::from_millis(ms);
+            }
+            "--capture" => opts.capture = Some(value()?),
+            "--replay" => opts.replay = Some(value()?),
+            "--speed" => {
+                let v = value()?;
+                opts.timing =
+                    Timing::Speed(v.parse().map_err(|e| format!("invalid speed {v:?}, {e}"))?);
+            }
+            "--interval" => {
+                let v = value()?;
+                let ms = v
+                    .parse()
+                    .map_err(|e| format!("invalid interval {v:?}, {e}"))?;
+                opts.timing = Timing::Interval(Duration::from_millis(ms));
+            }
+            "--src-port" => {
+                let v = value()?;
+                opts.src_port = Some(v.parse().map_err(|e| format!("invalid port {v:?}, {e}"))?);
+            }
+            "--dst-port" => {
+                let v = value()?;
+                opts.dst_port = Some(v.parse().map_err(|e| format!("invalid port {v:?}, {e}"))?);
+            }
+            "-q" | "--quiet" => opts.quiet = true,
+            "-h" | "--help" => return Ok(None),
+            s if s.starts_with('-') && s.len() > 1 => return Err(format!("unknown option {s}")),
+            _ => positionals.push(arg.clone()),
+        }
+    }
+
+    let mut positionals = positionals.into_iter();
+    match opts.mode {
+        Mode::Udp => {
+            opts.message = positionals.next();
+            if (opts.message.is_some() || opts.replay.is_some()) && opts.send_to.is_none() {
+                return Err("udp: MSG and --replay need --send HOST:PORT".to_string());
+            }
+        }
+        Mode::TcpServer => {
+            if opts.port.is_none() {
+                return Err("tcp-server needs --port".to_string());
+            }
+        }
+        Mode::TcpClient => {
+            opts.remote = Some(
+                positionals
+                    .next()
+                    .ok_or("tcp-client needs HOST:PORT".to_string())?,
+            );
+            opts.message = positionals.next();
+        }
+    }
+    if let Some(extra) = positionals.next() {
+        return Err(format!("unexpected argument {extra:?}"));
+    }
+    if opts.replay.is_some() && opts.message.is_some() {
+        return Err("either MSG or --replay, not both".to_string());
+    }
+    if opts.mode == Mode::Udp && opts.framing != Framing::None {
+        return Err("--framing is for tcp only, a datagram is already a message".to_string());
+    }
+    if opts.mode == Mode::Udp && opts.sockopt != SocketOptions::default() {
+        return Err(
+            "--nodelay, --keepalive, --linger, --sndbuf, --rcvbuf and --ttl are for tcp only"
+                .to_string(),
+        );
+    }
+    opts.sockopt.validate()?;
+    if opts.mode != Mode::TcpServer && opts.limits != Limits::default() {
+        return Err("--max-clients, --queue, --allow and --deny are for tcp-server".to_string());
+    }
+    if let Some(tls) = &opts.tls {
+        match opts.mode {
+            Mode::Udp => return Err("--tls is for tcp only".to_string()),
+            Mode::TcpServer => {
+                server_tls(tls)?;
+            }
+            Mode::TcpClient => {}
+        }
+        if tls.cert_file.is_some() != tls.key_file.is_some() {
+            return Err("--tls-cert and --tls-key go together".to_string());
+        }
+    }
+    Ok(Some(opts))
+}
+
+/// the server side of the --tls-* options
+fn server_tls(tls: &TlsClient) -> Result<TlsServer, String> {
+    if tls.ca_file.is_some() || tls.server_name.is_some() || tls.accept_invalid {
+        return Err("--tls-ca, --tls-name and --tls-insecure are for tcp-client".to_string());
+    }
+    Ok(match (&tls.cert_file, &tls.key_file) {
+        (Some(cert_file), Some(key_file)) => TlsServer::Pem {
+            cert_file: cert_file.clone(),
+            key_file: key_file.clone(),
+        },
+        _ => TlsServer::SelfSigned,
+    })
+}
+
+fn number<T: std::str::FromStr>(s: &str, what: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    s.trim()
+        .parse()
+        .map_err(|e| format!("invalid {what} {s:?}, {e}"))
+}
+
+/// IDLE[,INTERVAL,COUNT] in seconds, the rest from Keepalive::default()
+fn parse_keepalive(s: &str) -> Result<Keepalive, String> {
+    let mut ka = Keepalive::default();
+    let parts: Vec<&str> = s.split(',').collect();
+    match parts[..] {
+        [idle] => ka.idle = Duration::from_secs(number(idle, "keepalive idle")?),
+        [idle, interval, count] => {
+            ka.idle = Duration::from_secs(number(idle, "keepalive idle")?);
+            ka.interval = Duration::from_secs(number(interval, "keepalive interval")?);
+            ka.count = number(count, "keepalive count")?;
+        }
+        _ => return Err(format!("invalid --keepalive {s:?}, IDLE[,INTERVAL,COUNT]")),
+    }
+    Ok(ka)
+}
+
+fn parse_display(s: &str) -> Result<Option<DisplayMode>, String> {
+    let mode = match s {
+        "raw" => return Ok(None),
+        "utf8" => DisplayMode::Utf8,
+        "hex" => DisplayMode::Hex,
+        "hexdump" => DisplayMode::HexDump,
+        "decimal" => DisplayMode::Decimal,
+        "escaped" => DisplayMode::Escaped,
+        _ => return Err(format!("unknown display mode {s:?}")),
+    };
+    Ok(Some(mode))
+}
+
+/// see USAGE for the syntax
+fn parse_framing(s: &str) -> Result<Framing, String> {
+    let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+    let framing = match (kind, arg) {
+        ("none", "") => Framing::None,
+        ("slip", "") => Framing::Slip,
+        ("cobs", "") => Framing::Cobs,
+        ("delim", arg) => {
+            Framing::Delimiter(framing::parse_escaped(arg).map_err(|e| e.to_string())?)
+        }
+        ("len", arg) => {
+            let (arg, max) = match arg.split_once(':') {
+                Some((arg, max)) => (
+                    arg,
+                    max.parse()
+                        .map_err(|e| format!("invalid maximum frame {max:?}, {e}"))?,
+                ),
+                None => (arg, framing::DEFAULT_MAX_FRAME),
+            };
+            let (width, endian) = if let Some(w) = arg.strip_suffix("le") {
+                (w, Endian::Little)
+            } else {
+                (arg.strip_suffix("be").unwrap_or(arg), Endian::Big)
+            };
+            let width = width
+                .parse()
+                .map_err(|e| format!("invalid length width {width:?}, {e}"))?;
+            Framing::LengthPrefix { width, endian, max }
+        }
+        ("fixed", arg) => Framing::FixedSize(
+            arg.parse()
+                .map_err(|e| format!("invalid fixed size {arg:?}, {e}"))?,
+        ),
+        _ => return Err(format!("unknown framing {s:?}")),
+    };
+    framing.validate().map_err(|e| e.to_string())?;
+    Ok(framing)
+}
+
+/// the messages of `path` for the protocol of the session
+fn open_replay(opts: &Options, path: &str) -> Result<Replay, String> {
+    let filter = Filter {
+        protocol: Some(match opts.mode {
+            Mode::Udp => Protocol::Udp,
+            Mode::TcpServer | Mode::TcpClient => Protocol::Tcp,
+        }),
+        src_port: opts.src_port,
+        dst_port: opts.dst_port,
+    };
+    let messages: Vec<_> = replay::load(path)
+        .map_err(|e| format!("cannot load {path:?}, {e}"))?
+        .into_iter()
+        .filter(|m| filter.matches(m))
+        .collect();
+    log::info!("replaying {} messages from {path}", messages.len());
+    Replay::new(messages, opts.timing, Instant::now())
+}
+
+/// same rules as the hex message box, whitespace is ignored
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    let digits: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    hex::decode(&digits).map_err(|e| format!("invalid hex string {s:?}, {e}"))
+}
+
+fn to_bytes(s: &str, hex: bool) -> Result<Vec<u8>, String> {
+    if hex {
+        decode_hex(s)
+    } else {
+        Ok(s.as_bytes().to_vec())
+    }
+}
+
+/// reads stdin on its own thread, the channel disconnects at EOF
+fn spawn_stdin(hex: bool) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("cannot read stdin, {e}");
+                    break;
+                }
+            };
+            match to_bytes(&line, hex) {
+                Ok(data) if data.is_empty() => {}
+                Ok(data) => {
+                    if tx.send(data).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => log::error!("{e}"),
+            }
+        }
+    });
+    rx
+}
+
+/// the three engines behind one poll / send interface
+enum Session {
+    Udp { udp: Udp, to: Option<String> },
+    TcpServer(TcpServer),
+    TcpClient(TcpClient),
+}
+
+impl Session {
+    fn open(opts: &Options) -> Result<Session, String> {
+        match opts.mode {
+            Mode::Udp => {
+                let mut udp = Udp::default();
+                udp.toggle_broadcast(opts.broadcast)
+                    .map_err(|e| e.to_string())?;
+                let bind = opts.bind.clone().unwrap_or("0.0.0.0:0".to_string());
+                udp.connect_and_start(bind.clone())
+                    .map_err(|e| format!("cannot bind {bind}, {e}"))?;
+                Ok(Session::Udp {
+                    udp,
+                    to: opts.send_to.clone(),
+                })
+            }
+            Mode::TcpServer => {
+                let mut server = TcpServer::default();
+                server
+                    .set_framing(opts.framing.clone())
+                    .map_err(|e| e.to_string())?;
+                server.set_limits(opts.limits.clone());
+                server
+                    .set_socket_options(opts.sockopt.clone())
+                    .map_err(|e| e.to_string())?;
+                let tls = opts.tls.as_ref().map(server_tls).transpose()?;
+                server.set_tls(tls.as_ref()).map_err(|e| e.to_string())?;
+                let ip = opts.bind.as_deref().unwrap_or("0.0.0.0");
+                let port = opts.port.unwrap_or_default().to_string();
+                // begin() logs the reason itself
+                server
+                    .begin(network::host_port(ip, &port))
+                    .ok_or("cannot start the server".to_string())?;
+                Ok(Session::TcpServer(server))
+            }
+            Mode::TcpClient => {
+                let mut client = TcpClient::default();
+                client
+                    .set_framing(opts.framing.clone())
+                    .map_err(|e| e.to_string())?;
+                client.set_reconnect(opts.reconnect.then(ReconnectPolicy::default));
+                client
+                    .set_socket_options(opts.sockopt.clone())
+                    .map_err(|e| e.to_string())?;
+                client
+                    .set_tls(opts.tls.as_ref())
+                    .map_err(|e| e.to_string())?;
+                let remote = opts.remote.as_deref().unwrap_or_default();
+                client
+                    .begin(remote, opts.local)
+                    .ok_or("cannot connect".to_string())?;
+                Ok(Session::TcpClient(client))
+            }
+        }
+    }
+
+    fn can_send(&self) -> bool {
+        !matches!(self, Session::Udp { to: None, .. })
+    }
+
+    /// failures are logged by the engines, nothing else to do here
+    fn send(&self, data: &[u8]) {
+        match self {
+            Session::Udp { udp, to } => {
+                if let Some(to) = to {
+                    let _ = udp.send_data_to(data, to);
+                }
+            }
+            Session::TcpServer(server) => {
+                let clients = server.clients();
+                if clients.is_empty() {
+                    log::warn!("no clients connected, message dropped");
+                }
+                for stream in &clients {
+                    let _ = server.send_data(data, stream);
+                }
+            }
+            Session::TcpClient(client) => {
+                let _ = client.send_data(data);
+            }
+        }
+    }
+
+    /// received messages as (sender, data), everything including
+    /// the sends goes to `capture`, which is dropped if it fails
+    fn poll(&mut self, capture: &mut Option<Capture>) -> Vec<(SocketAddr, Vec<u8>)> {
+        let mut write_capture = |write: &dyn Fn(&mut Capture) -> io::Result<()>| {
+            if let Some(c) = capture.as_mut()
+                && let Err(e) = write(c)
+            {
+                log::error!("capture failed, {e}");
+                *capture = None;
+            }
+        };
+        let tcp = |ev: TcpEvent| {
+            write_capture(&|c| c.write_tcp(&ev));
+            match ev {
+                TcpEvent::Packet { peer, data, .. } => Some((peer, data)),
+                TcpEvent::Sent { .. } => None,
+            }
+        };
+        match self {
+            Session::Udp { udp, .. } => {
+                let local = udp.local_addr();
+                udp.poll_events()
+                    .into_iter()
+                    .filter_map(|ev| {
+                        if let Some(local) = local {
+                            write_capture(&|c| c.write_udp(local, &ev));
+                        }
+                        match ev {
+                            UdpEvent::Packet {
+                                src,
+                                data,
+                                truncated,
+                                ..
+                            } => {
+                                if let Some(n) = truncated {
+                                    log::warn!(
+                                        "[TRUNCATED {} of {n} bytes] from {src}",
+                                        data.len()
+                                    );
+                                }
+                                Some((src, data))
+                            }
+                            UdpEvent::Error(e) => {
+                                log::error!("UDP receive error, {e}");
+                                None
+                            }
+                            UdpEvent::Sent { .. } | UdpEvent::Stopped => None,
+                        }
+                    })
+                    .collect()
+            }
+            Session::TcpServer(server) => {
+                server.poll_events().into_iter().filter_map(tcp).collect()
+            }
+            Session::TcpClient(client) => {
+                client.poll_events().into_iter().filter_map(tcp).collect()
+            }
+        }
+    }
+
+    /// false once a tcp client has nothing left to talk to
+    fn is_alive(&self) -> bool {
+        match self {
+            Session::TcpClient(client) => !matches!(
+                client.state(),
+                ClientState::Disconnected | ClientState::GivenUp
+            ),
+            _ => true,
+        }
+    }
+}
+
+fn print(out: &mut impl Write, opts: &Options, from: SocketAddr, data: &[u8]) -> io::Result<()> {
+    match opts.display {
+        Some(mode) => writeln!(out, "{from} {}", mode.render(data))?,
+        None => {
+            out.write_all(data)?;
+            // a framed message has lost its delimiter, keep them apart
+            if opts.framing != Framing::None {
+                out.write_all(b"\n")?;
+            }
+        }
+    }
+    out.flush()
+}
+
+/// runs until the input is done (see --listen / --wait),
+/// the tcp client loses the server, or the process is killed
+pub fn run(opts: Options) -> i32 {
+    let logrx = Xlogger::init();
+    if opts.quiet {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    let flush_logs = || {
+        for line in logrx.try_iter() {
+            eprintln!("{line}");
+        }
+    };
+
+    let message = match opts.message.as_deref().map(|m| to_bytes(m, opts.hex)) {
+        Some(Ok(data)) => Some(data),
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            return 2;
+        }
+        None => None,
+    };
+
+    let mut session = match Session::open(&opts) {
+        Ok(session) => session,
+        Err(e) => {
+            log::error!("{e}");
+            flush_logs();
+            return 1;
+        }
+    };
+
+    let mut capture = None;
+    if let Some(path) = &opts.capture {
+        match Capture::create(path) {
+            Ok(c) => {
+                log::info!("capturing to {path}");
+                capture = Some(c);
+            }
+            Err(e) => {
+                log::error!("cannot create capture file {path:?}, {e}");
+                flush_logs();
+                return 1;
+            }
+        }
+    }
+
+    // None once there is nothing more to send
+    let mut input = None;
+    let mut replay = None;
+    if let Some(path) = &opts.replay {
+        match open_replay(&opts, path) {
+            Ok(r) => replay = Some(r),
+            Err(e) => {
+                log::error!("{e}");
+                flush_logs();
+                return 1;
+            }
+        }
+    } else if let Some(data) = &message {
+        session.send(data);
+    } else if session.can_send() {
+        input = Some(spawn_stdin(opts.hex));
+    }
+    let mut done_at = (input.is_none() && replay.is_none()).then(Instant::now);
+
+    // receive only, eg `udptcp udp --bind 0.0.0.0:9000`
+    let forever = opts.listen || (message.is_none() && !session.can_send());
+    if matches!(opts.mode, Mode::TcpServer) && message.is_none() {
+        // a server has no reason to quit when stdin ends
+        done_at = None;
+    }
+
+    let mut stdout = io::stdout().lock();
+    let mut code = 0;
+    loop {
+        for (from, data) in session.poll(&mut capture) {
+            if let Err(e) = print(&mut stdout, &opts, from, &data) {
+                // eg the other end of a pipe is gone
+                log::error!("cannot write to stdout, {e}");
+                code = 1;
+                break;
+            }
+        }
+        if code != 0 {
+            break;
+        }
+        // flushed every round so that a killed capture is complete
+        if let Some(c) = capture.as_mut()
+            && let Err(e) = c.flush()
+        {
+            log::error!("capture failed, {e}");
+            capture = None;
+        }
+
+        if let Some(rx) = &input {
+            loop {
+                match rx.try_recv() {
+                    Ok(data) => {
+                        session.send(&data);
+                        if done_at.is_some() {
+                            done_at = Some(Instant::now());
+                        }
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        input = None;
+                        if !matches!(opts.mode, Mode::TcpServer) {
+                            done_at = Some(Instant::now());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(r) = replay.as_mut() {
+            while let Some(msg) = r.poll(Instant::now()) {
+                session.send(&msg.data);
+            }
+            if r.is_done() {
+                log::info!("replay done");
+                replay = None;
+                if !matches!(opts.mode, Mode::TcpServer) {
+                    done_at = Some(Instant::now());
+                }
+            }
+        }
+        flush_logs();
+
+        if !session.is_alive() {
+            if let Session::TcpClient(client) = &session
+                && client.state() == ClientState::GivenUp
+            {
+                code = 1;
+            }
+            break;
+        }
+        if !forever
+            && input.is_none()
+            && replay.is_none()
+            && let Some(t) = done_at
+            && t.elapsed() >= opts.wait
+        {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    drop(session);
+    if let Some(c) = capture {
+        let packets = c.packets();
+        match c.finish() {
+            Ok(_) => log::info!("capture done, {packets} packets"),
+            Err(e) => log::error!("capture failed, {e}"),
+        }
+    }
+    flush_logs();
+    code
+}
+
+/// entry point from main(), returns the process exit code
+pub fn main(args: &[String]) -> i32 {
+    match parse(args) {
+        Ok(Some(opts)) => run(opts),
+        Ok(None) => {
+            print!("{USAGE}");
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_udp() {
+        let opts = parse(&args(
+            "udp --bind 0.0.0.0:9000 --send 10.0.0.2:9000 --hex 0102",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opts.mode, Mode::Udp);
+        assert_eq!(opts.bind.as_deref(), Some("0.0.0.0:9000"));
+        assert_eq!(opts.send_to.as_deref(), Some("10.0.0.2:9000"));
+        assert_eq!(opts.message.as_deref(), Some("0102"));
+        assert!(opts.hex);
+        assert_eq!(opts.capture, None);
+
+        let opts = parse(&args("udp --capture out.pcapng")).unwrap().unwrap();
+        assert_eq!(opts.capture.as_deref(), Some("out.pcapng"));
+
+        assert!(parse(&args("udp hello")).is_err()); // no --send
+        assert!(parse(&args("udp --framing slip")).is_err());
+        assert!(parse(&args("udp --send")).is_err());
+    }
+
+    #[test]
+    fn test_parse_tcp() {
+        let opts = parse(&args(
+            "tcp-server --port 7000 --framing len:2le --display hex",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opts.port, Some(7000));
+        assert_eq!(
+            opts.framing,
+            Framing::LengthPrefix {
+                width: 2,
+                endian: Endian::Little,
+                max: framing::DEFAULT_MAX_FRAME,
+            }
+        );
+        assert_eq!(opts.display, Some(DisplayMode::Hex));
+        assert!(parse(&args("tcp-server")).is_err());
+
+        let opts = parse(&args(
+            "tcp-client [::1]:7000 --local 0.0.0.0:5000 --reconnect hi",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opts.remote.as_deref(), Some("[::1]:7000"));
+        assert_eq!(opts.local, Some("0.0.0.0:5000".parse().unwrap()));
+        assert_eq!(opts.message.as_deref(), Some("hi"));
+        assert!(opts.reconnect);
+        assert!(parse(&args("tcp-client")).is_err());
+
+        let opts = parse(&args(
+            "tcp-client h:1 --replay s.pcap --speed 2 --dst-port 502",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opts.replay.as_deref(), Some("s.pcap"));
+        assert_eq!(opts.timing, Timing::Speed(2.0));
+        assert_eq!(opts.dst_port, Some(502));
+        assert!(parse(&args("tcp-client h:1 --replay s.pcap hi")).is_err());
+        assert!(parse(&args("udp --replay s.pcap")).is_err()); // no --send
+        assert!(parse(&args("tcp-client a:1 b c")).is_err());
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let opts = parse(&args(
+            "tcp-server --port 7000 --max-clients 1 --queue 5 --allow 10.0.0.0/8,10.1.0.1 --deny 10.0.0.13",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(opts.limits.max_clients, 1);
+        assert_eq!(opts.limits.queue, 5);
+        assert_eq!(opts.limits.allow.len(), 2);
+        assert_eq!(opts.limits.deny, vec!["10.0.0.13".parse().unwrap()]);
+
+        assert!(parse(&args("tcp-server --port 1 --max-clients x")).is_err());
+        assert!(parse(&args("tcp-server --port 1 --allow 10.0.0.0/40")).is_err());
+        assert!(parse(&args("tcp-client h:1 --max-clients 1")).is_err());
+    }
+
+    #[test]
+    fn test_parse_sockopt() {
+        let opts = parse(&args(
+            "tcp-client h:1 --nodelay --keepalive 30,5,3 --linger 0 --sndbuf 8192 --ttl 9",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            opts.sockopt,
+            SocketOptions {
+                nodelay: true,
+                keepalive: Some(Keepalive {
+                    idle: Duration::from_secs(30),
+                    interval: Duration::from_secs(5),
+                    count: 3
+                }),
+                linger: Some(Duration::ZERO),
+                send_buffer: Some(8192),
+                recv_buffer: None,
+                ttl: Some(9),
+            }
+        );
+        assert_eq!(parse_keepalive("20").unwrap().idle, Duration::from_secs(20));
+        assert!(parse_keepalive("20,5").is_err());
+        assert!(parse(&args("tcp-server --port 1 --ttl 0")).is_err());
+        assert!(parse(&args("udp --nodelay")).is_err());
+    }
+
+    #[test]
+    fn test_parse_tls() {
+        let opts = parse(&args("tcp-server --port 7000 --tls"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            server_tls(opts.tls.as_ref().unwrap()),
+            Ok(TlsServer::SelfSigned)
+        );
+
+        let opts = parse(&args(
+            "tcp-server --port 7000 --tls-cert c.pem --tls-key k.pem",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            server_tls(opts.tls.as_ref().unwrap()),
+            Ok(TlsServer::Pem {
+                cert_file: "c.pem".into(),
+                key_file: "k.pem".into()
+            })
+        );
+
+        let opts = parse(&args(
+            "tcp-client h:1 --tls-ca ca.pem --tls-name example.com --tls-insecure",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            opts.tls,
+            Some(TlsClient {
+                server_name: Some("example.com".to_string()),
+                ca_file: Some("ca.pem".into()),
+                accept_invalid: true,
+                ..Default::default()
+            })
+        );
+
+        assert!(parse(&args("udp --tls")).is_err());
+        assert!(parse(&args("tcp-server --port 1 --tls-insecure")).is_err());
+        assert!(parse(&args("tcp-client h:1 --tls-cert c.pem")).is_err());
+    }
+
+    #[test]
+    fn test_parse_help_and_unknown() {
+        assert_eq!(parse(&args("help")), Ok(None));
+        assert_eq!(parse(&args("udp --help")), Ok(None));
+        assert!(parse(&args("ftp")).is_err());
+        assert!(parse(&args("udp --nope")).is_err());
+    }
+
+    #[test]
+    fn test_parse_framing() {
+        assert_eq!(parse_framing("none"), Ok(Framing::None));
+        assert_eq!(parse_framing("cobs"), Ok(Framing::Cobs));
+        assert_eq!(
+            parse_framing("delim:\\r\\n"),
+            Ok(Framing::Delimiter(b"\r\n".to_vec()))
+        );
+        assert_eq!(
+            parse_framing("len:4"),
+            Ok(Framing::LengthPrefix {
+                width: 4,
+                endian: Endian::Big,
+                max: framing::DEFAULT_MAX_FRAME,
+            })
+        );
+        assert_eq!(
+            parse_framing("len:2le:512"),
+            Ok(Framing::LengthPrefix {
+                width: 2,
+                endian: Endian::Little,
+                max: 512,
+            })
+        );
+        assert!(parse_framing("len:4:0").is_err());
+        assert!(parse_framing("len:4:x").is_err());
+        assert_eq!(parse_framing("fixed:8"), Ok(Framing::FixedSize(8)));
+        assert!(parse_framing("len:3").is_err());
+        assert!(parse_framing("delim:").is_err());
+        assert!(parse_framing("fixed:0").is_err());
+        assert!(parse_framing("slip:x").is_err());
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("de ad\tbe ef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert!(decode_hex("abc").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! per peer transcripts of the tcp server, see [`Conversations`]
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use chrono::{DateTime, Local};
+
+use crate::tcp::TcpEvent;
+
+/*
+    fed like Stats with the server events, plus sync() with the
+    connected peers after every poll, that is how a conversation
+    learns it was closed, TcpServer has no event for that
+
+    a closed conversation stays until dismissed, a peer address
+    coming back (same port reused) starts a new one next to it
+*/
+
+/// lines kept per conversation, the oldest go first
+pub const MAX_LINES: usize = 10_000;
+
+/// one message of a conversation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    /// when it was received or written
+    pub timestamp: DateTime<Local>,
+    /// true for what the server sent
+    pub sent: bool,
+    /// payload, without framing
+    pub data: Vec<u8>,
+}
+
+/// everything exchanged with one connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversation {
+    /// unique within its Conversations, the peer address is not
+    pub id: u64,
+    /// the client end of the connection
+    pub peer: SocketAddr,
+    /// oldest first, up to MAX_LINES
+    pub lines: VecDeque<Line>,
+    /// when the connection was first seen
+    pub opened: DateTime<Local>,
+    /// None while connected
+    pub closed: Option<DateTime<Local>>,
+}
+
+impl Conversation {
+    fn new(id: u64, peer: SocketAddr, at: DateTime<Local>) -> Self {
+        Conversation {
+            id,
+            peer,
+            lines: VecDeque::new(),
+            opened: at,
+            closed: None,
+        }
+    }
+
+    fn push(&mut self, line: Line) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+/// the conversations of a tcp server, in the order they started
+#[derive(Debug, Default, Clone)]
+pub struct Conversations {
+    list: Vec<Conversation>,
+    next_id: u64,
+}
+
+impl Conversations {
+    /// record an event from TcpServer::poll_events()
+    pub fn on_tcp_server(&mut self, ev: &TcpEvent) {
+        let (peer, sent, data, timestamp) = match ev {
+            TcpEvent::Packet {
+                peer,
+                data,
+                timestamp,
+                ..
+            } => (peer, false, data, timestamp),
+            TcpEvent::Sent {
+                peer,
+                data,
+                timestamp,
+                ..
+            } => (peer, true, data, timestamp),
+        };
+        self.open(*peer, *timestamp).push(Line {
+            timestamp: *timestamp,
+            sent,
+            data: data.clone(),
+        });
+    }
+
+    /// the connected one for `peer`, started if there is none
+    fn open(&mut self, peer: SocketAddr, at: DateTime<Local>) -> &mut Conversation {
+        match self
+            .list
+            .iter()
+            .position(|c| c.peer == peer && c.closed.is_none())
+        {
+            Some(i) => &mut self.list[i],
+            None => {
+                self.next_id += 1;
+                self.list.push(Conversation::new(self.next_id, peer, at));
+                self.list.last_mut().expect("just pushed")
+            }
+        }
+    }
+
+    /// starts conversations for new `peers`, closes the ones not in it
+    pub fn sync(&mut self, peers: &[SocketAddr], now: DateTime<Local>) {
+        for c in self.list.iter_mut().filter(|c| c.closed.is_none()) {
+            if !peers.contains(&c.peer) {
+                c.closed = Some(now);
+            }
+        }
+        for peer in peers {
+            self.open(*peer, now);
+        }
+    }
+
+    /// all of them, in the order they started
+    pub fn iter(&self) -> impl Iterator<Item = &Conversation> {
+        self.list.iter()
+    }
+
+    /// None once dismissed
+    pub fn get(&self, id: u64) -> Option<&Conversation> {
+        self.list.iter().find(|c| c.id == id)
+    }
+
+    /// the latest conversation with `peer`, connected or not
+    pub fn latest(&self, peer: SocketAddr) -> Option<&Conversation> {
+        self.list.iter().rev().find(|c| c.peer == peer)
+    }
+
+    /// forgets a closed conversation, a connected one is kept
+    pub fn dismiss(&mut self, id: u64) {
+        self.list.retain(|c| c.id != id || c.closed.is_none());
+    }
+
+    /// forgets every closed conversation
+    pub fn dismiss_closed(&mut self) {
+        self.list.retain(|c| c.closed.is_none());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tcp::fixtures::{addr, event};
+
+    #[test]
+    fn test_transcripts_per_peer() {
+        let mut convs = Conversations::default();
+        convs.sync(&[addr(1), addr(2)], Local::now());
+        convs.on_tcp_server(&event(false, 1, b"hi"));
+        convs.on_tcp_server(&event(true, 1, b"hello"));
+        convs.on_tcp_server(&event(false, 2, b"other"));
+
+        let one = convs.latest(addr(1)).unwrap();
+        let lines: Vec<_> = one.lines.iter().map(|l| (l.sent, &l.data[..])).collect();
+        assert_eq!(lines, vec![(false, &b"hi"[..]), (true, &b"hello"[..])]);
+        assert_eq!(convs.latest(addr(2)).unwrap().lines.len(), 1);
+        assert_eq!(convs.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_closed_kept_until_dismissed() {
+        let mut convs = Conversations::default();
+        convs.on_tcp_server(&event(false, 1, b"hi"));
+        convs.sync(&[], Local::now());
+        assert!(convs.latest(addr(1)).unwrap().closed.is_some());
+
+        // same address again, a new conversation next to the closed one
+        convs.sync(&[addr(1)], Local::now());
+        assert_eq!(convs.iter().count(), 2);
+        assert!(convs.latest(addr(1)).unwrap().closed.is_none());
+        assert!(convs.latest(addr(1)).unwrap().lines.is_empty());
+
+        let first = convs.iter().next().unwrap().id;
+        let second = convs.latest(addr(1)).unwrap().id;
+        assert_ne!(first, second);
+        convs.dismiss(second); // still connected
+        convs.dismiss(first);
+        assert_eq!(convs.iter().count(), 1);
+        assert!(convs.get(first).is_none());
+        convs.sync(&[], Local::now());
+        convs.dismiss_closed();
+        assert_eq!(convs.iter().count(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! rendering received bytes as text
+
+use std::fmt;
+use std::fmt::Write;
+
+/// how received payloads are rendered into log lines
+///
+/// the workers only hand over raw bytes, picking a view
+/// is done per connection by the GUI
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// lossy utf-8, invalid sequences become U+FFFD
+    #[default]
+    Utf8,
+
+    /// `48 65 6c 6c 6f`
+    Hex,
+
+    /// offset + hex + ascii columns, like `xxd`
+    HexDump,
+
+    /// `72 101 108 108 111`
+    Decimal,
+
+    /// printable ascii as is, everything else as `\xNN`
+    Escaped,
+}
+
+impl fmt::Display for DisplayMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DisplayMode::Utf8 => "UTF-8",
+            DisplayMode::Hex => "Hex",
+            DisplayMode::HexDump => "Hex + ASCII",
+            DisplayMode::Decimal => "Decimal",
+            DisplayMode::Escaped => "Escaped",
+        };
+        f.write_str(s)
+    }
+}
+
+impl DisplayMode {
+    /// for combo boxes
+    pub const ALL: [DisplayMode; 5] = [
+        DisplayMode::Utf8,
+        DisplayMode::Hex,
+        DisplayMode::HexDump,
+        DisplayMode::Decimal,
+        DisplayMode::Escaped,
+    ];
+
+    /// one log line worth of text, see the variants for the formats
+    pub fn render(&self, data: &[u8]) -> String {
+        match self {
+            DisplayMode::Utf8 => format!("{:?}", String::from_utf8_lossy(data)),
+            DisplayMode::Hex => Self::hex(data),
+            DisplayMode::HexDump => Self::hexdump(data),
+            DisplayMode::Decimal => data
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            DisplayMode::Escaped => Self::escaped(data),
+        }
+    }
+
+    fn hex(data: &[u8]) -> String {
+        data.iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// the dump starts on a new line so that the columns
+    /// line up regardless of the log prefix
+    fn hexdump(data: &[u8]) -> String {
+        let mut out = String::new();
+        for (i, chunk) in data.chunks(16).enumerate() {
+            let _ = write!(out, "\n{:08x}: ", i * 16);
+            for pos in 0..16 {
+                match chunk.get(pos) {
+                    Some(b) => {
+                        let _ = write!(out, "{b:02x}");
+                    }
+                    None => out.push_str("  "),
+                }
+                // xxd groups 2 bytes together
+                if pos % 2 == 1 {
+                    out.push(' ');
+                }
+            }
+            out.push(' ');
+            out.extend(chunk.iter().map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            }));
+        }
+        out
+    }
+
+    fn escaped(data: &[u8]) -> String {
+        let mut out = String::with_capacity(data.len());
+        for &b in data {
+            match b {
+                b'\\' => out.push_str("\\\\"),
+                b' ' => out.push(' '),
+                _ if b.is_ascii_graphic() => out.push(b as char),
+                _ => {
+                    let _ = write!(out, "\\x{b:02x}");
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &[u8] = b"Hi\x00\xff\\";
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(DisplayMode::Utf8.render(b"Hi\n"), "\"Hi\\n\"");
+        assert_eq!(DisplayMode::Utf8.render(b"\xff"), "\"\u{fffd}\"");
+    }
+
+    #[test]
+    fn test_hex_and_decimal() {
+        assert_eq!(DisplayMode::Hex.render(DATA), "48 69 00 ff 5c");
+        assert_eq!(DisplayMode::Decimal.render(DATA), "72 105 0 255 92");
+        assert_eq!(DisplayMode::Hex.render(b""), "");
+    }
+
+    #[test]
+    fn test_escaped() {
+        assert_eq!(DisplayMode::Escaped.render(DATA), "Hi\\x00\\xff\\\\");
+        assert_eq!(DisplayMode::Escaped.render(b"a b\r\n"), "a b\\x0d\\x0a");
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = DisplayMode::HexDump.render(b"0123456789abcdefXY\n");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 3); // leading empty line + 2 rows
+        assert_eq!(lines[0], "");
+        assert_eq!(
+            lines[1],
+            "00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef"
+        );
+        assert_eq!(
+            lines[2],
+            "00000010: 5859 0a                                  XY."
+        );
+    }
+}
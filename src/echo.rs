@@ -0,0 +1,184 @@
+//! reflecting received data back to the sender
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/*
+    echo runs inside the receiving threads so that a reply goes out
+    without waiting for the owner to poll, which would add up to a
+    GUI frame of latency
+
+    delayed replies of a socket or connection go through its Replies,
+    sleeping in the receiving thread would hold up everything received
+    after it, one thread per reply would let them overtake each other
+    and interleave on a tcp stream, so a single worker sends them in
+    the order they were received, started with the first delayed one
+*/
+
+/// delayed replies waiting per socket or connection, more are dropped
+pub const MAX_PENDING: usize = 10_000;
+
+/// what happens to the data before it goes back
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// sent back as received
+    #[default]
+    None,
+
+    /// ascii letters only, other bytes are kept
+    Uppercase,
+
+    /// byte order reversed
+    Reverse,
+
+    /// these bytes go in front
+    Prefix(Vec<u8>),
+
+    /// these bytes go at the end
+    Suffix(Vec<u8>),
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::None => write!(f, "None"),
+            Transform::Uppercase => write!(f, "Uppercase"),
+            Transform::Reverse => write!(f, "Reverse"),
+            Transform::Prefix(p) => write!(f, "Prefix {:?}", String::from_utf8_lossy(p)),
+            Transform::Suffix(s) => write!(f, "Suffix {:?}", String::from_utf8_lossy(s)),
+        }
+    }
+}
+
+/// echo settings, see Udp::set_echo() and TcpServer::set_echo()
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Echo {
+    /// applied to every reply
+    pub transform: Transform,
+
+    /// wait before replying, zero replies right away
+    pub delay: Duration,
+}
+
+impl Echo {
+    /// the bytes to send back for `data`
+    pub fn reply(&self, data: &[u8]) -> Vec<u8> {
+        match &self.transform {
+            Transform::None => data.to_vec(),
+            Transform::Uppercase => data.to_ascii_uppercase(),
+            Transform::Reverse => data.iter().rev().copied().collect(),
+            Transform::Prefix(p) => [p.as_slice(), data].concat(),
+            Transform::Suffix(s) => [data, s.as_slice()].concat(),
+        }
+    }
+}
+
+type Reply = Box<dyn FnOnce() + Send>;
+
+/// the delayed replies of one socket or connection, see Echo
+#[derive(Default)]
+pub struct Replies {
+    tx: Option<mpsc::Sender<(Instant, Reply)>>,
+    last_due: Option<Instant>,
+}
+
+impl Replies {
+    /// runs `send` after `delay`, never before a reply scheduled earlier,
+    /// right away on the calling thread while nothing was delayed yet
+    pub fn schedule(&mut self, delay: Duration, send: impl FnOnce() + Send + 'static) {
+        if delay.is_zero() && self.tx.is_none() {
+            send();
+            return;
+        }
+        let Some(due) = Instant::now().checked_add(delay) else {
+            log::error!("echo delay {delay:?} too long, reply dropped");
+            return;
+        };
+        let due = self.last_due.map_or(due, |last| due.max(last));
+        self.last_due = Some(due);
+        let tx = self.tx.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || delay_line(rx));
+            tx
+        });
+        let _ = tx.send((due, Box::new(send)));
+    }
+}
+
+/// sends the replies when due, ends when its Replies is dropped,
+/// dropping the ones still waiting
+fn delay_line(rx: mpsc::Receiver<(Instant, Reply)>) {
+    // the sequence number keeps replies due at the same time in order
+    let mut queue: BTreeMap<(Instant, u64), Reply> = BTreeMap::new();
+    let mut seq = 0u64;
+    loop {
+        let now = Instant::now();
+        while let Some(entry) = queue.first_entry()
+            && entry.key().0 <= now
+        {
+            (entry.remove())();
+        }
+        let next = match queue.first_key_value() {
+            Some((k, _)) => rx.recv_timeout(k.0.saturating_duration_since(now)),
+            None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok((due, send)) => {
+                if queue.len() >= MAX_PENDING {
+                    log::warn!("more than {MAX_PENDING} echo replies waiting, reply dropped");
+                    continue;
+                }
+                seq += 1;
+                queue.insert((due, seq), send);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(transform: Transform) -> Echo {
+        Echo {
+            transform,
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_transforms() {
+        assert_eq!(echo(Transform::None).reply(b"abc"), b"abc");
+        assert_eq!(echo(Transform::Uppercase).reply(b"ab\xffc1"), b"AB\xffC1");
+        assert_eq!(echo(Transform::Reverse).reply(b"abc"), b"cba");
+        assert_eq!(
+            echo(Transform::Prefix(b">".to_vec())).reply(b"abc"),
+            b">abc"
+        );
+        assert_eq!(
+            echo(Transform::Suffix(b"\r\n".to_vec())).reply(b"abc"),
+            b"abc\r\n"
+        );
+        assert_eq!(echo(Transform::Reverse).reply(b""), b"");
+    }
+
+    #[test]
+    fn test_replies_in_order() {
+        let (tx, rx) = mpsc::channel();
+        let mut replies = Replies::default();
+        // a shorter delay later must not overtake the first reply
+        for (i, ms) in [(1, 60), (2, 1), (3, 0)] {
+            let tx = tx.clone();
+            replies.schedule(Duration::from_millis(ms), move || tx.send(i).unwrap());
+        }
+        let got: Vec<i32> = (0..3)
+            .map(|_| rx.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        assert_eq!(got, vec![1, 2, 3]);
+    }
+}
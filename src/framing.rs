@@ -0,0 +1,698 @@
+//! cutting a tcp byte stream into messages and back
+
+use std::fmt;
+use std::io;
+
+/*
+    message framing on top of a tcp stream
+
+    tcp only guarantees the order of the bytes, one write() on
+    the sender side can show up as several read() on our side
+    or several writes can be merged into one read, so to get the
+    application messages back we need to agree on a framing
+
+        Delimiter       message + delimiter, eg "\n" or "\r\n"
+        LengthPrefix    1/2/4 byte length header (not counting itself),
+                        with a maximum so that a bogus header does not
+                        make the decoder buffer up to 4 GiB
+        FixedSize       every message is exactly n bytes
+        Slip            RFC 1055, END = 0xC0, ESC = 0xDB
+        Cobs            consistent overhead byte stuffing + 0x00
+
+    the Decoder keeps whatever is left of an incomplete frame
+    so there must be one decoder per connection, a peer that never
+    sends the end of a frame would make it buffer forever, so every
+    mode has a maximum, the length prefix one is configured, the
+    others use DEFAULT_MAX_FRAME, going over it is an error and
+    drops whatever was buffered
+
+    the end of frame search resumes where the previous push stopped
+    instead of rescanning the whole buffer, a large frame coming in
+    small chunks would be quadratic otherwise
+*/
+
+/// the maximum frame when the framing has none of its own
+pub const DEFAULT_MAX_FRAME: u32 = 1 << 20;
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// byte order of a length prefix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    /// network order, most significant byte first
+    #[default]
+    Big,
+    /// least significant byte first
+    Little,
+}
+
+/// how messages are delimited on the wire, see the module notes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Framing {
+    /// no framing, whatever a single read() returns
+    #[default]
+    None,
+
+    /// frames end with the delimiter, which is stripped on receive
+    /// and appended on send
+    Delimiter(Vec<u8>),
+
+    /// `width` is 1, 2 or 4 bytes
+    LengthPrefix {
+        /// header size in bytes
+        width: u8,
+        /// byte order of the header
+        endian: Endian,
+        /// larger frames are a framing error, on send and receive
+        max: u32,
+    },
+
+    /// every message is exactly this many bytes
+    FixedSize(usize),
+
+    /// RFC 1055 serial line ip framing
+    Slip,
+
+    /// consistent overhead byte stuffing, 0x00 terminated
+    Cobs,
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Framing::None => write!(f, "None"),
+            Framing::Delimiter(d) => write!(f, "Delimiter {:?}", String::from_utf8_lossy(d)),
+            Framing::LengthPrefix { width, endian, max } => {
+                write!(
+                    f,
+                    "Length prefix {width} byte(s) {endian:?} endian, max {max} bytes"
+                )
+            }
+            Framing::FixedSize(n) => write!(f, "Fixed size {n} bytes"),
+            Framing::Slip => write!(f, "SLIP"),
+            Framing::Cobs => write!(f, "COBS"),
+        }
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn invalid_input(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+impl Framing {
+    /// catch settings that can never work before a connection uses them
+    pub fn validate(&self) -> io::Result<()> {
+        match self {
+            Framing::Delimiter(d) if d.is_empty() => Err(invalid_input("empty delimiter")),
+            Framing::LengthPrefix { width, .. } if ![1, 2, 4].contains(width) => {
+                Err(invalid_input(format!(
+                    "length prefix width must be 1, 2 or 4, got {width}"
+                )))
+            }
+            Framing::LengthPrefix { max: 0, .. } => {
+                Err(invalid_input("length prefix maximum must be > 0"))
+            }
+            Framing::FixedSize(0) => Err(invalid_input("fixed size must be > 0")),
+            _ => Ok(()),
+        }
+    }
+
+    /// wrap one application message for sending
+    pub fn encode(&self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Framing::None => Ok(payload.to_vec()),
+            Framing::Delimiter(d) => {
+                if !d.is_empty() && payload.windows(d.len()).any(|w| w == d.as_slice()) {
+                    return Err(invalid_input("payload contains the delimiter"));
+                }
+                let mut out = payload.to_vec();
+                out.extend_from_slice(d);
+                Ok(out)
+            }
+            Framing::LengthPrefix { width, endian, max } => {
+                let len = payload.len();
+                if len > *max as usize {
+                    return Err(invalid_input(format!(
+                        "{len} bytes are above the maximum frame of {max}"
+                    )));
+                }
+                let mut out = match (width, endian) {
+                    (1, _) => u8::try_from(len).map(|n| vec![n]).ok(),
+                    (2, Endian::Big) => u16::try_from(len).map(|n| n.to_be_bytes().to_vec()).ok(),
+                    (2, Endian::Little) => {
+                        u16::try_from(len).map(|n| n.to_le_bytes().to_vec()).ok()
+                    }
+                    (4, Endian::Big) => u32::try_from(len).map(|n| n.to_be_bytes().to_vec()).ok(),
+                    (4, Endian::Little) => {
+                        u32::try_from(len).map(|n| n.to_le_bytes().to_vec()).ok()
+                    }
+                    _ => return Err(invalid_input(format!("invalid prefix width {width}"))),
+                }
+                .ok_or_else(|| {
+                    invalid_input(format!(
+                        "{len} bytes do not fit a {width} byte length prefix"
+                    ))
+                })?;
+                out.extend_from_slice(payload);
+                Ok(out)
+            }
+            Framing::FixedSize(n) => {
+                // shorter messages are zero padded, longer ones are an error
+                // rather than silently becoming two records
+                if payload.len() > *n {
+                    return Err(invalid_input(format!(
+                        "{} bytes do not fit a {n} byte record",
+                        payload.len()
+                    )));
+                }
+                let mut out = payload.to_vec();
+                out.resize(*n, 0);
+                Ok(out)
+            }
+            Framing::Slip => Ok(slip_encode(payload)),
+            Framing::Cobs => {
+                let mut out = cobs_encode(payload);
+                out.push(0);
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// leading END flushes any line noise on the receiver (RFC 1055)
+fn slip_encode(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 2);
+    out.push(SLIP_END);
+    for &b in payload {
+        match b {
+            SLIP_END => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            _ => out.push(b),
+        }
+    }
+    out.push(SLIP_END);
+    out
+}
+
+fn slip_decode(frame: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(frame.len());
+    let mut iter = frame.iter();
+    while let Some(&b) = iter.next() {
+        if b != SLIP_ESC {
+            out.push(b);
+            continue;
+        }
+        match iter.next() {
+            Some(&SLIP_ESC_END) => out.push(SLIP_END),
+            Some(&SLIP_ESC_ESC) => out.push(SLIP_ESC),
+            Some(b) => return Err(invalid_data(format!("invalid SLIP escape 0x{b:02x}"))),
+            None => return Err(invalid_data("SLIP frame ends with ESC")),
+        }
+    }
+    Ok(out)
+}
+
+/// without the trailing 0x00 delimiter
+fn cobs_encode(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + payload.len() / 254 + 2);
+    let mut code_idx = 0;
+    out.push(0); // placeholder for the first code byte
+    let mut code = 1u8;
+
+    for (i, &b) in payload.iter().enumerate() {
+        if b == 0 {
+            out[code_idx] = code;
+            code_idx = out.len();
+            out.push(0);
+            code = 1;
+        } else {
+            out.push(b);
+            code += 1;
+            // a full block at the very end needs no empty one after it
+            if code == 0xFF && i + 1 < payload.len() {
+                out[code_idx] = code;
+                code_idx = out.len();
+                out.push(0);
+                code = 1;
+            }
+        }
+    }
+    out[code_idx] = code;
+    out
+}
+
+fn cobs_decode(frame: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(frame.len());
+    let mut i = 0;
+    while i < frame.len() {
+        let code = frame[i] as usize;
+        if code == 0 {
+            return Err(invalid_data("unexpected 0x00 in COBS frame"));
+        }
+        let end = i + code;
+        if end > frame.len() {
+            return Err(invalid_data("COBS code points past the end of the frame"));
+        }
+        out.extend_from_slice(&frame[i + 1..end]);
+        i = end;
+        // a 0xFF block has no implicit zero, neither does the last block
+        if code != 0xFF && i < frame.len() {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+/// turns stream chunks back into frames, one per connection
+pub struct Decoder {
+    framing: Framing,
+    buf: Vec<u8>,
+    scanned: usize, // bytes of buf already searched for the end of frame
+}
+
+impl Decoder {
+    /// one decoder per connection, it keeps partial frames
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            buf: vec![],
+            scanned: 0,
+        }
+    }
+
+    /// the largest frame, decoded, this decoder accepts
+    pub fn max(&self) -> usize {
+        let max = match self.framing {
+            Framing::LengthPrefix { max, .. } => max,
+            _ => DEFAULT_MAX_FRAME,
+        };
+        max as usize
+    }
+
+    /// the most bytes an incomplete frame can take before it is sure
+    /// to end up above max(), SLIP can double every byte
+    fn max_pending(&self) -> usize {
+        let max = self.max();
+        match &self.framing {
+            Framing::Delimiter(d) => max + d.len(),
+            Framing::Slip => max * 2,
+            Framing::Cobs => max + max / 254 + 2,
+            _ => usize::MAX, // bounded by the header or the size
+        }
+    }
+
+    /// start of the next `end` in the buffer, searching only what the
+    /// previous calls have not (minus an `end` split across pushes)
+    fn next_end(&mut self, end: &[u8]) -> Option<usize> {
+        let from = self.scanned.saturating_sub(end.len() - 1);
+        match find(&self.buf[from..], end) {
+            Some(pos) => {
+                self.scanned = 0; // the caller drains up to it
+                Some(from + pos)
+            }
+            None => {
+                self.scanned = self.buf.len();
+                None
+            }
+        }
+    }
+
+    /// the frame if not above max()
+    fn checked(&self, frame: io::Result<Vec<u8>>) -> io::Result<Vec<u8>> {
+        let max = self.max();
+        match frame {
+            Ok(f) if f.len() > max => Err(invalid_data(format!(
+                "frame of {} bytes is above the maximum of {max}",
+                f.len()
+            ))),
+            frame => frame,
+        }
+    }
+
+    /// bytes of an incomplete frame still waiting for more data
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// feed a chunk as returned by read(), get back every frame it
+    /// completed, frames that fail to decode come back as errors
+    /// and do not stop the following ones
+    pub fn push(&mut self, data: &[u8]) -> Vec<io::Result<Vec<u8>>> {
+        if self.framing == Framing::None {
+            return vec![Ok(data.to_vec())];
+        }
+
+        self.buf.extend_from_slice(data);
+        let mut frames = vec![];
+
+        match &self.framing {
+            Framing::None => unreachable!(),
+            Framing::Delimiter(d) => {
+                let d = d.clone();
+                while let Some(pos) = self.next_end(&d) {
+                    let frame = self.buf[..pos].to_vec();
+                    self.buf.drain(..pos + d.len());
+                    frames.push(self.checked(Ok(frame)));
+                }
+            }
+            Framing::LengthPrefix { width, endian, max } => {
+                let width = *width as usize;
+                while self.buf.len() >= width {
+                    let mut header = [0u8; 4];
+                    match endian {
+                        Endian::Big => header[4 - width..].copy_from_slice(&self.buf[..width]),
+                        Endian::Little => header[..width].copy_from_slice(&self.buf[..width]),
+                    }
+                    let len = match endian {
+                        Endian::Big => u32::from_be_bytes(header),
+                        Endian::Little => u32::from_le_bytes(header),
+                    } as usize;
+
+                    if len > *max as usize {
+                        frames.push(Err(invalid_data(format!(
+                            "frame of {len} bytes is above the maximum of {max}"
+                        ))));
+                        // no telling where the next header is, the rest goes
+                        self.buf.clear();
+                        break;
+                    }
+                    if self.buf.len() < width + len {
+                        break;
+                    }
+                    let frame = self.buf[width..width + len].to_vec();
+                    self.buf.drain(..width + len);
+                    frames.push(Ok(frame));
+                }
+            }
+            Framing::FixedSize(n) => {
+                while self.buf.len() >= *n {
+                    frames.push(Ok(self.buf.drain(..*n).collect()));
+                }
+            }
+            Framing::Slip => {
+                while let Some(pos) = self.next_end(&[SLIP_END]) {
+                    let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+                    let raw = &raw[..raw.len() - 1];
+                    // back to back END bytes are just separators
+                    if !raw.is_empty() {
+                        frames.push(self.checked(slip_decode(raw)));
+                    }
+                }
+            }
+            Framing::Cobs => {
+                while let Some(pos) = self.next_end(&[0]) {
+                    let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+                    let raw = &raw[..raw.len() - 1];
+                    if !raw.is_empty() {
+                        frames.push(self.checked(cobs_decode(raw)));
+                    }
+                }
+            }
+        }
+
+        // no end in sight, whatever the rest turns out to be it is too big
+        if self.buf.len() > self.max_pending() {
+            frames.push(Err(invalid_data(format!(
+                "{} bytes without the end of a frame, the maximum is {}",
+                self.buf.len(),
+                self.max()
+            ))));
+            self.buf.clear();
+            self.scanned = 0;
+        }
+        frames
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// parse a typed delimiter like `\r\n` or `\x03` into bytes,
+/// supports \n \r \t \0 \\ and \xNN, anything else is taken as is
+pub fn parse_escaped(s: &str) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut tmp = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut tmp).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push(b'\n'),
+            Some('r') => out.push(b'\r'),
+            Some('t') => out.push(b'\t'),
+            Some('0') => out.push(0),
+            Some('\\') => out.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let b = u8::from_str_radix(&hex, 16)
+                    .map_err(|_| invalid_input(format!("invalid escape \\x{hex}")))?;
+                out.push(b);
+            }
+            Some(c) => return Err(invalid_input(format!("unknown escape \\{c}"))),
+            None => return Err(invalid_input("dangling \\ at the end")),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// encode every message, concatenate them and feed the stream
+    /// back in awkward chunk sizes, the frames must survive
+    fn roundtrip(framing: Framing, messages: &[&[u8]]) {
+        let stream: Vec<u8> = messages
+            .iter()
+            .flat_map(|m| framing.encode(m).unwrap())
+            .collect();
+
+        for chunk_size in [1, 2, 3, 7, stream.len().max(1)] {
+            let mut decoder = Decoder::new(framing.clone());
+            let frames: Vec<Vec<u8>> = stream
+                .chunks(chunk_size)
+                .flat_map(|c| decoder.push(c))
+                .map(|f| f.unwrap())
+                .collect();
+            assert_eq!(frames, messages, "{framing} with chunks of {chunk_size}");
+            assert_eq!(decoder.pending(), 0);
+        }
+    }
+
+    #[test]
+    fn test_none_passes_chunks_through() {
+        let mut decoder = Decoder::new(Framing::None);
+        let frames = decoder.push(b"abc");
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].as_ref().unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_delimiter() {
+        roundtrip(
+            Framing::Delimiter(b"\n".to_vec()),
+            &[b"hello", b"", b"world"],
+        );
+        roundtrip(Framing::Delimiter(b"\r\n".to_vec()), &[b"a\rb", b"c\nd"]);
+        assert!(Framing::Delimiter(b"\n".to_vec()).encode(b"a\nb").is_err());
+        assert!(Framing::Delimiter(vec![]).validate().is_err());
+    }
+
+    #[test]
+    fn test_delimiter_merged_and_split() {
+        let mut decoder = Decoder::new(Framing::Delimiter(b"\n".to_vec()));
+        let frames: Vec<_> = decoder
+            .push(b"one\ntwo\nthr")
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(frames, vec![b"one".to_vec(), b"two".to_vec()]);
+        assert_eq!(decoder.pending(), 3);
+
+        let frames: Vec<_> = decoder.push(b"ee\n").into_iter().flatten().collect();
+        assert_eq!(frames, vec![b"three".to_vec()]);
+    }
+
+    #[test]
+    fn test_length_prefix() {
+        let big = vec![0xAAu8; 300];
+        let prefix = |width, endian| Framing::LengthPrefix {
+            width,
+            endian,
+            max: DEFAULT_MAX_FRAME,
+        };
+        for endian in [Endian::Big, Endian::Little] {
+            roundtrip(prefix(1, endian), &[b"ab", b"", b"xyz"]);
+            roundtrip(prefix(2, endian), &[b"ab", &big]);
+            roundtrip(prefix(4, endian), &[&big, b"c"]);
+        }
+
+        let be = prefix(2, Endian::Big);
+        assert_eq!(be.encode(b"hi").unwrap(), vec![0x00, 0x02, b'h', b'i']);
+        let le = prefix(4, Endian::Little);
+        assert_eq!(le.encode(b"hi").unwrap(), vec![0x02, 0, 0, 0, b'h', b'i']);
+
+        assert!(prefix(1, Endian::Big).encode(&big).is_err());
+        assert!(prefix(3, Endian::Big).validate().is_err());
+    }
+
+    #[test]
+    fn test_length_prefix_max() {
+        let framing = Framing::LengthPrefix {
+            width: 4,
+            endian: Endian::Big,
+            max: 16,
+        };
+        assert!(framing.encode(&[0; 17]).is_err());
+        roundtrip(framing.clone(), &[&[1; 16], b""]);
+
+        // a bogus header is an error right away, nothing is buffered
+        let mut decoder = Decoder::new(framing);
+        let frames = decoder.push(&[0xFF, 0xFF, 0xFF, 0xFF, 1, 2]);
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_err());
+        assert_eq!(decoder.pending(), 0);
+
+        let zero = Framing::LengthPrefix {
+            width: 2,
+            endian: Endian::Big,
+            max: 0,
+        };
+        assert!(zero.validate().is_err());
+    }
+
+    #[test]
+    fn test_max_without_end() {
+        let max = DEFAULT_MAX_FRAME as usize;
+        for framing in [
+            Framing::Delimiter(b"\r\n".to_vec()),
+            Framing::Slip,
+            Framing::Cobs,
+        ] {
+            // an endless frame in small chunks, an error each time the
+            // buffer goes over, it never holds more than that
+            let mut decoder = Decoder::new(framing.clone());
+            let chunk = vec![1u8; 4096];
+            let errors: usize = (0..3 * max / chunk.len())
+                .map(|_| decoder.push(&chunk).len())
+                .sum();
+            assert!(errors > 0, "{framing}");
+            assert!(decoder.pending() <= 2 * max, "{framing}");
+
+            // a frame just above the maximum, complete in one push
+            let mut decoder = Decoder::new(framing.clone());
+            let mut stream = framing.encode(&vec![1u8; max + 1]).unwrap();
+            stream.extend_from_slice(&framing.encode(b"ok").unwrap());
+            let frames = decoder.push(&stream);
+            assert!(frames[0].is_err(), "{framing}");
+            assert_eq!(frames.last().unwrap().as_ref().unwrap(), b"ok");
+        }
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        roundtrip(Framing::FixedSize(4), &[b"abcd", b"efgh"]);
+        assert_eq!(Framing::FixedSize(4).encode(b"ab").unwrap(), b"ab\0\0");
+        assert!(Framing::FixedSize(2).encode(b"abc").is_err());
+        assert!(Framing::FixedSize(0).validate().is_err());
+    }
+
+    #[test]
+    fn test_slip() {
+        roundtrip(
+            Framing::Slip,
+            &[
+                b"plain",
+                &[SLIP_END, 1, SLIP_ESC, 2],
+                &[SLIP_ESC, SLIP_ESC_END],
+            ],
+        );
+        assert_eq!(
+            Framing::Slip.encode(&[1, SLIP_END, SLIP_ESC]).unwrap(),
+            vec![
+                SLIP_END,
+                1,
+                SLIP_ESC,
+                SLIP_ESC_END,
+                SLIP_ESC,
+                SLIP_ESC_ESC,
+                SLIP_END
+            ]
+        );
+
+        let mut decoder = Decoder::new(Framing::Slip);
+        let frames = decoder.push(&[SLIP_ESC, 0x01, SLIP_END, b'o', b'k', SLIP_END]);
+        assert!(frames[0].is_err());
+        assert_eq!(frames[1].as_ref().unwrap(), b"ok");
+    }
+
+    #[test]
+    fn test_cobs() {
+        // examples from the wikipedia article
+        assert_eq!(cobs_encode(&[0x00]), vec![0x01, 0x01]);
+        assert_eq!(cobs_encode(&[0x00, 0x00]), vec![0x01, 0x01, 0x01]);
+        assert_eq!(
+            cobs_encode(&[0x11, 0x22, 0x00, 0x33]),
+            vec![0x03, 0x11, 0x22, 0x02, 0x33]
+        );
+        assert_eq!(
+            cobs_encode(&[0x11, 0x00, 0x00, 0x00]),
+            vec![0x02, 0x11, 0x01, 0x01, 0x01]
+        );
+
+        // the 254 and 255 byte examples, a full block at the end
+        // must not be followed by an empty one
+        let run: Vec<u8> = (0x01..=0xFE).collect();
+        assert_eq!(cobs_encode(&run), [&[0xFF][..], &run].concat());
+        assert_eq!(
+            cobs_encode(&[&[0x00][..], &run].concat()),
+            [&[0x01, 0xFF][..], &run].concat()
+        );
+        let run: Vec<u8> = (0x01..=0xFF).collect();
+        assert_eq!(
+            cobs_encode(&run),
+            [&[0xFF][..], &run[..254], &[0x02, 0xFF]].concat()
+        );
+        let run: Vec<u8> = (0x02..=0xFF).chain([0x00]).collect();
+        assert_eq!(
+            cobs_encode(&run),
+            [&[0xFF][..], &run[..254], &[0x01, 0x01]].concat()
+        );
+        let run: Vec<u8> = (0x03..=0xFF).chain([0x00, 0x01]).collect();
+        assert_eq!(
+            cobs_encode(&run),
+            [&[0xFE][..], &run[..253], &[0x02, 0x01]].concat()
+        );
+
+        let long: Vec<u8> = (1..=255u8).collect();
+        let mut long_zero = long.clone();
+        long_zero.push(0);
+        roundtrip(Framing::Cobs, &[b"", &[0, 0, 1], &long, &long_zero]);
+
+        let mut decoder = Decoder::new(Framing::Cobs);
+        let frames = decoder.push(&[0x05, 0x11, 0x00]);
+        assert!(frames[0].is_err());
+    }
+
+    #[test]
+    fn test_parse_escaped() {
+        assert_eq!(parse_escaped("\\r\\n").unwrap(), b"\r\n");
+        assert_eq!(parse_escaped("END\\x03").unwrap(), b"END\x03");
+        assert_eq!(parse_escaped("a\\\\b").unwrap(), b"a\\b");
+        assert!(parse_escaped("\\q").is_err());
+        assert!(parse_escaped("\\xZZ").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use eframe::egui;
+
+use udptcp::bridge::UdpTarget;
+
+use super::Targets;
+use crate::sends::{Bridging, Worker};
+
+/// the Bridge section, which tcp ends get the datagrams, where tcp
+/// messages go, start / stop and the counters
+///
+/// the bridge is the caller's, Some while on, `targets` are copied
+/// into it every frame
+pub struct BridgePanel {
+    /// the tcp ends datagrams are passed on to
+    pub targets: Targets,
+    to_last_source: bool,
+}
+
+impl Default for BridgePanel {
+    fn default() -> Self {
+        Self {
+            targets: Targets {
+                udp: false,
+                tcp_client: true,
+                tcp_server: true,
+            },
+            to_last_source: false,
+        }
+    }
+}
+
+impl BridgePanel {
+    /// `udp_remote` is where tcp messages go unless sent to the
+    /// last source, taken when starting or switching, returns true
+    /// if Start / Stop was clicked
+    pub fn show_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        bridge: Option<&Worker<Bridging>>,
+        udp_remote: &str,
+    ) -> bool {
+        let mut clicked = false;
+        if let Some(b) = bridge {
+            b.lock().targets = self.targets;
+        }
+        egui::CollapsingHeader::new("Bridge")
+            .id_salt("bridge")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("UDP →").on_hover_text(
+                        "every datagram is one message, framed with the TCP framing",
+                    );
+                    self.targets.show_ui(ui, false);
+                    ui.separator();
+                    ui.label("TCP → UDP")
+                        .on_hover_text("every TCP message (frame) is one datagram");
+                    let before = self.to_last_source;
+                    ui.radio_value(&mut self.to_last_source, false, "UDP remote");
+                    ui.radio_value(&mut self.to_last_source, true, "last source");
+                    if before != self.to_last_source
+                        && let Some(b) = bridge
+                    {
+                        b.lock().bridge.target = self.target(udp_remote);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let running = bridge.is_some();
+                    let label = if running { "Stop" } else { "Start" };
+                    clicked = ui
+                        .add(egui::SelectableLabel::new(running, label))
+                        .on_hover_text("the UDP remote is taken when starting")
+                        .clicked();
+                    if let Some(b) = bridge {
+                        let b = &b.lock().bridge;
+                        ui.label(format!("to TCP {}, to UDP {}", b.to_tcp, b.to_udp));
+                        ui.colored_label(
+                            if b.failed > 0 {
+                                egui::Color32::LIGHT_RED
+                            } else {
+                                ui.visuals().text_color()
+                            },
+                            format!("failed {}", b.failed),
+                        );
+                        if let Some(src) = b.last_source() {
+                            ui.label(format!("last source {src}"));
+                        }
+                    }
+                });
+            });
+        clicked
+    }
+
+    /// where tcp messages go, see show_ui()
+    pub fn target(&self, udp_remote: &str) -> UdpTarget {
+        if self.to_last_source {
+            UdpTarget::LastSource
+        } else {
+            UdpTarget::Fixed(udp_remote.to_string())
+        }
+    }
+}
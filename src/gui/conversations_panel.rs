@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+
+use eframe::egui;
+
+use udptcp::conversation::Conversations;
+use udptcp::display::DisplayMode;
+
+/// the Conversations window, one tab per tcp server peer with its
+/// transcript and a send box, closed ones stay until dismissed
+///
+/// the transcripts are the caller's, so is sending, show() hands
+/// back what was typed for the peer of the open tab
+#[derive(Default)]
+pub struct ConversationsPanel {
+    /// the window is shown
+    pub open: bool,
+    /// conversation id of the open tab
+    pub selected: Option<u64>,
+    msg: String, // escaped, see framing::parse_escaped
+}
+
+impl ConversationsPanel {
+    /// opens the tab of conversation `id`
+    pub fn select(&mut self, id: Option<u64>) {
+        self.selected = id;
+        self.open = true;
+    }
+
+    /// Some((peer, bytes)) when the send box was sent,
+    /// `view` is how the transcript is shown
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        conversations: &mut Conversations,
+        view: DisplayMode,
+    ) -> Option<(SocketAddr, Vec<u8>)> {
+        let mut open = self.open;
+        let mut out = None;
+        egui::Window::new("Conversations")
+            .open(&mut open)
+            .default_width(560.0)
+            .default_height(360.0)
+            .show(ctx, |ui| {
+                let mut dismiss = None;
+                ui.horizontal_wrapped(|ui| {
+                    for conv in conversations.iter() {
+                        let label = match conv.closed {
+                            Some(_) => format!("{} (closed)", conv.peer),
+                            None => conv.peer.to_string(),
+                        };
+                        ui.selectable_value(&mut self.selected, Some(conv.id), label);
+                    }
+                    if conversations.iter().any(|c| c.closed.is_some())
+                        && ui.button("Dismiss closed").clicked()
+                    {
+                        conversations.dismiss_closed();
+                    }
+                });
+                ui.separator();
+
+                let Some(conv) = self.selected.and_then(|id| conversations.get(id)) else {
+                    ui.label("click a peer of the TCP server to open its conversation");
+                    return;
+                };
+                let (id, peer, closed) = (conv.id, conv.peer, conv.closed);
+                ui.horizontal(|ui| match closed {
+                    Some(at) => {
+                        ui.label(format!("{peer} closed at {}", at.format("%H:%M:%S%.3f")));
+                        if ui.button("Dismiss").clicked() {
+                            dismiss = Some(id);
+                        }
+                    }
+                    None => {
+                        ui.label(format!(
+                            "{peer} connected at {}",
+                            conv.opened.format("%H:%M:%S%.3f")
+                        ));
+                    }
+                });
+
+                // the send box stays at the bottom, the transcript takes the rest
+                let bytes = udptcp::framing::parse_escaped(&self.msg);
+                let mut send = false;
+                egui::TopBottomPanel::bottom(egui::Id::new("conversation_send")).show_inside(
+                    ui,
+                    |ui| {
+                        ui.add_enabled_ui(closed.is_none(), |ui| {
+                            ui.horizontal(|ui| {
+                                send |= ui.button("Send").clicked();
+                                let resp = ui
+                                    .add(
+                                        egui::TextEdit::singleline(&mut self.msg)
+                                            .desired_width(ui.available_width())
+                                            .text_color_opt(
+                                                bytes.is_err().then_some(egui::Color32::LIGHT_RED),
+                                            ),
+                                    )
+                                    .on_hover_text("supports \\n \\r \\t \\0 \\\\ and \\xNN");
+                                send |= resp.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            });
+                        });
+                    },
+                );
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &conv.lines {
+                            let arrow = if line.sent { "→" } else { "←" };
+                            ui.monospace(format!(
+                                "{} {arrow} {}",
+                                line.timestamp.format("%H:%M:%S%.3f"),
+                                view.render(&line.data)
+                            ));
+                        }
+                    });
+
+                if send && let Ok(bytes) = bytes {
+                    out = Some((peer, bytes));
+                }
+                if let Some(id) = dismiss {
+                    conversations.dismiss(id);
+                }
+            });
+        self.open = open;
+        out
+    }
+}
@@ -17,20 +17,20 @@ impl DevToolbar {
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     // button to show current app positon
-                    if ui.button("APP INFO").clicked() {
-                        if let Some(viewport) = ctx.input(|i| i.viewport().outer_rect) {
-                            println!("--- APP INFO ---");
-                            println!("app position: {}", viewport.min); // top-left corner
-                            println!("app size: {}", viewport.size());
-                            println!("app dark mode: {:?}", ctx.style().visuals.dark_mode);
+                    if ui.button("APP INFO").clicked()
+                        && let Some(viewport) = ctx.input(|i| i.viewport().outer_rect)
+                    {
+                        println!("--- APP INFO ---");
+                        println!("app position: {}", viewport.min); // top-left corner
+                        println!("app size: {}", viewport.size());
+                        println!("app dark mode: {:?}", ctx.style().visuals.dark_mode);
 
-                            // ctx.memory(|mem| {
-                            //     // no luck getting anything useful
-                            //     println!("{}", mem.layer_ids().len());
-                            // });
+                        // ctx.memory(|mem| {
+                        //     // no luck getting anything useful
+                        //     println!("{}", mem.layer_ids().len());
+                        // });
 
-                            println!("--- END APP INFO ---");
-                        }
+                        println!("--- END APP INFO ---");
                     }
                     ui.separator();
 
@@ -0,0 +1,15 @@
+use eframe::egui;
+
+use udptcp::display::DisplayMode;
+
+/// combo box to pick how received data is shown
+pub fn display_mode_combo(ui: &mut egui::Ui, id_salt: &str, mode: &mut DisplayMode) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(mode.to_string())
+        .width(ui.available_width())
+        .show_ui(ui, |ui| {
+            for m in DisplayMode::ALL {
+                ui.selectable_value(mode, m, m.to_string());
+            }
+        });
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use eframe::egui;
+
+use udptcp::echo::{Echo, Transform};
+use udptcp::framing;
+
+#[derive(PartialEq, Clone, Copy)]
+enum Kind {
+    None,
+    Uppercase,
+    Reverse,
+    Prefix,
+    Suffix,
+}
+
+impl Kind {
+    const ALL: [(Kind, &'static str); 5] = [
+        (Kind::None, "As is"),
+        (Kind::Uppercase, "Uppercase"),
+        (Kind::Reverse, "Reverse"),
+        (Kind::Prefix, "Prefix"),
+        (Kind::Suffix, "Suffix"),
+    ];
+
+    fn label(&self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(k, _)| k == self)
+            .map(|(_, l)| *l)
+            .unwrap_or_default()
+    }
+}
+
+/// on / off + transform + delay for an echo::Echo
+///
+/// unlike the framing, echo applies while running, so the
+/// caller pushes echo() to the engine whenever show_ui() reports
+/// a change, a field that does not parse is shown in red and
+/// the last valid setting stays in effect
+pub struct EchoEdit {
+    enabled: bool,
+    kind: Kind,
+    text: String,
+    delay_ms: String,
+}
+
+impl Default for EchoEdit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kind: Kind::None,
+            text: String::default(),
+            delay_ms: "0".to_string(),
+        }
+    }
+}
+
+impl EchoEdit {
+    /// returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(super::my_toggle(&mut self.enabled))
+                .on_hover_text("send everything received back to where it came from")
+                .changed();
+
+            egui::ComboBox::from_id_salt(format!("{id_salt}_kind"))
+                .selected_text(self.kind.label())
+                .width(80.0)
+                .show_ui(ui, |ui| {
+                    for (kind, label) in Kind::ALL {
+                        changed |= ui.selectable_value(&mut self.kind, kind, label).changed();
+                    }
+                });
+
+            let delay_ok = self.delay().is_some();
+            changed |= ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.delay_ms)
+                        .desired_width(40.0)
+                        .text_color_opt((!delay_ok).then_some(egui::Color32::LIGHT_RED)),
+                )
+                .on_hover_text("delay in ms")
+                .changed();
+
+            if matches!(self.kind, Kind::Prefix | Kind::Suffix) {
+                let text_ok = framing::parse_escaped(&self.text).is_ok();
+                changed |= ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.text)
+                            .desired_width(ui.available_width())
+                            .text_color_opt((!text_ok).then_some(egui::Color32::LIGHT_RED)),
+                    )
+                    .on_hover_text("supports \\n \\r \\t \\0 \\\\ and \\xNN")
+                    .changed();
+            }
+        });
+        changed
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        self.delay_ms.trim().parse().ok().map(Duration::from_millis)
+    }
+
+    /// Ok(None) when switched off, Err if a field does not parse
+    pub fn echo(&self) -> Result<Option<Echo>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let delay = self
+            .delay()
+            .ok_or_else(|| format!("invalid echo delay {:?}", self.delay_ms))?;
+        let text = || framing::parse_escaped(&self.text).map_err(|e| e.to_string());
+        let transform = match self.kind {
+            Kind::None => Transform::None,
+            Kind::Uppercase => Transform::Uppercase,
+            Kind::Reverse => Transform::Reverse,
+            Kind::Prefix => Transform::Prefix(text()?),
+            Kind::Suffix => Transform::Suffix(text()?),
+        };
+        Ok(Some(Echo { transform, delay }))
+    }
+}
@@ -0,0 +1,140 @@
+use std::io;
+
+use eframe::egui;
+
+use udptcp::framing::{self, DEFAULT_MAX_FRAME, Endian, Framing};
+
+#[derive(PartialEq, Clone, Copy)]
+enum Kind {
+    None,
+    Delimiter,
+    LengthPrefix,
+    FixedSize,
+    Slip,
+    Cobs,
+}
+
+impl Kind {
+    const ALL: [(Kind, &'static str); 6] = [
+        (Kind::None, "None"),
+        (Kind::Delimiter, "Delimiter"),
+        (Kind::LengthPrefix, "Length prefix"),
+        (Kind::FixedSize, "Fixed size"),
+        (Kind::Slip, "SLIP"),
+        (Kind::Cobs, "COBS"),
+    ];
+
+    fn label(&self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(k, _)| k == self)
+            .map(|(_, l)| *l)
+            .unwrap_or_default()
+    }
+}
+
+/// editor for a framing::Framing
+///
+/// keeps the typed text around instead of a Framing so that
+/// half typed values (eg an empty size) survive between frames,
+/// the actual framing is only built when a connection starts
+pub struct FramingEdit {
+    kind: Kind,
+    delimiter: String,
+    width: u8,
+    endian: Endian,
+    max_len: String,
+    fixed_size: String,
+}
+
+impl Default for FramingEdit {
+    fn default() -> Self {
+        Self {
+            kind: Kind::None,
+            delimiter: "\\n".to_string(),
+            width: 2,
+            endian: Endian::Big,
+            max_len: DEFAULT_MAX_FRAME.to_string(),
+            fixed_size: "16".to_string(),
+        }
+    }
+}
+
+impl FramingEdit {
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(format!("{id_salt}_kind"))
+                .selected_text(self.kind.label())
+                .show_ui(ui, |ui| {
+                    for (kind, label) in Kind::ALL {
+                        ui.selectable_value(&mut self.kind, kind, label);
+                    }
+                });
+
+            match self.kind {
+                Kind::Delimiter => {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.delimiter)
+                            .desired_width(ui.available_width()),
+                    )
+                    .on_hover_text("supports \\n \\r \\t \\0 \\\\ and \\xNN");
+                }
+                Kind::LengthPrefix => {
+                    egui::ComboBox::from_id_salt(format!("{id_salt}_width"))
+                        .selected_text(format!("{} B", self.width))
+                        .width(40.0)
+                        .show_ui(ui, |ui| {
+                            for w in [1, 2, 4] {
+                                ui.selectable_value(&mut self.width, w, format!("{w} B"));
+                            }
+                        });
+                    ui.selectable_value(&mut self.endian, Endian::Big, "BE");
+                    ui.selectable_value(&mut self.endian, Endian::Little, "LE");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.max_len)
+                            .desired_width(ui.available_width()),
+                    )
+                    .on_hover_text("largest frame in bytes, a bigger header is a framing error");
+                }
+                Kind::FixedSize => {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.fixed_size)
+                            .desired_width(ui.available_width()),
+                    )
+                    .on_hover_text("bytes per record, shorter messages are zero padded");
+                }
+                Kind::None | Kind::Slip | Kind::Cobs => {}
+            }
+        });
+    }
+
+    pub fn framing(&self) -> io::Result<Framing> {
+        let framing = match self.kind {
+            Kind::None => Framing::None,
+            Kind::Delimiter => Framing::Delimiter(framing::parse_escaped(&self.delimiter)?),
+            Kind::LengthPrefix => Framing::LengthPrefix {
+                width: self.width,
+                endian: self.endian,
+                max: self.max_len.trim().parse().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid maximum frame {:?}, {e}", self.max_len),
+                    )
+                })?,
+            },
+            Kind::FixedSize => {
+                let n = self.fixed_size.trim().parse().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid fixed size {:?}, {e}", self.fixed_size),
+                    )
+                })?;
+                Framing::FixedSize(n)
+            }
+            Kind::Slip => Framing::Slip,
+            Kind::Cobs => Framing::Cobs,
+        };
+        framing.validate()?;
+        Ok(framing)
+    }
+}
@@ -0,0 +1,114 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use eframe::egui;
+
+use udptcp::proxy::Impairments;
+
+/// the text fields of a proxy::Impairments
+///
+/// like EchoEdit the settings apply while running, the caller
+/// pushes impairments() to the proxy whenever show_ui() reports
+/// a change, a field that does not parse is shown in red
+pub struct ImpairEdit {
+    latency_ms: String,
+    jitter_ms: String,
+    loss: String,
+    duplicate: String,
+    reorder: String,
+    corrupt: String,
+    bandwidth: String,
+    max_chunk: String,
+    reset: String,
+}
+
+impl Default for ImpairEdit {
+    fn default() -> Self {
+        let zero = || "0".to_string();
+        Self {
+            latency_ms: zero(),
+            jitter_ms: zero(),
+            loss: zero(),
+            duplicate: zero(),
+            reorder: zero(),
+            corrupt: zero(),
+            bandwidth: zero(),
+            max_chunk: zero(),
+            reset: zero(),
+        }
+    }
+}
+
+/// a field in red while it does not parse
+fn field<T: FromStr>(ui: &mut egui::Ui, label: &str, text: &mut String, hover: &str) -> bool {
+    ui.label(label);
+    let ok = parse::<T>(text).is_some();
+    ui.add(
+        egui::TextEdit::singleline(text)
+            .desired_width(50.0)
+            .text_color_opt((!ok).then_some(egui::Color32::LIGHT_RED)),
+    )
+    .on_hover_text(hover)
+    .changed()
+}
+
+fn parse<T: FromStr>(text: &str) -> Option<T> {
+    text.trim().parse().ok()
+}
+
+impl ImpairEdit {
+    /// returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) -> bool {
+        let mut changed = false;
+        egui::Grid::new(format!("{id_salt}_grid"))
+            .num_columns(8)
+            .show(ui, |ui| {
+                changed |= field::<u64>(ui, "Latency", &mut self.latency_ms, "ms, UDP and TCP");
+                changed |= field::<u64>(ui, "Jitter", &mut self.jitter_ms, "ms, UDP");
+                changed |= field::<f64>(ui, "Loss", &mut self.loss, "%, UDP");
+                changed |= field::<f64>(ui, "Duplicate", &mut self.duplicate, "%, UDP");
+                ui.end_row();
+
+                changed |= field::<f64>(ui, "Reorder", &mut self.reorder, "%, UDP");
+                changed |=
+                    field::<f64>(ui, "Corrupt", &mut self.corrupt, "%, UDP, one bit flipped");
+                changed |= field::<u64>(
+                    ui,
+                    "Bandwidth",
+                    &mut self.bandwidth,
+                    "bytes / s per direction, TCP, 0 is unlimited",
+                );
+                changed |= field::<usize>(
+                    ui,
+                    "Max chunk",
+                    &mut self.max_chunk,
+                    "bytes, TCP writes are cut into random pieces up to this, 0 is off",
+                );
+                ui.end_row();
+
+                changed |= field::<f64>(ui, "Reset", &mut self.reset, "% per piece, TCP");
+                ui.end_row();
+            });
+        changed
+    }
+
+    /// Err if a field does not parse or is out of range
+    pub fn impairments(&self) -> Result<Impairments, String> {
+        fn get<T: FromStr>(name: &str, text: &str) -> Result<T, String> {
+            parse(text).ok_or_else(|| format!("invalid {name} {text:?}"))
+        }
+        let imp = Impairments {
+            latency: Duration::from_millis(get("latency", &self.latency_ms)?),
+            jitter: Duration::from_millis(get("jitter", &self.jitter_ms)?),
+            loss: get("loss", &self.loss)?,
+            duplicate: get("duplicate", &self.duplicate)?,
+            reorder: get("reorder", &self.reorder)?,
+            corrupt: get("corrupt", &self.corrupt)?,
+            bandwidth: get("bandwidth", &self.bandwidth)?,
+            max_chunk: get("max chunk", &self.max_chunk)?,
+            reset: get("reset", &self.reset)?,
+        };
+        imp.validate()?;
+        Ok(imp)
+    }
+}
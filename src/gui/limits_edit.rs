@@ -0,0 +1,115 @@
+use eframe::egui;
+
+use udptcp::network::Cidr;
+use udptcp::tcp::Limits;
+
+/// max clients + queue + allow / deny lists for a tcp::Limits
+///
+/// like EchoEdit the limits apply while running, the caller
+/// pushes limits() to the server whenever show_ui() reports a
+/// change, a field that does not parse is shown in red and the
+/// last valid limits stay in effect
+pub struct LimitsEdit {
+    max_clients: String,
+    queue: String,
+    allow: String,
+    deny: String,
+}
+
+impl Default for LimitsEdit {
+    fn default() -> Self {
+        Self {
+            max_clients: "0".to_string(),
+            queue: "0".to_string(),
+            allow: String::default(),
+            deny: String::default(),
+        }
+    }
+}
+
+fn list(ui: &mut egui::Ui, label: &str, text: &mut String, hover: &str) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let ok = Cidr::parse_list(text).is_ok();
+        ui.add(
+            egui::TextEdit::singleline(text)
+                .hint_text("eg 192.168.1.0/24, 10.0.0.5")
+                .desired_width(ui.available_width())
+                .text_color_opt((!ok).then_some(egui::Color32::LIGHT_RED)),
+        )
+        .on_hover_text(hover)
+        .changed()
+    })
+    .inner
+}
+
+impl LimitsEdit {
+    /// returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) -> bool {
+        let mut changed = false;
+        ui.push_id(id_salt, |ui| {
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Max");
+                    let ok = self.max().is_some();
+                    changed |= ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.max_clients)
+                                .desired_width(40.0)
+                                .text_color_opt((!ok).then_some(egui::Color32::LIGHT_RED)),
+                        )
+                        .on_hover_text("clients served at once, 0 is unlimited")
+                        .changed();
+                    ui.label("Queue");
+                    let ok = self.queue().is_some();
+                    changed |= ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.queue)
+                                .desired_width(40.0)
+                                .text_color_opt((!ok).then_some(egui::Color32::LIGHT_RED)),
+                        )
+                        .on_hover_text(
+                            "when full, hold up to this many new connections until a\n\
+                             client leaves, 0 closes them, so do those beyond",
+                        )
+                        .changed();
+                });
+                changed |= list(
+                    ui,
+                    "Allow",
+                    &mut self.allow,
+                    "only these IPs / CIDRs may connect, blank allows everyone",
+                );
+                changed |= list(
+                    ui,
+                    "Deny",
+                    &mut self.deny,
+                    "these IPs / CIDRs are always rejected, even if allowed",
+                );
+            });
+        });
+        changed
+    }
+
+    fn max(&self) -> Option<usize> {
+        self.max_clients.trim().parse().ok()
+    }
+
+    fn queue(&self) -> Option<usize> {
+        self.queue.trim().parse().ok()
+    }
+
+    /// Err if a field does not parse
+    pub fn limits(&self) -> Result<Limits, String> {
+        Ok(Limits {
+            max_clients: self
+                .max()
+                .ok_or_else(|| format!("invalid max clients {:?}", self.max_clients))?,
+            queue: self
+                .queue()
+                .ok_or_else(|| format!("invalid queue length {:?}", self.queue))?,
+            allow: Cidr::parse_list(&self.allow)?,
+            deny: Cidr::parse_list(&self.deny)?,
+        })
+    }
+}
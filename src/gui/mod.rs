@@ -1,7 +1,39 @@
+mod bridge_panel;
+mod conversations_panel;
 mod devtoolbar;
+mod display_combo;
+mod echo_edit;
+mod framing_edit;
+mod impair_edit;
+mod limits_edit;
+mod peers_panel;
+mod proxy_panel;
+mod relay_panel;
+mod replay_panel;
+mod scheduler_panel;
+mod sockopt_edit;
+mod stats_panel;
+mod targets;
 mod textedit_hex;
+mod tls_edit;
 mod toggle_switch;
 
+pub use bridge_panel::BridgePanel;
+pub use conversations_panel::ConversationsPanel;
 // pub use devtoolbar::DevToolbar;
-// pub use textedit_hex::HexEdit;
+pub use display_combo::display_mode_combo;
+pub use echo_edit::EchoEdit;
+pub use framing_edit::FramingEdit;
+pub use impair_edit::ImpairEdit;
+pub use limits_edit::LimitsEdit;
+pub use peers_panel::PeersPanel;
+pub use proxy_panel::ProxyPanel;
+pub use relay_panel::RelayPanel;
+pub use replay_panel::ReplayPanel;
+pub use scheduler_panel::SchedulerPanel;
+pub use sockopt_edit::SockOptEdit;
+pub use stats_panel::StatsPanel;
+pub use targets::Targets;
+pub use textedit_hex::HexEdit;
+pub use tls_edit::{TlsClientEdit, TlsServerEdit};
 pub use toggle_switch::*;
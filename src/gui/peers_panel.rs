@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use eframe::egui;
+
+use udptcp::conversation::Conversations;
+use udptcp::sockopt::SocketInfo;
+use udptcp::tcp::TcpServer;
+
+use super::ConversationsPanel;
+
+/// the connected peers of the tcp server, which ones the sends go
+/// to, the select all / none / invert helpers and a menu per peer
+///
+/// a click opens the peer's conversation, a double click or the
+/// menu closes it, hovering shows the socket options and tls version
+#[derive(Default)]
+pub struct PeersPanel {
+    // since connected clients are in a vec (ordered)
+    // using position (usize) to keep tracking them is easy to do
+    // but with a downside when the collection gets changed
+    // eg [a, b, c] are current connected clients, and selected = 1
+    // when first one `a` gets removed, now `c` is now on position 1
+    // so b is deselected and c is now selected
+    selected: HashSet<SocketAddr>,
+    send_all: bool, // every connected client instead of the selected ones
+}
+
+impl PeersPanel {
+    /// true if a send to the selected peers goes to `peer`
+    pub fn is_target(&self, peer: SocketAddr) -> bool {
+        self.send_all || self.selected.contains(&peer)
+    }
+
+    /// the ticked peers and whether every peer is a target instead
+    pub fn selection(&self) -> (&HashSet<SocketAddr>, bool) {
+        (&self.selected, self.send_all)
+    }
+
+    /// Some(peer) when "Send message" was picked from its menu
+    pub fn show_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        server: &mut TcpServer,
+        conversations: &Conversations,
+        conversations_panel: &mut ConversationsPanel,
+    ) -> Option<SocketAddr> {
+        ui.horizontal(|ui| {
+            ui.label("Connected peers...");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.checkbox(&mut self.send_all, "All connected")
+                    .on_hover_text("send to every connected peer, new ones included");
+                ui.add_enabled_ui(!self.send_all, |ui| {
+                    let peers = server.peers();
+                    if ui.small_button("Invert").clicked() {
+                        self.selected = peers
+                            .into_iter()
+                            .filter(|p| !self.selected.contains(p))
+                            .collect();
+                    } else if ui.small_button("None").clicked() {
+                        self.selected.clear();
+                    } else if ui.small_button("All").clicked() {
+                        self.selected = peers.into_iter().collect();
+                    }
+                });
+            });
+        });
+        ui.separator();
+
+        // collected first, closing a peer changes the list
+        let peers: Vec<_> = server
+            .clients()
+            .iter()
+            .filter_map(|s| {
+                // what the os made of the options, and the tls version
+                let info = [
+                    SocketInfo::read(s.tcp()).ok().map(|i| i.to_string()),
+                    s.tls_info(),
+                ];
+                let info: Vec<_> = info.into_iter().flatten().collect();
+                Some((s.peer_addr().ok()?, info.join("\n")))
+            })
+            .collect();
+        let mut send_to_peer = None;
+        egui::ScrollArea::vertical()
+            // .auto_shrink([false, false])
+            // .auto_shrink([true, true])
+            // .max_height(20.0)
+            .max_height(ui.available_height())
+            .show(ui, |ui| {
+                for (peer_addr, info) in peers {
+                    let conv = conversations.latest(peer_addr).map(|c| c.id);
+                    let resp = ui
+                        .horizontal(|ui| {
+                            // select - diselect, every peer is a
+                            // target in the send all mode
+                            let mut checked = self.is_target(peer_addr);
+                            if ui
+                                .add_enabled(
+                                    !self.send_all,
+                                    egui::Checkbox::without_text(&mut checked),
+                                )
+                                .on_hover_text("send to this peer")
+                                .changed()
+                            {
+                                if checked {
+                                    self.selected.insert(peer_addr);
+                                } else {
+                                    self.selected.remove(&peer_addr);
+                                }
+                            }
+                            let resp = ui.add(egui::SelectableLabel::new(
+                                conversations_panel.open
+                                    && conv.is_some()
+                                    && conversations_panel.selected == conv,
+                                peer_addr.to_string(),
+                            ));
+                            match info.is_empty() {
+                                true => resp,
+                                false => resp.on_hover_text(info),
+                            }
+                        })
+                        .inner;
+
+                    // open its conversation
+                    if resp.clicked() {
+                        conversations_panel.select(conv);
+                    }
+
+                    // close connection with double click
+                    if resp.double_clicked() {
+                        self.selected.remove(&peer_addr);
+                        server.close_client(peer_addr);
+                    }
+
+                    // send to this peer only, or close with right click popup
+                    resp.context_menu(|ui| {
+                        if ui.button("Send message").clicked() {
+                            send_to_peer = Some(peer_addr);
+                            ui.close_menu();
+                        }
+                        if ui.button("Close").clicked() {
+                            self.selected.remove(&peer_addr);
+                            server.close_client(peer_addr);
+                            ui.close_menu(); // ensure the menu closes
+                        }
+                    });
+                }
+            });
+        send_to_peer
+    }
+}
@@ -0,0 +1,132 @@
+use eframe::egui;
+
+use udptcp::network;
+use udptcp::proxy::Proxy;
+use udptcp::replay::Protocol;
+
+use super::ImpairEdit;
+
+/// the Impairment Proxy window, listen port, remote, impairments
+/// and the proxy counters
+///
+/// the proxy itself is the caller's, it listens on the ip handed
+/// to show(), the selected netif, the impairments apply while
+/// running like in ImpairEdit
+pub struct ProxyPanel {
+    /// the window is shown
+    pub open: bool,
+    protocol: Protocol,
+    local_port: String,
+    remote_ip: String,
+    remote_port: String,
+    impair: ImpairEdit,
+}
+
+impl Default for ProxyPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            protocol: Protocol::Udp,
+            local_port: "0".to_string(),
+            remote_ip: String::default(),
+            remote_port: String::default(),
+            impair: ImpairEdit::default(),
+        }
+    }
+}
+
+impl ProxyPanel {
+    /// `local_ip` is where the proxy listens when started
+    pub fn show(&mut self, ctx: &egui::Context, proxy: &mut Proxy, local_ip: &str) {
+        let mut open = self.open;
+        egui::Window::new("Impairment Proxy")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                let running = proxy.is_up();
+                ui.add_enabled_ui(!running, |ui| {
+                    ui.horizontal(|ui| {
+                        for p in [Protocol::Udp, Protocol::Tcp] {
+                            ui.selectable_value(&mut self.protocol, p, p.to_string());
+                        }
+                        ui.separator();
+                        ui.label("Local port");
+                        ui.add(egui::TextEdit::singleline(&mut self.local_port).desired_width(50.0))
+                            .on_hover_text("on the selected netif, 0 picks a free one");
+                        ui.label("Remote");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.remote_ip)
+                                .hint_text("ip")
+                                .desired_width(110.0),
+                        );
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.remote_port)
+                                .hint_text("port")
+                                .desired_width(50.0),
+                        );
+                    });
+                });
+
+                ui.separator();
+                if self.impair.show_ui(ui, "proxy_impair") {
+                    match self
+                        .impair
+                        .impairments()
+                        .and_then(|imp| proxy.set_impairments(imp))
+                    {
+                        Ok(()) => log::debug!("proxy impairments {:?}", proxy.impairments()),
+                        // shown in red, the last valid setting stays
+                        Err(e) => log::debug!("proxy impairments not applied, {e}"),
+                    }
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    let label = if running { "Stop" } else { "Start" };
+                    if ui.add(egui::SelectableLabel::new(running, label)).clicked() {
+                        if running {
+                            proxy.stop();
+                        } else {
+                            self.start(proxy, local_ip);
+                        }
+                    }
+                    if let Some(local) = proxy.local_addr() {
+                        ui.label(format!("listening on {local}"));
+                    }
+                });
+                let s = proxy.stats();
+                ui.label(match self.protocol {
+                    Protocol::Udp => format!(
+                        "forwarded {} ({} bytes), dropped {}, duplicated {}, reordered {}, corrupted {}",
+                        s.forwarded, s.bytes, s.dropped, s.duplicated, s.reordered, s.corrupted
+                    ),
+                    Protocol::Tcp => format!(
+                        "connections {}, forwarded {} pieces ({} bytes), resets {}",
+                        s.connections, s.forwarded, s.bytes, s.resets
+                    ),
+                });
+            });
+        self.open = open;
+    }
+
+    fn start(&mut self, proxy: &mut Proxy, local_ip: &str) {
+        if let Err(e) = self
+            .impair
+            .impairments()
+            .and_then(|imp| proxy.set_impairments(imp))
+        {
+            log::error!("cannot start the proxy, {e}");
+            return;
+        }
+        let local = network::host_port(local_ip, &self.local_port);
+        let remote = network::host_port(&self.remote_ip, &self.remote_port);
+        let started = match self.protocol {
+            Protocol::Udp => proxy.start_udp(&local, &remote),
+            Protocol::Tcp => proxy.start_tcp(&local, &remote),
+        };
+        match started {
+            Ok(local) => self.local_port = local.port().to_string(),
+            Err(e) => log::error!("cannot start the proxy on {local}, {e}"),
+        }
+    }
+}
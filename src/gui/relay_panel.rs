@@ -0,0 +1,197 @@
+use eframe::egui;
+
+use udptcp::display::DisplayMode;
+use udptcp::network;
+use udptcp::relay::{Direction, Leg, Relay};
+
+use super::display_mode_combo;
+
+/// the TCP Relay window, listen / upstream, the connections with
+/// their pause and kill buttons, and a byte editor for the held
+/// bytes and injecting
+///
+/// the relay is the caller's, it listens on the ip handed to show(),
+/// the byte editor works on the selected connection and direction
+pub struct RelayPanel {
+    /// the window is shown
+    pub open: bool,
+    /// how relayed data is logged
+    pub view: DisplayMode,
+    /// connection id the byte editor works on
+    pub selected: Option<usize>,
+    local_port: String,
+    upstream_ip: String,
+    upstream_port: String,
+    dir: Direction,
+    bytes: String, // escaped, see framing::parse_escaped
+}
+
+impl Default for RelayPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            view: DisplayMode::default(),
+            selected: None,
+            local_port: "0".to_string(),
+            upstream_ip: String::default(),
+            upstream_port: String::default(),
+            dir: Direction::ToUpstream,
+            bytes: String::default(),
+        }
+    }
+}
+
+impl RelayPanel {
+    /// `local_ip` is where the relay listens when started
+    pub fn show(&mut self, ctx: &egui::Context, relay: &mut Relay, local_ip: &str) {
+        let mut open = self.open;
+        egui::Window::new("TCP Relay")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                self.listen_ui(ui, relay, local_ip);
+                ui.separator();
+                self.connections_ui(ui, relay);
+            });
+        self.open = open;
+    }
+
+    fn listen_ui(&mut self, ui: &mut egui::Ui, relay: &mut Relay, local_ip: &str) {
+        let running = relay.is_up();
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                ui.label("Local port");
+                ui.add(egui::TextEdit::singleline(&mut self.local_port).desired_width(50.0))
+                    .on_hover_text("on the selected netif, 0 picks a free one");
+                ui.label("Upstream");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.upstream_ip)
+                        .hint_text("ip")
+                        .desired_width(110.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.upstream_port)
+                        .hint_text("port")
+                        .desired_width(50.0),
+                );
+            });
+            let label = if running { "Stop" } else { "Start" };
+            if ui.add(egui::SelectableLabel::new(running, label)).clicked() {
+                if running {
+                    relay.stop();
+                } else {
+                    let local = network::host_port(local_ip, &self.local_port);
+                    let upstream = network::host_port(&self.upstream_ip, &self.upstream_port);
+                    match relay.begin(&local, &upstream) {
+                        Ok(local) => self.local_port = local.port().to_string(),
+                        Err(e) => log::error!("cannot start the relay on {local}, {e}"),
+                    }
+                }
+            }
+            ui.allocate_ui(egui::vec2(110.0, 20.0), |ui| {
+                display_mode_combo(ui, "relay_view", &mut self.view);
+            });
+        });
+        if let Some(local) = relay.local_addr() {
+            ui.label(format!("listening on {local}"));
+        }
+    }
+
+    fn connections_ui(&mut self, ui: &mut egui::Ui, relay: &Relay) {
+        let conns = relay.connections();
+        if conns.is_empty() {
+            ui.label("no connections");
+        }
+        egui::Grid::new("relay_grid")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for conn in &conns {
+                    let id = conn.id;
+                    ui.radio_value(
+                        &mut self.selected,
+                        Some(id),
+                        format!("#{id} {} ⇄ {}", conn.client, conn.upstream),
+                    );
+                    for dir in Direction::ALL {
+                        let paused = relay.is_paused(id, dir);
+                        let held = relay.held(id, dir).len();
+                        let label = if paused {
+                            format!("▶ {dir} ({held} held)")
+                        } else {
+                            format!("⏸ {dir}")
+                        };
+                        if ui
+                            .add(egui::SelectableLabel::new(paused, label))
+                            .on_hover_text("paused bytes are held until resumed")
+                            .clicked()
+                            && let Err(e) = relay.pause(id, dir, !paused)
+                        {
+                            log::error!("relay #{id} {dir}, {e}");
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        for leg in [Leg::Client, Leg::Upstream] {
+                            if ui.button(format!("Kill {leg}")).clicked()
+                                && let Err(e) = relay.kill(id, leg)
+                            {
+                                log::error!("cannot kill relay #{id} {leg} leg, {e}");
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+        if let Some(id) = self.selected.filter(|id| conns.iter().any(|c| c.id == *id)) {
+            self.bytes_ui(ui, relay, id);
+        }
+    }
+
+    fn bytes_ui(&mut self, ui: &mut egui::Ui, relay: &Relay, id: usize) {
+        ui.horizontal(|ui| {
+            ui.label(format!("#{id}"));
+            for dir in Direction::ALL {
+                ui.selectable_value(&mut self.dir, dir, dir.to_string());
+            }
+        });
+        let bytes = udptcp::framing::parse_escaped(&self.bytes);
+        ui.add(
+            egui::TextEdit::multiline(&mut self.bytes)
+                .desired_rows(3)
+                .desired_width(f32::INFINITY)
+                .text_color_opt(bytes.is_err().then_some(egui::Color32::LIGHT_RED)),
+        )
+        .on_hover_text("supports \\n \\r \\t \\0 \\\\ and \\xNN");
+        ui.horizontal(|ui| {
+            let dir = self.dir;
+            if ui
+                .button("Load held")
+                .on_hover_text("copy the held bytes here for editing")
+                .clicked()
+            {
+                self.bytes = DisplayMode::Escaped.render(&relay.held(id, dir));
+            }
+            let Ok(bytes) = bytes else {
+                return;
+            };
+            if ui
+                .button("Replace held")
+                .on_hover_text("these bytes go out instead when resumed")
+                .clicked()
+                && let Err(e) = relay.set_held(id, dir, bytes.clone())
+            {
+                log::error!("relay #{id} {dir}, {e}");
+            }
+            if ui
+                .button("Inject")
+                .on_hover_text("send these bytes now, even while paused")
+                .clicked()
+            {
+                // logged by the engine
+                let _ = relay.inject(id, dir, &bytes);
+            }
+        });
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+use udptcp::replay::{self, Filter, Message, Protocol, Replay, Timing};
+
+use crate::sends::Worker;
+
+/// the timing buttons, the value is typed next to them
+#[derive(Clone, Copy, PartialEq)]
+enum ReplayTiming {
+    Original,
+    Speed,
+    Interval,
+}
+
+/// the Replay section, file, filters, timing, start / stop and
+/// the counters
+///
+/// the file is loaded here, the fields are parsed by replay() when
+/// starting, the replay and its worker are the caller's (udp messages
+/// go to the udp remote and tcp ones to the tcp client)
+pub struct ReplayPanel {
+    path: String,
+    messages: Vec<Message>, // as loaded, before filtering
+    protocol: Option<Protocol>,
+    src_port: String,
+    dst_port: String,
+    timing: ReplayTiming,
+    speed: String,
+    interval_ms: String,
+}
+
+impl Default for ReplayPanel {
+    fn default() -> Self {
+        Self {
+            path: String::default(),
+            messages: vec![],
+            protocol: None,
+            src_port: String::default(),
+            dst_port: String::default(),
+            timing: ReplayTiming::Original,
+            speed: "1".to_string(),
+            interval_ms: "100".to_string(),
+        }
+    }
+}
+
+impl ReplayPanel {
+    /// returns true when Start is clicked, Stop stops `replay` here
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, replay: Option<&Worker<Replay>>) -> bool {
+        let running = replay.is_some_and(|r| !r.lock().is_done());
+        let mut start = false;
+        egui::CollapsingHeader::new("Replay")
+            .id_salt("replay")
+            .show(ui, |ui| {
+                ui.add_enabled_ui(!running, |ui| {
+                    self.file_ui(ui);
+                    self.filter_ui(ui);
+                });
+
+                ui.horizontal(|ui| {
+                    let label = if running { "Stop" } else { "Start" };
+                    if ui.add(egui::SelectableLabel::new(running, label)).clicked() {
+                        if !running {
+                            start = true;
+                        } else if let Some(r) = replay {
+                            let mut r = r.lock();
+                            r.stop();
+                            log::info!("replay stopped, {} sent, {} failed", r.sent, r.failed);
+                        }
+                    }
+                    if let Some(r) = replay {
+                        let r = r.lock();
+                        let (done, total) = r.progress();
+                        ui.label(format!("{done} / {total}, sent {}", r.sent));
+                        ui.colored_label(
+                            if r.failed > 0 {
+                                egui::Color32::LIGHT_RED
+                            } else {
+                                ui.visuals().text_color()
+                            },
+                            format!("failed {}", r.failed),
+                        );
+                    }
+                });
+            });
+        start
+    }
+
+    fn file_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.path)
+                    .hint_text("capture.pcapng")
+                    .desired_width(240.0),
+            )
+            .on_hover_text("pcap or pcapng");
+            if ui.button("Load").clicked() {
+                self.load();
+            }
+            ui.label(format!("{} messages", self.messages.len()));
+        });
+    }
+
+    fn filter_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("combo_replay_protocol")
+                .selected_text(match self.protocol {
+                    None => "UDP + TCP".to_string(),
+                    Some(p) => p.to_string(),
+                })
+                .width(90.0)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.protocol, None, "UDP + TCP");
+                    for p in [Protocol::Udp, Protocol::Tcp] {
+                        ui.selectable_value(&mut self.protocol, Some(p), p.to_string());
+                    }
+                })
+                .response
+                .on_hover_text("UDP goes to the UDP remote, TCP to the TCP client");
+            ui.label("src port");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.src_port)
+                    .hint_text("any")
+                    .desired_width(50.0),
+            );
+            ui.label("dst port");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.dst_port)
+                    .hint_text("any")
+                    .desired_width(50.0),
+            );
+
+            ui.separator();
+            ui.selectable_value(&mut self.timing, ReplayTiming::Original, "Original");
+            ui.selectable_value(&mut self.timing, ReplayTiming::Speed, "Speed");
+            ui.selectable_value(&mut self.timing, ReplayTiming::Interval, "Interval");
+            match self.timing {
+                ReplayTiming::Original => {}
+                ReplayTiming::Speed => {
+                    ui.add(egui::TextEdit::singleline(&mut self.speed).desired_width(40.0))
+                        .on_hover_text("2 is twice as fast, 0.5 half as fast");
+                    ui.label("x");
+                }
+                ReplayTiming::Interval => {
+                    ui.add(egui::TextEdit::singleline(&mut self.interval_ms).desired_width(50.0));
+                    ui.label("ms");
+                }
+            }
+        });
+    }
+
+    fn load(&mut self) {
+        let path = self.path.trim();
+        match replay::load(path) {
+            Ok(messages) => {
+                let udp = messages
+                    .iter()
+                    .filter(|m| m.protocol == Protocol::Udp)
+                    .count();
+                log::info!(
+                    "loaded {} UDP and {} TCP messages from {path}",
+                    udp,
+                    messages.len() - udp
+                );
+                self.messages = messages;
+            }
+            Err(e) => log::error!("cannot load {path:?}, {e}"),
+        }
+    }
+
+    /// filters the loaded messages and parses the timing fields
+    pub fn replay(&self, now: Instant) -> Result<Replay, String> {
+        let port = |text: &str, what: &str| -> Result<Option<u16>, String> {
+            match text.trim() {
+                "" => Ok(None),
+                t => t
+                    .parse()
+                    .map(Some)
+                    .map_err(|e| format!("invalid {what} port {t:?}, {e}")),
+            }
+        };
+        let filter = Filter {
+            protocol: self.protocol,
+            src_port: port(&self.src_port, "source")?,
+            dst_port: port(&self.dst_port, "destination")?,
+        };
+        let timing = match self.timing {
+            ReplayTiming::Original => Timing::Original,
+            ReplayTiming::Speed => Timing::Speed(
+                self.speed
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid speed {:?}, {e}", self.speed))?,
+            ),
+            ReplayTiming::Interval => Timing::Interval(Duration::from_millis(
+                self.interval_ms
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid interval {:?}, {e}", self.interval_ms))?,
+            )),
+        };
+
+        let messages: Vec<_> = self
+            .messages
+            .iter()
+            .filter(|m| filter.matches(m))
+            .cloned()
+            .collect();
+        let count = messages.len();
+        let replay = Replay::new(messages, timing, now)?;
+        log::info!("replay started, {count} messages, {timing:?}");
+        Ok(replay)
+    }
+}
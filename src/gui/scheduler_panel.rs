@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use eframe::egui;
+
+use udptcp::schedule::{Schedule, Scheduler, Stamp};
+
+use super::Targets;
+use crate::sends::Worker;
+
+/// the Scheduler section, interval, limits, stamp and targets,
+/// start / stop and the counters
+///
+/// the text fields are parsed by schedule() when starting, the
+/// scheduler and its worker are the caller's
+pub struct SchedulerPanel {
+    interval_ms: String,
+    max_sends: String,
+    max_secs: String,
+    stamp: Stamp,
+    /// where the sends go
+    pub targets: Targets,
+}
+
+impl Default for SchedulerPanel {
+    fn default() -> Self {
+        Self {
+            interval_ms: "1000".to_string(),
+            max_sends: "0".to_string(),
+            max_secs: "0".to_string(),
+            stamp: Stamp::None,
+            targets: Targets::ALL,
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    text.trim()
+        .parse()
+        .map_err(|e| format!("invalid {what} {text:?}, {e}"))
+}
+
+impl SchedulerPanel {
+    /// returns true when Start is clicked, Stop stops `scheduler` here
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, scheduler: Option<&Worker<Scheduler>>) -> bool {
+        let running = scheduler.is_some_and(|s| !s.lock().is_done());
+        let mut start = false;
+        egui::CollapsingHeader::new("Scheduler")
+            .id_salt("scheduler")
+            .show(ui, |ui| {
+                ui.add_enabled_ui(!running, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Every");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.interval_ms).desired_width(50.0),
+                        );
+                        ui.label("ms, stop after");
+                        ui.add(egui::TextEdit::singleline(&mut self.max_sends).desired_width(50.0))
+                            .on_hover_text("0 for no limit");
+                        ui.label("sends or");
+                        ui.add(egui::TextEdit::singleline(&mut self.max_secs).desired_width(50.0))
+                            .on_hover_text("0 for no limit");
+                        ui.label("s");
+
+                        ui.separator();
+                        ui.label("Append")
+                            .on_hover_text("added to the message after a space");
+                        egui::ComboBox::from_id_salt("combo_sched_stamp")
+                            .selected_text(self.stamp.to_string())
+                            .width(90.0)
+                            .show_ui(ui, |ui| {
+                                for stamp in Stamp::ALL {
+                                    ui.selectable_value(&mut self.stamp, stamp, stamp.to_string());
+                                }
+                            });
+
+                        ui.separator();
+                        self.targets.show_ui(ui, true);
+                    });
+                });
+
+                ui.horizontal(|ui| {
+                    let label = if running { "Stop" } else { "Start" };
+                    if ui
+                        .add(egui::SelectableLabel::new(running, label))
+                        .on_hover_text("the message is taken when starting")
+                        .clicked()
+                    {
+                        if !running {
+                            start = true;
+                        } else if let Some(s) = scheduler {
+                            let mut s = s.lock();
+                            s.stop();
+                            log::info!("scheduler stopped, {} sent, {} failed", s.sent, s.failed);
+                        }
+                    }
+                    if let Some(s) = scheduler {
+                        let s = s.lock();
+                        ui.label(format!("sent {}", s.sent));
+                        ui.colored_label(
+                            if s.failed > 0 {
+                                egui::Color32::LIGHT_RED
+                            } else {
+                                ui.visuals().text_color()
+                            },
+                            format!("failed {}", s.failed),
+                        );
+                        if s.skipped > 0 {
+                            ui.colored_label(
+                                egui::Color32::LIGHT_RED,
+                                format!("skipped {}", s.skipped),
+                            )
+                            .on_hover_text(
+                                "planned sends left out after a long pause, eg the computer slept",
+                            );
+                        }
+                    }
+                });
+            });
+        start
+    }
+
+    /// Err if a field does not parse
+    pub fn schedule(&self) -> Result<Schedule, String> {
+        let interval = number(&self.interval_ms, "interval")?;
+        let max_sends = number(&self.max_sends, "send count")?;
+        let max_secs: u64 = number(&self.max_secs, "duration")?;
+        Ok(Schedule {
+            interval: Duration::from_millis(interval),
+            max_sends,
+            max_duration: (max_secs > 0).then(|| Duration::from_secs(max_secs)),
+            stamp: self.stamp,
+        })
+    }
+}
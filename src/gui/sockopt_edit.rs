@@ -0,0 +1,170 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use eframe::egui;
+
+use udptcp::sockopt::{Keepalive, SocketOptions};
+
+/// the fields of a sockopt::SocketOptions
+///
+/// like EchoEdit the options apply while running, the caller
+/// pushes options() to the engine whenever show_ui() reports a
+/// change, a blank size or ttl keeps the os default, a field
+/// that does not parse is shown in red
+pub struct SockOptEdit {
+    nodelay: bool,
+    keepalive: bool,
+    idle_s: String,
+    interval_s: String,
+    count: String,
+    linger: bool,
+    linger_s: String,
+    send_buffer: String,
+    recv_buffer: String,
+    ttl: String,
+}
+
+impl Default for SockOptEdit {
+    fn default() -> Self {
+        let ka = Keepalive::default();
+        Self {
+            nodelay: false,
+            keepalive: false,
+            idle_s: ka.idle.as_secs().to_string(),
+            interval_s: ka.interval.as_secs().to_string(),
+            count: ka.count.to_string(),
+            linger: false,
+            linger_s: "0".to_string(),
+            send_buffer: String::default(),
+            recv_buffer: String::default(),
+            ttl: String::default(),
+        }
+    }
+}
+
+/// a field in red while it does not parse, blank is fine if `optional`
+fn field<T: FromStr>(
+    ui: &mut egui::Ui,
+    text: &mut String,
+    optional: bool,
+    width: f32,
+    hover: &str,
+) -> bool {
+    let ok = (optional && text.trim().is_empty()) || text.trim().parse::<T>().is_ok();
+    ui.add(
+        egui::TextEdit::singleline(text)
+            .hint_text(if optional { "default" } else { "" })
+            .desired_width(width)
+            .text_color_opt((!ok).then_some(egui::Color32::LIGHT_RED)),
+    )
+    .on_hover_text(hover)
+    .changed()
+}
+
+fn get<T: FromStr>(name: &str, text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("invalid {name} {text:?}"))
+}
+
+/// None for a blank field
+fn get_opt<T: FromStr>(name: &str, text: &str) -> Result<Option<T>, String> {
+    match text.trim().is_empty() {
+        true => Ok(None),
+        false => get(name, text).map(Some),
+    }
+}
+
+impl SockOptEdit {
+    /// returns true if anything changed
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) -> bool {
+        let mut changed = false;
+        egui::Grid::new(format!("{id_salt}_grid"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                changed |= ui
+                    .checkbox(&mut self.nodelay, "NoDelay")
+                    .on_hover_text("TCP_NODELAY, send small writes right away (no Nagle)")
+                    .changed();
+                ui.end_row();
+
+                changed |= ui
+                    .checkbox(&mut self.keepalive, "Keepalive")
+                    .on_hover_text("SO_KEEPALIVE")
+                    .changed();
+                ui.horizontal(|ui| {
+                    changed |= field::<u64>(ui, &mut self.idle_s, false, 35.0, "idle s");
+                    changed |= field::<u64>(ui, &mut self.interval_s, false, 35.0, "interval s");
+                    changed |= field::<u32>(ui, &mut self.count, false, 25.0, "probes");
+                });
+                ui.end_row();
+
+                changed |= ui
+                    .checkbox(&mut self.linger, "Linger")
+                    .on_hover_text("SO_LINGER, 0 resets (RST) the connection on close")
+                    .changed();
+                changed |= field::<u64>(ui, &mut self.linger_s, false, 35.0, "seconds");
+                ui.end_row();
+
+                ui.label("Buffers");
+                ui.horizontal(|ui| {
+                    changed |=
+                        field::<usize>(ui, &mut self.send_buffer, true, 60.0, "SO_SNDBUF bytes");
+                    changed |=
+                        field::<usize>(ui, &mut self.recv_buffer, true, 60.0, "SO_RCVBUF bytes");
+                });
+                ui.end_row();
+
+                ui.label("TTL");
+                changed |= field::<u32>(ui, &mut self.ttl, true, 60.0, "IP TTL / hop limit");
+                ui.end_row();
+            });
+        changed
+    }
+
+    /// show_ui() in a collapsible "Socket Options" section with
+    /// `below` under the fields (eg what the os made of them),
+    /// returns the options when they changed and are valid
+    pub fn show_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        id_salt: &str,
+        below: impl FnOnce(&mut egui::Ui),
+    ) -> Option<SocketOptions> {
+        egui::CollapsingHeader::new("Socket Options")
+            .id_salt(id_salt)
+            .show(ui, |ui| {
+                let changed = self.show_ui(ui, &format!("{id_salt}_fields"));
+                below(ui);
+                changed.then(|| self.options().ok()).flatten()
+            })
+            .body_returned
+            .flatten()
+    }
+
+    /// Err if a field does not parse or is out of range
+    pub fn options(&self) -> Result<SocketOptions, String> {
+        let keepalive = match self.keepalive {
+            false => None,
+            true => Some(Keepalive {
+                idle: Duration::from_secs(get("keepalive idle", &self.idle_s)?),
+                interval: Duration::from_secs(get("keepalive interval", &self.interval_s)?),
+                count: get("keepalive count", &self.count)?,
+            }),
+        };
+        let linger = match self.linger {
+            false => None,
+            true => Some(Duration::from_secs(get("linger", &self.linger_s)?)),
+        };
+        let opts = SocketOptions {
+            nodelay: self.nodelay,
+            keepalive,
+            linger,
+            send_buffer: get_opt("send buffer", &self.send_buffer)?,
+            recv_buffer: get_opt("receive buffer", &self.recv_buffer)?,
+            ttl: get_opt("ttl", &self.ttl)?,
+        };
+        opts.validate()?;
+        Ok(opts)
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use eframe::egui;
+
+use udptcp::stats::{Counters, Stats};
+use udptcp::tcp::TcpServer;
+
+/// the Traffic Stats window, counters per socket / peer and a
+/// throughput plot
+///
+/// the counters are kept by the caller, the window only shows
+/// them and resets them on request
+#[derive(Default)]
+pub struct StatsPanel {
+    /// the window is shown
+    pub open: bool,
+}
+
+fn time(t: Option<chrono::DateTime<chrono::Local>>) -> String {
+    t.map(|t| t.format("%H:%M:%S").to_string())
+        .unwrap_or("-".to_string())
+}
+
+fn row(ui: &mut egui::Ui, name: &str, c: &Counters) {
+    ui.label(name);
+    for n in [c.rx_packets, c.rx_bytes, c.tx_packets, c.tx_bytes, c.errors] {
+        ui.label(n.to_string());
+    }
+    ui.label(time(c.first_seen));
+    ui.label(time(c.last_seen));
+    ui.end_row();
+}
+
+impl StatsPanel {
+    /// tcp server peers no longer in `server` are marked as closed
+    pub fn show(&mut self, ctx: &egui::Context, stats: &mut Stats, server: &TcpServer) {
+        egui::Window::new("Traffic Stats")
+            .open(&mut self.open)
+            .default_width(720.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("stats_grid")
+                            .num_columns(8)
+                            .spacing([16.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for h in [
+                                    "",
+                                    "RX pkts",
+                                    "RX bytes",
+                                    "TX pkts",
+                                    "TX bytes",
+                                    "Errors",
+                                    "First seen",
+                                    "Last seen",
+                                ] {
+                                    ui.strong(h);
+                                }
+                                ui.end_row();
+
+                                row(ui, "UDP socket", &stats.udp);
+                                for (addr, c) in &stats.udp_peers {
+                                    row(ui, &format!("  UDP {addr}"), c);
+                                }
+                                row(ui, "TCP client", &stats.tcp_client);
+
+                                let connected: HashSet<SocketAddr> =
+                                    server.peers().into_iter().collect();
+                                for (addr, c) in &stats.tcp_server_peers {
+                                    let closed = if connected.contains(addr) {
+                                        ""
+                                    } else {
+                                        " (closed)"
+                                    };
+                                    row(ui, &format!("TCP server {addr}{closed}"), c);
+                                }
+                            });
+                    });
+
+                ui.separator();
+                let (rx, tx) = stats.throughput.series(chrono::Local::now());
+                egui_plot::Plot::new("throughput")
+                    .height(180.0)
+                    .legend(egui_plot::Legend::default())
+                    .x_axis_label("seconds ago")
+                    .y_axis_label("bytes / s")
+                    .allow_scroll(false)
+                    .include_y(0.0)
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(egui_plot::Line::new(rx).name("RX"));
+                        plot_ui.line(egui_plot::Line::new(tx).name("TX"));
+                    });
+
+                if ui.button("Reset").clicked() {
+                    stats.reset();
+                    log::info!("traffic stats reset");
+                }
+            });
+    }
+}
@@ -0,0 +1,28 @@
+use eframe::egui;
+
+/// which connections a send goes to, whatever is down is skipped
+#[derive(Clone, Copy)]
+pub struct Targets {
+    pub udp: bool,
+    pub tcp_client: bool,
+    pub tcp_server: bool, // the selected clients only
+}
+
+impl Targets {
+    /// all of them
+    pub const ALL: Targets = Targets {
+        udp: true,
+        tcp_client: true,
+        tcp_server: true,
+    };
+
+    /// a checkbox per connection, without the udp one if `!udp`
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, udp: bool) {
+        if udp {
+            ui.checkbox(&mut self.udp, "UDP");
+        }
+        ui.checkbox(&mut self.tcp_client, "TCP Client");
+        ui.checkbox(&mut self.tcp_server, "TCP Server")
+            .on_hover_text("the selected clients");
+    }
+}
@@ -5,11 +5,11 @@ use eframe::egui;
 ///
 /// potential issues
 /// 1. when deleting to the right, eg [xx| x] using DEL key,
-/// nothing happens (same behavior in DSA)
+///    nothing happens (same behavior in DSA)
 ///
 /// 2. when deleting to the left eg [xx |xx] using delete key,
-/// nothing happens but only cursor moves to [xx| xx], this
-/// maybe is not an issue
+///    nothing happens but only cursor moves to [xx| xx], this
+///    maybe is not an issue
 pub struct HexEdit {
     text: String,
 }
@@ -154,6 +154,12 @@ impl HexEdit {
             .collect()
     }
 
+    /// decode the hex digits into bytes, an odd number
+    /// of digits is an error rather than a guessed nibble
+    pub fn get_bytes(&self) -> Result<Vec<u8>, hex::FromHexError> {
+        hex::decode(self.get_text())
+    }
+
     pub fn set_text(&mut self, s: &str) {
         let (formatted_text, _) = Self::format_hex_and_calculate_cursor(s, s.len());
         self.text = formatted_text;
@@ -204,6 +210,18 @@ mod tests {
         assert_eq!(new_cursor, 0);
     }
 
+    #[test]
+    fn test_get_bytes() {
+        let edit = HexEdit::new("de ad BE EF 00");
+        assert_eq!(
+            edit.get_bytes().unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef, 0x00]
+        );
+
+        let edit = HexEdit::new("123");
+        assert!(edit.get_bytes().is_err());
+    }
+
     #[test]
     fn test_inserting_in_middle() {
         // Initial: "12 34", cursor: "12 |34" (index 3)
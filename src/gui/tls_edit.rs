@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use eframe::egui;
+
+use udptcp::tls::{TlsClient, TlsServer};
+
+/// None for a blank field
+fn path(text: &str) -> Option<PathBuf> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| PathBuf::from(text))
+}
+
+fn field(ui: &mut egui::Ui, label: &str, text: &mut String, hover: &str) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::TextEdit::singleline(text).desired_width(ui.available_width()))
+            .on_hover_text(hover);
+    });
+}
+
+/// on / off + server name, CA and client certificate for a tls::TlsClient
+///
+/// like the framing, tls applies from the next connect, the caller
+/// pushes tls() to the client before starting it, files are only
+/// read then
+#[derive(Default)]
+pub struct TlsClientEdit {
+    enabled: bool,
+    server_name: String,
+    ca_file: String,
+    cert_file: String,
+    key_file: String,
+    accept_invalid: bool,
+}
+
+impl TlsClientEdit {
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        ui.push_id(id_salt, |ui| {
+            ui.vertical(|ui| {
+                ui.add(super::my_toggle(&mut self.enabled))
+                    .on_hover_text("TLS over the TCP connection");
+                if !self.enabled {
+                    return;
+                }
+                field(
+                    ui,
+                    "Name",
+                    &mut self.server_name,
+                    "server name to send and verify, blank for the server address",
+                );
+                field(
+                    ui,
+                    "CA",
+                    &mut self.ca_file,
+                    "PEM file of the CAs to trust, blank for the public ones",
+                );
+                field(
+                    ui,
+                    "Cert",
+                    &mut self.cert_file,
+                    "PEM file of the client certificate chain, optional",
+                );
+                field(
+                    ui,
+                    "Key",
+                    &mut self.key_file,
+                    "PEM file of the client private key, with Cert",
+                );
+                ui.checkbox(&mut self.accept_invalid, "Accept invalid certs")
+                    .on_hover_text("skip verifying the server certificate, for testing only");
+            });
+        });
+    }
+
+    /// Ok(None) when switched off, Err for a cert without a key or so
+    pub fn tls(&self) -> Result<Option<TlsClient>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let (cert_file, key_file) = (path(&self.cert_file), path(&self.key_file));
+        if cert_file.is_some() != key_file.is_some() {
+            return Err("a client certificate needs both Cert and Key".to_string());
+        }
+        let server_name = self.server_name.trim();
+        Ok(Some(TlsClient {
+            server_name: (!server_name.is_empty()).then(|| server_name.to_string()),
+            ca_file: path(&self.ca_file),
+            cert_file,
+            key_file,
+            accept_invalid: self.accept_invalid,
+        }))
+    }
+}
+
+/// on / off + certificate files for a tls::TlsServer,
+/// both files blank means a generated self signed certificate
+#[derive(Default)]
+pub struct TlsServerEdit {
+    enabled: bool,
+    cert_file: String,
+    key_file: String,
+}
+
+impl TlsServerEdit {
+    pub fn show_ui(&mut self, ui: &mut egui::Ui, id_salt: &str) {
+        ui.push_id(id_salt, |ui| {
+            ui.vertical(|ui| {
+                ui.add(super::my_toggle(&mut self.enabled))
+                    .on_hover_text("TLS for every accepted connection");
+                if !self.enabled {
+                    return;
+                }
+                field(
+                    ui,
+                    "Cert",
+                    &mut self.cert_file,
+                    "PEM file of the certificate chain, blank for a self signed one",
+                );
+                field(
+                    ui,
+                    "Key",
+                    &mut self.key_file,
+                    "PEM file of the private key, blank for a self signed one",
+                );
+            });
+        });
+    }
+
+    /// Ok(None) when switched off, Err for a cert without a key or so
+    pub fn tls(&self) -> Result<Option<TlsServer>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        match (path(&self.cert_file), path(&self.key_file)) {
+            (None, None) => Ok(Some(TlsServer::SelfSigned)),
+            (Some(cert_file), Some(key_file)) => Ok(Some(TlsServer::Pem {
+                cert_file,
+                key_file,
+            })),
+            _ => Err("give both Cert and Key, or neither for a self signed one".to_string()),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+#![warn(missing_docs)]
+//! udp / tcp engine behind the `udptcp` GUI and command line
+//!
+//! every engine owns its sockets and worker threads, the workers
+//! never call back into the caller, they emit events over a channel
+//! which the owner drains with `poll_events()` whenever it likes
+//! (once per GUI frame, or in a loop for headless use)
+//!
+//! sending happens on the caller's thread, or on any other through a
+//! cloned sender ([`UdpSender`], [`TcpClientSender`], [`TcpServerSender`])
+//!
+//! ```no_run
+//! use udptcp::{Udp, UdpEvent};
+//!
+//! let mut udp = Udp::default();
+//! udp.connect_and_start("0.0.0.0:9000".to_string()).unwrap();
+//! udp.send_data_to(b"hello", "192.168.1.20:9000").unwrap();
+//!
+//! loop {
+//!     for ev in udp.poll_events() {
+//!         if let UdpEvent::Packet { src, data, .. } = ev {
+//!             println!("{src}: {data:?}");
+//!         }
+//!     }
+//!     std::thread::sleep(std::time::Duration::from_millis(10));
+//! }
+//! ```
+//!
+//! modules
+//!
+//! - [`udp`] unicast / broadcast / multicast socket, [`Udp`]
+//! - [`tcp`] [`TcpServer`] and [`TcpClient`] with optional auto reconnect
+//! - [`tls`] optional tls (rustls) for both tcp ends
+//! - [`sockopt`] nodelay, keepalive, linger and such for both tcp ends
+//! - [`framing`] how tcp byte streams are cut into messages
+//! - [`network`] local interfaces ([`Netif`]) and address helpers
+//! - [`schedule`] timing for periodic sends
+//! - [`stats`] traffic counters built from the events
+//! - [`conversation`] per peer transcripts of the tcp server
+//! - [`capture`] pcapng files of the traffic, built from the events
+//! - [`replay`] resending the payloads of a pcap / pcapng file
+//! - [`display`] rendering received bytes as text
+//! - [`proxy`] forwarding to a remote with latency, loss and such, [`Proxy`]
+//! - [`relay`] a tcp relay that shows, holds and alters the traffic, [`Relay`]
+//! - [`bridge`] passing udp datagrams to tcp peers and back, [`Bridge`]
+//! - [`echo`] replying to whatever [`Udp`] or [`TcpServer`] receives
+//! - [`xlogger`] a `log` backend that hands formatted lines over a channel
+//!
+//! the engines log through the `log` crate, nothing is shown unless
+//! a logger is installed, eg [`xlogger::Xlogger::init`]
+
+pub mod bridge;
+pub mod capture;
+pub mod conversation;
+pub mod display;
+pub mod echo;
+pub mod framing;
+pub mod network;
+pub mod proxy;
+pub mod relay;
+pub mod replay;
+pub mod schedule;
+pub mod sockopt;
+pub mod stats;
+pub mod tcp;
+pub mod tls;
+pub mod udp;
+pub mod xlogger;
+
+pub use bridge::Bridge;
+pub use capture::Capture;
+pub use conversation::Conversations;
+pub use display::DisplayMode;
+pub use echo::{Echo, Transform};
+pub use framing::Framing;
+pub use network::Netif;
+pub use proxy::{Impairments, Proxy};
+pub use relay::{Relay, RelayEvent};
+pub use replay::{Replay, Timing};
+pub use schedule::{Schedule, Scheduler, Stamp};
+pub use sockopt::{SocketInfo, SocketOptions};
+pub use stats::Stats;
+pub use tcp::{
+    ClientState, Limits, ReconnectPolicy, TcpClient, TcpClientSender, TcpEvent, TcpServer,
+    TcpServerSender,
+};
+pub use tls::{TlsClient, TlsServer};
+pub use udp::{Udp, UdpEvent, UdpSender};
@@ -1,20 +1,37 @@
 // #![allow(unused)]
-use std::collections::HashSet;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use eframe::egui;
 
-mod network;
-use network::Netif;
+use udptcp::bridge::Bridge;
+use udptcp::capture::Capture;
+use udptcp::conversation::Conversations;
+use udptcp::display::DisplayMode;
+use udptcp::network::Netif;
+use udptcp::proxy::{Proxy, ProxyEvent};
+use udptcp::relay::{Relay, RelayEvent};
+use udptcp::replay::Replay;
+use udptcp::schedule::Scheduler;
+use udptcp::stats::Stats;
+use udptcp::xlogger::Xlogger;
+use udptcp::{network, tcp, udp};
+
+mod cli;
 mod gui;
+use gui::Targets;
 
-mod xlogger;
-use xlogger::Xlogger;
+mod sends;
+use sends::{Bridging, Outlets, Worker};
 
-mod tcp;
-mod udp;
+mod settings;
+use settings::{Profiles, SendMode, Settings};
+
+/// the log keeps this many lines, the oldest go first
+/// (a scheduler running overnight would fill the memory otherwise)
+const MAX_LOG_LINES: usize = 100_000;
 
 /// rust egui udp / tcp tester program
 ///
@@ -37,41 +54,118 @@ struct App {
     local_port_udp: String,
     broadcast_ip_udp: String,
     broadcast_ip_manual_udp: String,
+    multicast_group_udp: String,
+    multicast_ttl_udp: String,
+    recv_buffer_udp: String,
     remote_ip_udp: String,
     remote_port_udp: String,
     local_port_tcp_server: String,
     local_port_tcp_client: String,
     remote_ip_tcpserver: String,
     remote_port_tcpserver: String,
+    read_buffer_tcp_server: String,
+    read_buffer_tcp_client: String,
+    reconnect_tcp_client: bool,
+    reconnect_initial_ms: String,
+    reconnect_max_ms: String,
+    reconnect_multiplier: String,
+    reconnect_max_attempts: String,
+    reconnect_jitter: String,
 
     // udp
     udp: udp::Udp,
     udp_bc: bool,
+    udp_mc_loop: bool,
+    udp_mc_send: bool, // send to the multicast group instead of remote
+    udp_view: DisplayMode,
+    udp_echo: gui::EchoEdit,
 
     tcpserver: tcp::TcpServer,
     tcpclient: tcp::TcpClient,
-
-    // since connected clients are in a vec (ordered)
-    // using position (usize) to keep tracking them is easy to do
-    // but with a downside when the collection gets changed
-    // eg [a, b, c] are current connected clients, and selected = 1
-    // when first one `a` gets removed, now `c` is now on position 1
-    // so b is deselected and c is now selected
-    selected_clients: HashSet<SocketAddr>,
+    tcpserver_view: DisplayMode,
+    tcpclient_view: DisplayMode,
+    tcpserver_framing: gui::FramingEdit,
+    tcpclient_framing: gui::FramingEdit,
+    tcpserver_echo: gui::EchoEdit,
+    tcpserver_tls: gui::TlsServerEdit,
+    tcpserver_limits: gui::LimitsEdit,
+    tcpserver_sockopt: gui::SockOptEdit,
+    tcpclient_sockopt: gui::SockOptEdit,
+    tcpclient_tls: gui::TlsClientEdit,
+
+    tcpserver_peers: gui::PeersPanel,
 
     msg: String,
+    msg_hex: gui::HexEdit,
+
+    // the engines' send handles for the workers below, and
+    // the sends that failed on any thread, for the stats
+    outlets: Outlets,
+    send_failures: mpsc::Receiver<sends::Failure>,
+
+    // periodic send, the fields are parsed on start
+    scheduler_panel: gui::SchedulerPanel,
+    scheduler: Option<Worker<Scheduler>>, // kept after it's done for the counters
+
+    // replay of a pcap file, udp messages go to the udp remote
+    // and tcp ones to the tcp client
+    replay_panel: gui::ReplayPanel,
+    replay: Option<Worker<Replay>>,
+
+    // udp <-> tcp bridge, Some while on, tcp messages
+    // go to the udp remote or the last source
+    bridge: Option<Worker<Bridging>>,
+    bridge_panel: gui::BridgePanel,
+
+    stats: Stats,
+    stats_panel: gui::StatsPanel,
+
+    // impairment proxy, listens on the selected netif
+    proxy: Proxy,
+    proxy_panel: gui::ProxyPanel,
+
+    // tcp relay, listens on the selected netif, the byte editor
+    // works on the selected connection and direction
+    relay: Relay,
+    relay_panel: gui::RelayPanel,
+
+    // per peer transcripts of the tcp server, a click on
+    // a connected peer opens its tab
+    conversations: Conversations,
+    conversations_panel: gui::ConversationsPanel,
+
+    // pcapng capture, the path is the typed one, the file the one in use
+    capture: Option<Capture>,
+    capture_path: String,
+    capture_file: String,
+    // the udp socket's address, taken when the capture or the socket
+    // starts, its last events come in after it is gone
+    capture_udp_local: Option<SocketAddr>,
+
+    send_mode: SendMode,
     log: Vec<String>,
     logrx: mpsc::Receiver<String>,
 
     // settings
     dark_mode: bool,
+    profiles: Profiles,
+    profile_selected: String, // name in the dropdown
+    profile_name: String,     // typed name for save / rename
 }
 
 impl App {
-    fn new() -> Self {
+    /// `storage` is where the last session and the profiles
+    /// are restored from, None on platforms without persistence
+    fn new(storage: Option<&dyn eframe::Storage>) -> Self {
         let logrx = Xlogger::init();
         log::info!(">>> starting app {} <<<", chrono::Local::now());
 
+        let udp = udp::Udp::default();
+        let tcpserver = tcp::TcpServer::default();
+        let tcpclient = tcp::TcpClient::default();
+        let (outlets, send_failures) =
+            Outlets::new(udp.sender(), tcpclient.sender(), tcpserver.sender());
+
         let mut app = Self {
             netif_vec: Netif::get_local_netif(),
             netif_selected: 0,
@@ -80,39 +174,157 @@ impl App {
             local_port_udp: String::default(),
             broadcast_ip_udp: String::default(),
             broadcast_ip_manual_udp: String::default(),
+            multicast_group_udp: String::default(),
+            multicast_ttl_udp: "1".to_string(),
+            recv_buffer_udp: udp::MAX_DATAGRAM_SIZE.to_string(),
             remote_ip_udp: String::default(),
             remote_port_udp: String::default(),
             local_port_tcp_server: String::default(),
             local_port_tcp_client: String::default(),
             remote_ip_tcpserver: String::default(),
             remote_port_tcpserver: String::default(),
-
-            udp: udp::Udp::default(),
+            read_buffer_tcp_server: tcp::DEFAULT_READ_BUFFER_SIZE.to_string(),
+            read_buffer_tcp_client: tcp::DEFAULT_READ_BUFFER_SIZE.to_string(),
+            reconnect_tcp_client: false,
+            reconnect_initial_ms: "500".to_string(),
+            reconnect_max_ms: "30000".to_string(),
+            reconnect_multiplier: "2.0".to_string(),
+            reconnect_max_attempts: "0".to_string(),
+            reconnect_jitter: "10".to_string(),
+
+            udp,
             udp_bc: false,
+            udp_mc_loop: true,
+            udp_mc_send: false,
+            udp_view: DisplayMode::default(),
+            udp_echo: gui::EchoEdit::default(),
 
             // tcp_server_mode: false,
-            tcpserver: tcp::TcpServer::default(),
-            selected_clients: HashSet::new(),
-            tcpclient: tcp::TcpClient::default(),
+            tcpserver,
+            tcpserver_peers: gui::PeersPanel::default(),
+            tcpclient,
+            tcpserver_view: DisplayMode::default(),
+            tcpclient_view: DisplayMode::default(),
+            tcpserver_framing: gui::FramingEdit::default(),
+            tcpclient_framing: gui::FramingEdit::default(),
+            tcpserver_echo: gui::EchoEdit::default(),
+            tcpserver_tls: gui::TlsServerEdit::default(),
+            tcpserver_limits: gui::LimitsEdit::default(),
+            tcpserver_sockopt: gui::SockOptEdit::default(),
+            tcpclient_sockopt: gui::SockOptEdit::default(),
+            tcpclient_tls: gui::TlsClientEdit::default(),
 
             msg: String::new(),
+            msg_hex: gui::HexEdit::new(""),
+
+            outlets,
+            send_failures,
+            scheduler_panel: gui::SchedulerPanel::default(),
+            replay_panel: gui::ReplayPanel::default(),
+            replay: None,
+            bridge: None,
+            bridge_panel: gui::BridgePanel::default(),
+            scheduler: None,
+
+            stats: Stats::default(),
+            stats_panel: gui::StatsPanel::default(),
+            proxy: Proxy::default(),
+            proxy_panel: gui::ProxyPanel::default(),
+            relay: Relay::default(),
+            relay_panel: gui::RelayPanel::default(),
+            conversations: Conversations::default(),
+            conversations_panel: gui::ConversationsPanel::default(),
+            capture: None,
+            capture_udp_local: None,
+            capture_path: String::default(),
+            capture_file: String::default(),
+            send_mode: SendMode::Text,
             log: vec![],
             logrx,
 
             // settings
             dark_mode: false,
+            profiles: Profiles::default(),
+            profile_selected: String::default(),
+            profile_name: String::default(),
         };
 
         app.apply_netif_selection(0);
+
+        if let Some(storage) = storage {
+            if let Some(profiles) = eframe::get_value(storage, settings::KEY_PROFILES) {
+                app.profiles = profiles;
+            }
+            if let Some(last) = eframe::get_value::<Settings>(storage, settings::KEY_SETTINGS) {
+                app.apply_settings(&last);
+                log::info!("restored settings of the last session");
+            }
+        }
         app
     }
 
+    /// snapshot of what's worth remembering, see Settings
+    fn settings(&self) -> Settings {
+        let netif = self.netif_vec.get(self.netif_selected);
+        Settings {
+            netif_name: netif.map(|n| n.name.clone()).unwrap_or_default(),
+            netif_ip: netif.map(|n| n.ip_with_scope()).unwrap_or_default(),
+            local_port_udp: self.local_port_udp.clone(),
+            broadcast_ip_manual_udp: self.broadcast_ip_manual_udp.clone(),
+            multicast_group_udp: self.multicast_group_udp.clone(),
+            remote_ip_udp: self.remote_ip_udp.clone(),
+            remote_port_udp: self.remote_port_udp.clone(),
+            udp_bc: self.udp_bc,
+            local_port_tcp_server: self.local_port_tcp_server.clone(),
+            local_port_tcp_client: self.local_port_tcp_client.clone(),
+            remote_ip_tcpserver: self.remote_ip_tcpserver.clone(),
+            remote_port_tcpserver: self.remote_port_tcpserver.clone(),
+            dark_mode: self.dark_mode,
+            send_mode: self.send_mode,
+        }
+    }
+
+    /// the netif goes first because selecting it resets the fields
+    fn apply_settings(&mut self, s: &Settings) {
+        let index = self
+            .netif_vec
+            .iter()
+            .position(|n| n.ip_with_scope() == s.netif_ip)
+            .or_else(|| self.netif_vec.iter().position(|n| n.name == s.netif_name));
+        match index {
+            Some(index) => self.apply_netif_selection(index),
+            None => log::warn!(
+                "saved netif {} ({}) not found, keeping the current one",
+                s.netif_name,
+                s.netif_ip
+            ),
+        }
+
+        self.local_port_udp = s.local_port_udp.clone();
+        self.broadcast_ip_manual_udp = s.broadcast_ip_manual_udp.clone();
+        self.multicast_group_udp = s.multicast_group_udp.clone();
+        self.remote_ip_udp = s.remote_ip_udp.clone();
+        self.remote_port_udp = s.remote_port_udp.clone();
+        // same as the toggle, also applied to a running socket
+        let bc = s.udp_bc && !self.local_has_no_broadcast();
+        match self.udp.toggle_broadcast(bc) {
+            Ok(()) => self.udp_bc = bc,
+            Err(e) => log::error!("failed to set broadcast to {bc}, err = {e}"),
+        }
+        self.local_port_tcp_server = s.local_port_tcp_server.clone();
+        self.local_port_tcp_client = s.local_port_tcp_client.clone();
+        self.remote_ip_tcpserver = s.remote_ip_tcpserver.clone();
+        self.remote_port_tcpserver = s.remote_port_tcpserver.clone();
+        self.dark_mode = s.dark_mode;
+        self.send_mode = s.send_mode;
+    }
+
     /// helper
     fn apply_netif_selection(&mut self, index: usize) {
         if let Some(netif) = self.netif_vec.get(index) {
             self.netif_selected = index;
 
-            self.local_ip = netif.ip.to_string();
+            self.local_ip = netif.ip_with_scope();
             self.local_port_udp = "0".to_string();
             self.broadcast_ip_udp = netif.bc.map(|ip| ip.to_string()).unwrap_or_default();
             self.broadcast_ip_manual_udp = String::default();
@@ -135,15 +347,413 @@ impl App {
         while let Ok(line) = self.logrx.try_recv() {
             self.log.push(line);
         }
+        // dropping a chunk at a time keeps this off every frame
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.drain(..self.log.len() - MAX_LOG_LINES * 9 / 10);
+        }
+    }
+
+    /// local address for the tcp client to bind before connecting
+    /// Some(None) when nothing is pinned (INADDR_ANY + port 0) and
+    /// the OS should choose, None if the typed values are invalid
+    fn tcp_client_local_addr(&self) -> Option<Option<SocketAddr>> {
+        let localsock = network::host_port(&self.local_ip, &self.local_port_tcp_client);
+        match localsock.parse::<SocketAddr>() {
+            Ok(local) if local.ip().is_unspecified() && local.port() == 0 => Some(None),
+            Ok(local) => Some(Some(local)),
+            Err(e) => {
+                log::error!("invalid TCP client local address {localsock}, {e}");
+                None
+            }
+        }
+    }
+
+    /// reconnect settings from the text fields, Ok(None) when disabled
+    fn tcp_client_reconnect_policy(&self) -> Result<Option<tcp::ReconnectPolicy>, String> {
+        if !self.reconnect_tcp_client {
+            return Ok(None);
+        }
+
+        fn parse<T: std::str::FromStr>(name: &str, text: &str) -> Result<T, String>
+        where
+            T::Err: std::fmt::Display,
+        {
+            text.trim()
+                .parse()
+                .map_err(|e| format!("invalid {name} {text:?}, {e}"))
+        }
+
+        Ok(Some(tcp::ReconnectPolicy {
+            initial_delay: Duration::from_millis(parse(
+                "initial delay",
+                &self.reconnect_initial_ms,
+            )?),
+            max_delay: Duration::from_millis(parse("max delay", &self.reconnect_max_ms)?),
+            multiplier: parse("backoff factor", &self.reconnect_multiplier)?,
+            max_attempts: parse("max attempts", &self.reconnect_max_attempts)?,
+            jitter: parse::<f64>("jitter", &self.reconnect_jitter)? / 100.0,
+        }))
+    }
+
+    /// bytes to send from the message box depending on send mode,
+    /// None if there's nothing (valid) to send
+    fn payload(&self) -> Option<Vec<u8>> {
+        match self.send_mode {
+            SendMode::Text => Some(self.msg.as_bytes().to_vec()),
+            SendMode::Hex => match self.msg_hex.get_bytes() {
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    log::error!("invalid hex string {:?}, {e}", self.msg_hex.get_text_raw());
+                    None
+                }
+            },
+        }
+        .filter(|data| !data.is_empty())
+    }
+
+    /// where udp sends go, the remote, the broadcast address or the group
+    fn udp_destination(&self) -> String {
+        let remote_ip = if self.udp_mc_send {
+            self.multicast_group_udp.trim().to_string()
+        } else if !self.udp_bc {
+            self.remote_ip_udp.clone()
+        } else if !self.broadcast_ip_manual_udp.is_empty() {
+            self.broadcast_ip_manual_udp.clone()
+        } else {
+            self.broadcast_ip_udp.clone()
+        };
+        network::host_port(&remote_ip, &self.remote_port_udp)
+    }
+
+    /// returns (ok, failed) sends, see Outlets::send()
+    fn send_to(&self, data: &[u8], targets: Targets) -> (u64, u64) {
+        self.outlets.send(data, targets)
+    }
+
+    /// one tcp server client only, a failure counts in its stats
+    fn send_to_peer(&self, data: &[u8], peer: SocketAddr) -> io::Result<()> {
+        self.outlets.send_to_peer(data, peer)
+    }
+
+    /// the workers send with what the GUI shows now, and their
+    /// failures (the GUI's too) go into the stats
+    fn update_sends(&mut self) {
+        let (peers, all_peers) = self.tcpserver_peers.selection();
+        self.outlets.set_route(sends::Route {
+            udp: self.udp_destination(),
+            peers: peers.clone(),
+            all_peers,
+        });
+        for (leg, at) in self.send_failures.try_iter() {
+            match leg {
+                sends::Leg::Udp => self.stats.udp.add_error(at),
+                sends::Leg::TcpClient => self.stats.tcp_client.add_error(at),
+                sends::Leg::Peer(peer) => self
+                    .stats
+                    .tcp_server_peers
+                    .entry(peer)
+                    .or_default()
+                    .add_error(at),
+            }
+        }
+    }
+
+    /// parses the scheduler fields, the payload is taken as it is now
+    fn start_scheduler(&mut self) -> Result<(), String> {
+        let schedule = self.scheduler_panel.schedule()?;
+        let payload = self.payload().ok_or("nothing to send")?;
+        let interval = schedule.interval.as_millis();
+        let scheduler = Scheduler::new(schedule, Instant::now())?;
+        // the route the first sends go by is the current one
+        self.update_sends();
+        self.scheduler = Some(sends::schedule(
+            scheduler,
+            payload,
+            self.scheduler_panel.targets,
+            self.outlets.clone(),
+        ));
+        log::info!("scheduler started, every {interval} ms");
+        Ok(())
+    }
+
+    /// starts the bridge, or stops it and removes the engine taps
+    fn toggle_bridge(&mut self, udp_remote: &str) {
+        match self.bridge.take() {
+            Some(worker) => {
+                let b = &worker.lock().bridge;
+                log::info!(
+                    "bridge stopped, {} to TCP, {} to UDP, {} failed",
+                    b.to_tcp,
+                    b.to_udp,
+                    b.failed
+                );
+                self.udp.set_tap(None);
+                self.tcpclient.set_tap(None);
+                self.tcpserver.set_tap(None);
+            }
+            None => {
+                let bridge = Bridge::new(self.bridge_panel.target(udp_remote));
+                log::info!("bridge started, TCP messages go to {}", bridge.target);
+                // the route the first datagrams go by is the current one
+                self.update_sends();
+                let bridging = Bridging {
+                    bridge,
+                    targets: self.bridge_panel.targets,
+                };
+                self.bridge = Some(sends::bridge(
+                    bridging,
+                    &self.udp,
+                    &self.tcpclient,
+                    &self.tcpserver,
+                    self.outlets.clone(),
+                ));
+            }
+        }
+    }
+
+    /// the udp worker no longer logs by itself, so whatever
+    /// it received is turned into log lines here
+    fn update_udp_events(&mut self) {
+        for ev in self.udp.poll_events() {
+            self.stats.on_udp(&ev);
+            if let Some(local) = self.capture_udp_local {
+                self.write_capture(|c| c.write_udp(local, &ev));
+            }
+            match ev {
+                udp::UdpEvent::Packet {
+                    src,
+                    data,
+                    truncated,
+                    ..
+                } => {
+                    let msg = self.udp_view.render(&data);
+                    match truncated {
+                        Some(len) => log::warn!(
+                            "[UDP RECV] {} from {} [TRUNCATED {} of {} bytes]",
+                            msg,
+                            src,
+                            data.len(),
+                            len
+                        ),
+                        None => log::info!("[UDP RECV] {} from {}", msg, src),
+                    }
+                }
+                // already logged by the engine
+                udp::UdpEvent::Sent { .. } => {}
+                udp::UdpEvent::Error(e) => log::error!("receiving error: {e}"),
+                udp::UdpEvent::Stopped => log::debug!("UDP worker stopped"),
+            }
+        }
+    }
+
+    /// same as update_udp_events, for both tcp ends
+    fn update_tcp_events(&mut self) {
+        let server_events = if self.tcpserver.is_up() {
+            // todo optional
+            // this part is suggested by gpt but in my testing
+            // the app works fine without the block, so disable for now
+            // also not verified with the code block enabled
+            // let current: HashSet<SocketAddr> = self
+            //     .tcpserver
+            //     .clients
+            //     .iter()
+            //     .filter_map(|s| s.peer_addr().ok())
+            //     .collect();
+            // self.selected_clients.retain(|a| current.contains(a));
+            self.tcpserver.poll_events()
+        } else {
+            vec![]
+        };
+        let (server_view, client_view) = (self.tcpserver_view, self.tcpclient_view);
+        for ev in &server_events {
+            self.stats.on_tcp_server(ev);
+            self.conversations.on_tcp_server(ev);
+            self.write_capture(|c| c.write_tcp(ev));
+        }
+        // after the events, the last words of a closed peer go in first
+        self.conversations
+            .sync(&self.tcpserver.peers(), chrono::Local::now());
+        let server_events = server_events.into_iter().map(|ev| (ev, server_view));
+        let connecting = self.tcpclient.state() == tcp::ClientState::Connecting;
+        let client_events = self.tcpclient.poll_events();
+        if connecting
+            && self.tcpclient.state() == tcp::ClientState::Connected
+            && let Some(local) = self.tcpclient.local_addr()
+        {
+            // the netif selection is kept as is, only the
+            // port is updated in case it was ephemeral
+            self.local_port_tcp_client = local.port().to_string();
+        }
+        for ev in &client_events {
+            self.stats.on_tcp_client(ev);
+            self.write_capture(|c| c.write_tcp(ev));
+        }
+        let client_events = client_events.into_iter().map(|ev| (ev, client_view));
+
+        for (ev, view) in server_events.chain(client_events) {
+            match ev {
+                tcp::TcpEvent::Packet { peer, data, .. } => {
+                    log::info!("[TCP RECV] {} from {}", view.render(&data), peer);
+                }
+                // already logged by the engine
+                tcp::TcpEvent::Sent { .. } => {}
+            }
+        }
+    }
+
+    /// logs both directions of every relayed connection
+    fn update_relay_events(&mut self) {
+        for ev in self.relay.poll_events() {
+            for tcp in ev.tcp_events() {
+                self.write_capture(|c| c.write_tcp(&tcp));
+            }
+            match ev {
+                RelayEvent::Opened(conn) => {
+                    self.relay_panel.selected.get_or_insert(conn.id);
+                }
+                RelayEvent::Data {
+                    conn,
+                    dir,
+                    data,
+                    held,
+                    dropped,
+                    ..
+                } => {
+                    let held = match (held, dropped) {
+                        (false, _) => String::new(),
+                        (true, 0) => " (held)".to_string(),
+                        (true, n) => format!(" (held, last {n} bytes dropped)"),
+                    };
+                    log::info!(
+                        "[RELAY #{} {dir}] {}{held}",
+                        conn.id,
+                        self.relay_panel.view.render(&data)
+                    );
+                }
+                // already logged by the engine
+                RelayEvent::Injected { .. } | RelayEvent::Closed { .. } => {}
+            }
+        }
+    }
+
+    /// the proxy logs by itself, its traffic only goes to the capture
+    fn update_proxy_events(&mut self) {
+        for ev in self.proxy.poll_events() {
+            match ev {
+                ProxyEvent::Udp { local, ev } => self.write_capture(|c| c.write_udp(local, &ev)),
+                ProxyEvent::Tcp(ev) => self.write_capture(|c| c.write_tcp(&ev)),
+            }
+        }
+    }
+
+    /// an empty path gets a name with the current time, in the working directory
+    fn start_capture(&mut self) {
+        let path = match self.capture_path.trim() {
+            "" => chrono::Local::now()
+                .format("capture_%Y%m%d_%H%M%S.pcapng")
+                .to_string(),
+            path => path.to_string(),
+        };
+        match Capture::create(&path) {
+            Ok(capture) => {
+                self.capture_file = std::path::absolute(&path)
+                    .map(|p| p.display().to_string())
+                    .unwrap_or(path);
+                log::info!("capture started, writing to {}", self.capture_file);
+                self.capture_udp_local = self.udp.local_addr();
+                self.capture = Some(capture);
+            }
+            Err(e) => log::error!("cannot create capture file {path:?}, {e}"),
+        }
+    }
+
+    fn stop_capture(&mut self) {
+        if let Some(capture) = self.capture.take() {
+            let packets = capture.packets();
+            match capture.finish() {
+                Ok(_) => log::info!(
+                    "capture stopped, {packets} packets in {}",
+                    self.capture_file
+                ),
+                Err(e) => log::error!("cannot finish capture {}, {e}", self.capture_file),
+            }
+        }
+    }
+
+    /// a write error ends the capture, the file keeps what got in
+    fn write_capture(&mut self, write: impl FnOnce(&mut Capture) -> std::io::Result<()>) {
+        if let Some(capture) = self.capture.as_mut()
+            && let Err(e) = write(capture)
+        {
+            log::error!("capture to {} failed, {e}", self.capture_file);
+            self.capture = None;
+        }
+    }
+
+    /// parse a typed buffer size and hand it to the setter,
+    /// the text is reverted to the current value on failure
+    fn apply_buffer_size(
+        text: &mut String,
+        current: usize,
+        set: impl FnOnce(usize) -> std::io::Result<()>,
+    ) {
+        let result = text
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+            .and_then(|size| set(size).map(|_| size));
+        match result {
+            Ok(size) => log::info!("receive buffer set to {size} bytes"),
+            Err(e) => {
+                log::error!("invalid buffer size {:?}, {e}", text);
+                *text = current.to_string();
+            }
+        }
+    }
+
+    /// join or leave the typed multicast group on the selected netif
+    fn toggle_multicast_group(&mut self) {
+        let group = match self.multicast_group_udp.trim().parse::<Ipv4Addr>() {
+            Ok(group) => group,
+            Err(e) => {
+                log::error!(
+                    "invalid multicast group {:?}, {e}",
+                    self.multicast_group_udp
+                );
+                return;
+            }
+        };
+        let iface = match network::parse_ip(&self.local_ip) {
+            Some(IpAddr::V4(ip)) => ip,
+            _ => Ipv4Addr::UNSPECIFIED,
+        };
+
+        let result = if self.udp.multicast_groups().contains(&(group, iface)) {
+            self.udp.leave_multicast(group, iface)
+        } else {
+            self.udp.join_multicast(group, iface)
+        };
+        if let Err(e) = result {
+            log::error!("multicast group {group} on {iface} failed, {e}");
+        }
+    }
+
+    fn is_multicast_group_joined(&self) -> bool {
+        let Ok(group) = self.multicast_group_udp.trim().parse::<Ipv4Addr>() else {
+            return false;
+        };
+        self.udp.multicast_groups().iter().any(|(g, _)| *g == group)
     }
 
     /// used by broadcast toggle
     /// for loopback and unspecific nics there are no bc feature
-    fn local_is_loopback_or_unspecified(&self) -> bool {
-        self.local_ip
-            .parse::<Ipv4Addr>()
-            .map(|ip| ip.is_loopback() || ip.is_unspecified())
-            .unwrap_or(false)
+    /// and IPv6 has no broadcast at all
+    fn local_has_no_broadcast(&self) -> bool {
+        match network::parse_ip(&self.local_ip) {
+            Some(IpAddr::V4(ip)) => ip.is_loopback() || ip.is_unspecified(),
+            Some(IpAddr::V6(_)) => true,
+            None => false,
+        }
     }
 
     // optional
@@ -165,7 +775,7 @@ impl App {
                     ui.label("UDP broadcast");
                     if ui
                         .add_enabled(
-                            !self.local_is_loopback_or_unspecified(),
+                            !self.local_has_no_broadcast(),
                             gui::my_toggle(&mut self.udp_bc),
                         )
                         .clicked()
@@ -205,14 +815,14 @@ impl App {
 
                     ui.separator();
 
-                    if ui.button("test").clicked() {
-                        if let Some(viewport) = ctx.input(|i| i.viewport().outer_rect) {
-                            println!("--- APP INFO ---");
-                            println!("app position: {}", viewport.min); // top-left corner
-                            println!("app size: {}", viewport.size());
-                            println!("app dark mode: {:?}", ctx.style().visuals.dark_mode);
-                            println!("--- END APP INFO ---");
-                        }
+                    if ui.button("test").clicked()
+                        && let Some(viewport) = ctx.input(|i| i.viewport().outer_rect)
+                    {
+                        println!("--- APP INFO ---");
+                        println!("app position: {}", viewport.min); // top-left corner
+                        println!("app size: {}", viewport.size());
+                        println!("app dark mode: {:?}", ctx.style().visuals.dark_mode);
+                        println!("--- END APP INFO ---");
                     }
                 });
 
@@ -220,6 +830,137 @@ impl App {
             });
     }
 
+    /// file name, start / stop and the packet count,
+    /// laid out right to left
+    fn render_capture(&mut self, ui: &mut egui::Ui) {
+        match self.capture.as_ref().map(|c| c.packets()) {
+            Some(packets) => {
+                if ui.button("Stop capture").clicked() {
+                    self.stop_capture();
+                }
+                ui.label(format!("{packets} packets"))
+                    .on_hover_text(&self.capture_file);
+            }
+            None => {
+                if ui
+                    .button("Capture")
+                    .on_hover_text(
+                        "write everything sent and received to a pcapng file,\n\
+                         TCP payloads are the messages without their framing",
+                    )
+                    .clicked()
+                {
+                    self.start_capture();
+                }
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.capture_path)
+                        .hint_text("capture_<time>.pcapng")
+                        .desired_width(160.0),
+                );
+            }
+        }
+    }
+
+    /// named profiles + dark mode
+    fn render_profile_panel(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("profiles")
+            .show_separator_line(false)
+            .show(ctx, |ui| {
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.label("Profile");
+                    egui::ComboBox::from_id_salt("combo_profiles")
+                        .selected_text(self.profile_selected.as_str())
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for name in self.profiles.names() {
+                                if ui
+                                    .selectable_label(self.profile_selected == *name, name)
+                                    .clicked()
+                                {
+                                    self.profile_selected = name.clone();
+                                    self.profile_name = name.clone();
+                                }
+                            }
+                        });
+
+                    let has_selected = self.profiles.get(&self.profile_selected).is_some();
+                    if ui
+                        .add_enabled(has_selected, egui::Button::new("Load"))
+                        .clicked()
+                        && let Some(s) = self.profiles.get(&self.profile_selected).cloned()
+                    {
+                        self.apply_settings(&s);
+                        log::info!("profile {:?} loaded", self.profile_selected);
+                    }
+                    if ui
+                        .add_enabled(has_selected, egui::Button::new("Delete"))
+                        .clicked()
+                    {
+                        match self.profiles.delete(&self.profile_selected) {
+                            Ok(()) => {
+                                log::info!("profile {:?} deleted", self.profile_selected);
+                                self.profile_selected.clear();
+                            }
+                            Err(e) => log::error!("{e}"),
+                        }
+                    }
+
+                    ui.separator();
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.profile_name)
+                            .hint_text("profile name")
+                            .desired_width(160.0),
+                    );
+                    if ui
+                        .button("Save")
+                        .on_hover_text("save the current fields under this name")
+                        .clicked()
+                    {
+                        match self.profiles.save(&self.profile_name, self.settings()) {
+                            Ok(()) => {
+                                self.profile_selected = self.profile_name.trim().to_string();
+                                log::info!("profile {:?} saved", self.profile_selected);
+                            }
+                            Err(e) => log::error!("{e}"),
+                        }
+                    }
+                    if ui
+                        .add_enabled(has_selected, egui::Button::new("Rename"))
+                        .on_hover_text("rename the selected profile to this name")
+                        .clicked()
+                    {
+                        match self
+                            .profiles
+                            .rename(&self.profile_selected, &self.profile_name)
+                        {
+                            Ok(()) => {
+                                log::info!(
+                                    "profile {:?} renamed to {:?}",
+                                    self.profile_selected,
+                                    self.profile_name.trim()
+                                );
+                                self.profile_selected = self.profile_name.trim().to_string();
+                            }
+                            Err(e) => log::error!("{e}"),
+                        }
+                    }
+
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.add(gui::my_toggle(&mut self.dark_mode));
+                        ui.label("Dark mode");
+                        ui.separator();
+                        ui.toggle_value(&mut self.stats_panel.open, "Stats");
+                        ui.toggle_value(&mut self.proxy_panel.open, "Proxy");
+                        ui.toggle_value(&mut self.relay_panel.open, "Relay");
+                        ui.toggle_value(&mut self.conversations_panel.open, "Conversations");
+                        ui.separator();
+                        self.render_capture(ui);
+                    });
+                });
+            });
+    }
+
     fn render_local_netif_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("local_netif")
             .default_height(100.0)
@@ -301,15 +1042,17 @@ impl App {
                                 {
                                     // if UDP is not running
                                     if !self.udp.is_up() {
-                                        let localsock =
-                                            format!("{}:{}", self.local_ip, self.local_port_udp);
+                                        let localsock = network::host_port(
+                                            &self.local_ip,
+                                            &self.local_port_udp,
+                                        );
                                         match self.udp.connect_and_start(localsock) {
                                             Ok(port) => {
                                                 self.local_port_udp = port;
+                                                self.capture_udp_local = self.udp.local_addr();
                                             }
                                             Err(e) => {
                                                 log::error!("starting UDP failed: {e}");
-                                                return;
                                             }
                                         }
                                     } else {
@@ -334,20 +1077,19 @@ impl App {
                                     ui.label("Broadcast");
                                     if ui
                                         .add_enabled(
-                                            !self.local_is_loopback_or_unspecified(),
+                                            !self.local_has_no_broadcast(),
                                             gui::my_toggle(&mut self.udp_bc),
                                         )
                                         .clicked()
+                                        && let Err(e) = self.udp.toggle_broadcast(self.udp_bc)
                                     {
-                                        if let Err(e) = self.udp.toggle_broadcast(self.udp_bc) {
-                                            log::error!(
-                                                "failed to set broadcast to {}, err = {e}",
-                                                self.udp_bc
-                                            );
+                                        log::error!(
+                                            "failed to set broadcast to {}, err = {e}",
+                                            self.udp_bc
+                                        );
 
-                                            // revert in case of failure
-                                            self.udp_bc = !self.udp_bc;
-                                        }
+                                        // revert in case of failure
+                                        self.udp_bc = !self.udp_bc;
                                     }
                                     ui.end_row();
 
@@ -361,6 +1103,75 @@ impl App {
                                     );
                                     ui.end_row();
 
+                                    ui.label("Multicast Group")
+                                        .on_hover_text("IPv4 group eg 239.255.0.1\njoined on the selected netif");
+                                    ui.horizontal(|ui| {
+                                        let joined = self.is_multicast_group_joined();
+                                        let btn = if joined { "Leave" } else { "Join" };
+                                        // button goes first to get its space with right to left
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(egui::Align::Center),
+                                            |ui| {
+                                                if ui
+                                                    .add_enabled(
+                                                        self.udp.is_up(),
+                                                        egui::Button::new(btn),
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    self.toggle_multicast_group();
+                                                }
+                                                ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.multicast_group_udp,
+                                                    )
+                                                    .desired_width(ui.available_width()),
+                                                );
+                                            },
+                                        );
+                                    });
+                                    ui.end_row();
+
+                                    ui.label("Multicast TTL / Loop");
+                                    ui.horizontal(|ui| {
+                                        let resp = ui.add(
+                                            egui::TextEdit::singleline(&mut self.multicast_ttl_udp)
+                                                .desired_width(40.0),
+                                        );
+                                        if resp.lost_focus() {
+                                            match self.multicast_ttl_udp.trim().parse::<u32>() {
+                                                Ok(ttl) => {
+                                                    if let Err(e) = self.udp.set_multicast_ttl(ttl) {
+                                                        log::error!("failed to set multicast ttl to {ttl}, {e}");
+                                                    }
+                                                }
+                                                Err(e) => log::error!(
+                                                    "invalid multicast ttl {:?}, {e}",
+                                                    self.multicast_ttl_udp
+                                                ),
+                                            }
+                                        }
+
+                                        if ui
+                                            .add(gui::my_toggle(&mut self.udp_mc_loop))
+                                            .on_hover_text("receive our own multicast traffic")
+                                            .clicked()
+                                            && let Err(e) = self.udp.set_multicast_loop(self.udp_mc_loop)
+                                        {
+                                            log::error!(
+                                                "failed to set multicast loop to {}, err = {e}",
+                                                self.udp_mc_loop
+                                            );
+                                            self.udp_mc_loop = !self.udp_mc_loop;
+                                        }
+                                    });
+                                    ui.end_row();
+
+                                    ui.label("Send to Group")
+                                        .on_hover_text("SEND goes to the multicast group\ninstead of the remote address");
+                                    ui.add(gui::my_toggle(&mut self.udp_mc_send));
+                                    ui.end_row();
+
                                     ui.label("Local Port");
                                     ui.add(
                                         egui::TextEdit::singleline(&mut self.local_port_udp)
@@ -381,6 +1192,39 @@ impl App {
                                             .desired_width(ui.available_width()),
                                     );
                                     ui.end_row();
+
+                                    ui.label("Receive Size")
+                                        .on_hover_text(format!("bytes read per datagram, max {}\n\
+                                            the OS drops the rest of longer ones, they are marked\n\
+                                            as truncated with their real length, echo replies with what was read", udp::MAX_DATAGRAM_SIZE));
+                                    if ui
+                                        .add(
+                                            egui::TextEdit::singleline(&mut self.recv_buffer_udp)
+                                                .desired_width(ui.available_width()),
+                                        )
+                                        .lost_focus()
+                                    {
+                                        Self::apply_buffer_size(
+                                            &mut self.recv_buffer_udp,
+                                            self.udp.recv_buffer_size(),
+                                            |size| self.udp.set_recv_buffer_size(size),
+                                        );
+                                    }
+                                    ui.end_row();
+
+                                    ui.label("Receive View");
+                                    gui::display_mode_combo(ui, "combo_view_udp", &mut self.udp_view);
+                                    ui.end_row();
+
+                                    ui.label("Echo")
+                                        .on_hover_text("reply to every datagram
+transform / delay (ms) / text");
+                                    if self.udp_echo.show_ui(ui, "echo_udp")
+                                        && let Ok(echo) = self.udp_echo.echo()
+                                    {
+                                        self.udp.set_echo(echo);
+                                    }
+                                    ui.end_row();
                                 }); // grid end
                         });
                     });
@@ -412,10 +1256,26 @@ impl App {
                                 {
                                     if self.tcpserver.is_up() {
                                         self.tcpserver.disconnect();
+                                    } else if let Err(e) = self
+                                        .tcpserver_framing
+                                        .framing()
+                                        .and_then(|f| self.tcpserver.set_framing(f))
+                                    {
+                                        log::error!("invalid TCP server framing, {e}");
+                                    } else if let Err(e) = self
+                                        .tcpserver_tls
+                                        .tls()
+                                        .and_then(|t| {
+                                            self.tcpserver
+                                                .set_tls(t.as_ref())
+                                                .map_err(|e| e.to_string())
+                                        })
+                                    {
+                                        log::error!("invalid TCP server TLS settings, {e}");
                                     } else {
-                                        let sockaddr = format!(
-                                            "{}:{}",
-                                            self.local_ip, self.local_port_tcp_server
+                                        let sockaddr = network::host_port(
+                                            &self.local_ip,
+                                            &self.local_port_tcp_server,
                                         );
                                         if let Some(port) = self.tcpserver.begin(sockaddr) {
                                             self.local_port_tcp_server = port;
@@ -438,61 +1298,85 @@ impl App {
                                         .desired_width(ui.available_width()),
                                 );
                                 ui.end_row();
-                            });
 
-                        ui.add_space(4.0);
-                        ui.label("Connected peers...");
-                        ui.separator();
+                                ui.label("Read Buffer");
+                                if ui
+                                    .add(
+                                        egui::TextEdit::singleline(
+                                            &mut self.read_buffer_tcp_server,
+                                        )
+                                        .desired_width(ui.available_width()),
+                                    )
+                                    .lost_focus()
+                                {
+                                    Self::apply_buffer_size(
+                                        &mut self.read_buffer_tcp_server,
+                                        self.tcpserver.read_buffer_size(),
+                                        |size| self.tcpserver.set_read_buffer_size(size),
+                                    );
+                                }
+                                ui.end_row();
 
-                        egui::ScrollArea::vertical()
-                            // .auto_shrink([false, false])
-                            // .auto_shrink([true, true])
-                            // .max_height(20.0)
-                            .max_height(ui.available_height())
-                            .show(ui, |ui| {
-                                // notice in this looping we are modifying the collection
-                                for stream_ref in self.tcpserver.clients.iter() {
-                                    let Ok(peer_addr) = stream_ref.peer_addr() else {
-                                        log::error!(
-                                            "unable to retrive peer address from {:?}, skipped",
-                                            stream_ref
-                                        );
-                                        continue;
-                                    };
-
-                                    // let selected = self.selected_peers.contains(&peer_addr);
-                                    let selected = self.selected_clients.contains(&peer_addr);
-
-                                    let resp = ui.add(egui::SelectableLabel::new(
-                                        selected,
-                                        peer_addr.to_string(),
-                                    ));
-
-                                    // select - diselect
-                                    if resp.clicked() {
-                                        if selected {
-                                            self.selected_clients.remove(&peer_addr);
-                                        } else {
-                                            self.selected_clients.insert(peer_addr);
-                                        }
-                                    }
+                                ui.label("Receive View");
+                                gui::display_mode_combo(
+                                    ui,
+                                    "combo_view_tcpserver",
+                                    &mut self.tcpserver_view,
+                                );
+                                ui.end_row();
 
-                                    // close connection with double click
-                                    if resp.double_clicked() {
-                                        self.selected_clients.remove(&peer_addr);
-                                        self.tcpserver.close_client(peer_addr);
-                                    }
+                                ui.label("Framing")
+                                    .on_hover_text("applies to both directions\nonly editable while stopped");
+                                ui.add_enabled_ui(!self.tcpserver.is_up(), |ui| {
+                                    self.tcpserver_framing.show_ui(ui, "framing_tcpserver");
+                                });
+                                ui.end_row();
 
-                                    // close with right click popup
-                                    resp.context_menu(|ui| {
-                                        if ui.button("Close").clicked() {
-                                            self.selected_clients.remove(&peer_addr);
-                                            self.tcpserver.close_client(peer_addr);
-                                            ui.close_menu(); // ensure the menu closes
-                                        }
-                                    });
+                                ui.label("TLS").on_hover_text("only editable while stopped");
+                                ui.add_enabled_ui(!self.tcpserver.is_up(), |ui| {
+                                    self.tcpserver_tls.show_ui(ui, "tls_tcpserver");
+                                });
+                                ui.end_row();
+
+                                ui.label("Clients")
+                                    .on_hover_text("checked for every new connection, rejects are logged");
+                                if self.tcpserver_limits.show_ui(ui, "limits_tcpserver")
+                                    && let Ok(limits) = self.tcpserver_limits.limits()
+                                {
+                                    self.tcpserver.set_limits(limits);
                                 }
+                                ui.end_row();
+
+                                ui.label("Echo")
+                                    .on_hover_text("reply to every message on the same connection
+transform / delay (ms) / text");
+                                if self.tcpserver_echo.show_ui(ui, "echo_tcpserver")
+                                    && let Ok(echo) = self.tcpserver_echo.echo()
+                                {
+                                    self.tcpserver.set_echo(echo);
+                                }
+                                ui.end_row();
                             });
+
+                        ui.add_space(4.0);
+                        if let Some(opts) =
+                            self.tcpserver_sockopt.show_section(ui, "tcpserver_sockopt", |ui| {
+                                ui.label("applies to every peer, hover a peer for its values");
+                            })
+                            && let Err(e) = self.tcpserver.set_socket_options(opts)
+                        {
+                            log::error!("cannot set the TCP server socket options, {e}");
+                        }
+
+                        if let Some(peer) = self.tcpserver_peers.show_ui(
+                            ui,
+                            &mut self.tcpserver,
+                            &self.conversations,
+                            &mut self.conversations_panel,
+                        ) && let Some(data) = self.payload()
+                        {
+                            let _ = self.send_to_peer(&data, peer); // logged by the server
+                        }
                     });
 
                     cui[2].group(|ui| {
@@ -524,14 +1408,34 @@ impl App {
                                     /***** tcp client code *****/
                                     if self.tcpclient.is_up() {
                                         self.tcpclient.disconnect();
+                                    } else if let Err(e) = self
+                                        .tcpclient_framing
+                                        .framing()
+                                        .and_then(|f| self.tcpclient.set_framing(f))
+                                    {
+                                        log::error!("invalid TCP client framing, {e}");
+                                    } else if let Err(e) = self
+                                        .tcp_client_reconnect_policy()
+                                        .map(|p| self.tcpclient.set_reconnect(p))
+                                    {
+                                        log::error!("invalid TCP client reconnect settings, {e}");
+                                    } else if let Err(e) = self
+                                        .tcpclient_tls
+                                        .tls()
+                                        .and_then(|t| {
+                                            self.tcpclient
+                                                .set_tls(t.as_ref())
+                                                .map_err(|e| e.to_string())
+                                        })
+                                    {
+                                        log::error!("invalid TCP client TLS settings, {e}");
                                     } else {
-                                        let sockaddr = format!(
-                                            "{}:{}",
-                                            self.remote_ip_tcpserver, self.remote_port_tcpserver
+                                        let sockaddr = network::host_port(
+                                            &self.remote_ip_tcpserver,
+                                            &self.remote_port_tcpserver,
                                         );
-                                        if let Some(sock) = self.tcpclient.begin(&sockaddr) {
-                                            self.local_ip = sock.ip().to_string();
-                                            self.local_port_tcp_client = sock.port().to_string();
+                                        if let Some(local) = self.tcp_client_local_addr() {
+                                            self.tcpclient.begin_background(&sockaddr, local);
                                         }
                                     }
                                 }
@@ -564,7 +1468,97 @@ impl App {
                                         .desired_width(ui.available_width()),
                                 );
                                 ui.end_row();
+
+                                ui.label("Read Buffer");
+                                if ui
+                                    .add(
+                                        egui::TextEdit::singleline(
+                                            &mut self.read_buffer_tcp_client,
+                                        )
+                                        .desired_width(ui.available_width()),
+                                    )
+                                    .lost_focus()
+                                {
+                                    Self::apply_buffer_size(
+                                        &mut self.read_buffer_tcp_client,
+                                        self.tcpclient.read_buffer_size(),
+                                        |size| self.tcpclient.set_read_buffer_size(size),
+                                    );
+                                }
+                                ui.end_row();
+
+                                ui.label("Receive View");
+                                gui::display_mode_combo(
+                                    ui,
+                                    "combo_view_tcpclient",
+                                    &mut self.tcpclient_view,
+                                );
+                                ui.end_row();
+
+                                ui.label("Framing")
+                                    .on_hover_text("applies to both directions\nonly editable while stopped");
+                                ui.add_enabled_ui(!self.tcpclient.is_up(), |ui| {
+                                    self.tcpclient_framing.show_ui(ui, "framing_tcpclient");
+                                });
+                                ui.end_row();
+
+                                ui.label("TLS").on_hover_text("only editable while stopped");
+                                ui.add_enabled_ui(!self.tcpclient.is_up(), |ui| {
+                                    self.tcpclient_tls.show_ui(ui, "tls_tcpclient");
+                                });
+                                ui.end_row();
+
+                                ui.label("State");
+                                let state = ui.label(self.tcpclient.state().to_string());
+                                if let Some(info) = self.tcpclient.tls_info() {
+                                    state.on_hover_text(info);
+                                }
+                                ui.end_row();
                             });
+
+                        ui.add_space(4.0);
+                        ui.add_enabled_ui(!self.tcpclient.is_up(), |ui| {
+                            egui::CollapsingHeader::new("Auto Reconnect")
+                                .id_salt("tcpclient_reconnect")
+                                .show(ui, |ui| {
+                                    egui::Grid::new("tcp_reconnect_grid")
+                                        .num_columns(2)
+                                        .spacing([20.0, 4.0])
+                                        .show(ui, |ui| {
+                                            ui.label("Enabled");
+                                            ui.add(gui::my_toggle(&mut self.reconnect_tcp_client));
+                                            ui.end_row();
+
+                                            for (label, hint, text) in [
+                                                ("Initial Delay (ms)", "delay before the first retry", &mut self.reconnect_initial_ms),
+                                                ("Max Delay (ms)", "the delay never grows beyond this", &mut self.reconnect_max_ms),
+                                                ("Backoff Factor", "delay multiplier per retry", &mut self.reconnect_multiplier),
+                                                ("Max Attempts", "0 retries forever", &mut self.reconnect_max_attempts),
+                                                ("Jitter (%)", "random +- part of each delay", &mut self.reconnect_jitter),
+                                            ] {
+                                                ui.label(label).on_hover_text(hint);
+                                                ui.add(
+                                                    egui::TextEdit::singleline(text)
+                                                        .desired_width(ui.available_width()),
+                                                );
+                                                ui.end_row();
+                                            }
+                                        });
+                                });
+                        });
+
+                        let tcpclient = &self.tcpclient;
+                        if let Some(opts) =
+                            self.tcpclient_sockopt.show_section(ui, "tcpclient_sockopt", |ui| {
+                                // read back, what the os made of them
+                                if let Ok(info) = tcpclient.socket_info() {
+                                    ui.add(egui::Label::new(info.to_string()).wrap());
+                                }
+                            })
+                            && let Err(e) = self.tcpclient.set_socket_options(opts)
+                        {
+                            log::error!("cannot set the TCP client socket options, {e}");
+                        }
                     });
                 });
             });
@@ -572,12 +1566,14 @@ impl App {
 
     // helper method
     fn color_logs(line: &str) -> egui::Color32 {
-        if line.contains("SEND") || line.contains("RECV") {
-            return egui::Color32::from_rgb(65, 105, 225); // blue
+        if line.contains("TRUNCATED") {
+            egui::Color32::from_rgb(230, 140, 0) // orange
+        } else if line.contains("SEND") || line.contains("RECV") || line.contains("ECHO") {
+            egui::Color32::from_rgb(65, 105, 225) // blue
         } else if line.contains("ERR") {
-            return egui::Color32::LIGHT_RED;
+            egui::Color32::LIGHT_RED
         } else {
-            return egui::Color32::DARK_GRAY;
+            egui::Color32::DARK_GRAY
         }
     }
 
@@ -594,7 +1590,7 @@ impl App {
                     .hscroll(true)
                     .show(ui, |ui| {
                         for line in self.log.iter() {
-                            let color = Self::color_logs(&line);
+                            let color = Self::color_logs(line);
 
                             // extend() to avoid soft line wrapping
                             ui.add(
@@ -661,6 +1657,7 @@ impl App {
 
     fn render(&mut self, ctx: &egui::Context) {
         // self.render_toolbar(ctx);
+        self.render_profile_panel(ctx);
         self.render_local_netif_panel(ctx);
 
         self.render_udp_and_tcp_panel(ctx);
@@ -672,58 +1669,62 @@ impl App {
             .show(ctx, |ui| {
                 ui.add_space(5.0);
                 ui.vertical_centered_justified(|ui| {
-                    ui.text_edit_singleline(&mut self.msg);
-                    ui.add_space(5.0);
-                    if ui.button("SEND").clicked() {
-                        if self.msg.is_empty() {
-                            return;
-                        }
-
-                        /*** UDP send handling ***/
-                        if self.udp.is_up() {
-                            let remote_ip = if !self.udp_bc {
-                                self.remote_ip_udp.clone()
-                            } else {
-                                if !self.broadcast_ip_manual_udp.is_empty() {
-                                    self.broadcast_ip_manual_udp.clone()
-                                } else {
-                                    self.broadcast_ip_udp.clone()
-                                }
-                            };
-                            let remote_sockaddr = format!("{}:{}", remote_ip, self.remote_port_udp);
-                            self.udp.send_data_to(&self.msg, &remote_sockaddr)
-                        }
-
-                        /* TCP client send handling */
-                        if self.tcpclient.is_up() {
-                            self.tcpclient.send_data(&self.msg);
-                        }
-
-                        /* TCP host send handling */
-                        if self.tcpserver.is_up() {
-                            // if self.selected_peers_new.is_empty() {
-                            //     log::error!("no destination peer selected for TcpServer to send");
-                            //     return;
-                            // }
-                            for stream in self.tcpserver.clients.iter() {
-                                let Ok(peer_addr) = stream.peer_addr() else {
-                                    log::error!(
-                                        "unable to retrive peer address from {:?} when sending, skipped",
-                                        stream
-                                    );
-                                    continue;
-                                };
-                                if self.selected_clients.contains(&peer_addr) {
-                                    // extract the mutable stream
-                                    self.tcpserver.send_data(&self.msg, stream);
-                                }
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.send_mode, SendMode::Text, "Text");
+                        ui.selectable_value(&mut self.send_mode, SendMode::Hex, "Hex");
+                        ui.separator();
+                        match self.send_mode {
+                            SendMode::Text => {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.msg)
+                                        .desired_width(ui.available_width()),
+                                );
                             }
+                            SendMode::Hex => self.msg_hex.show_ui(ui),
                         }
+                    });
+                    ui.add_space(5.0);
+                    if ui.button("SEND").clicked()
+                        && let Some(data) = self.payload()
+                    {
+                        self.send_to(&data, Targets::ALL);
                     };
                 });
                 ui.add_space(5.0);
+                if self.scheduler_panel.show_ui(ui, self.scheduler.as_ref())
+                    && let Err(e) = self.start_scheduler()
+                {
+                    log::error!("cannot start the scheduler, {e}");
+                }
+                if self.replay_panel.show_ui(ui, self.replay.as_ref()) {
+                    match self.replay_panel.replay(Instant::now()) {
+                        Ok(replay) => {
+                            self.update_sends();
+                            self.replay = Some(sends::replay(replay, self.outlets.clone()));
+                        }
+                        Err(e) => log::error!("cannot start the replay, {e}"),
+                    }
+                }
+                let udp_remote = self.udp_destination();
+                if self
+                    .bridge_panel
+                    .show_ui(ui, self.bridge.as_ref(), &udp_remote)
+                {
+                    self.toggle_bridge(&udp_remote);
+                }
+                ui.add_space(5.0);
             });
 
+        self.stats_panel.show(ctx, &mut self.stats, &self.tcpserver);
+        self.proxy_panel.show(ctx, &mut self.proxy, &self.local_ip);
+        self.relay_panel.show(ctx, &mut self.relay, &self.local_ip);
+        if let Some((peer, bytes)) =
+            self.conversations_panel
+                .show(ctx, &mut self.conversations, self.tcpserver_view)
+        {
+            let _ = self.send_to_peer(&bytes, peer); // logged by the server
+        }
+
         // log panel
         self.render_log_panel(ctx);
     }
@@ -734,26 +1735,16 @@ impl eframe::App for App {
         // drive a periodic repaint
         // with this periodic repaint, we dont need the manual
         // repaint inside tcp or udp anymore
-        if self.udp.is_up() || self.is_tcp_running() {
+        if self.udp.is_up() || self.is_tcp_running() || self.proxy.is_up() || self.relay.is_up() {
             ctx.request_repaint_after(Duration::from_millis(50)); // 20fps
         }
 
-        if self.tcpserver.is_up() {
-            self.tcpserver.poll_events();
-
-            // todo optional
-            // this part is suggested by gpt but in my testing
-            // the app works fine without the block, so disable for now
-            // also not verified with the code block enabled
-            // let current: HashSet<SocketAddr> = self
-            //     .tcpserver
-            //     .clients
-            //     .iter()
-            //     .filter_map(|s| s.peer_addr().ok())
-            //     .collect();
-            // self.selected_clients.retain(|a| current.contains(a));
-        }
-
+        self.update_udp_events();
+        self.update_tcp_events();
+        self.update_relay_events();
+        self.update_proxy_events();
+        self.write_capture(|c| c.flush());
+        self.update_sends();
         self.update_logs();
 
         // mode
@@ -765,6 +1756,12 @@ impl eframe::App for App {
 
         self.render(ctx);
     }
+
+    /// called by eframe every now and then and on exit
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, settings::KEY_SETTINGS, &self.settings());
+        eframe::set_value(storage, settings::KEY_PROFILES, &self.profiles);
+    }
 }
 
 /// patch
@@ -860,6 +1857,12 @@ fn install_cjk_fallback(ctx: &egui::Context) {
 }
 
 fn main() -> eframe::Result<()> {
+    // any argument means headless, see cli::USAGE
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::main(&args));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size((1114.0, 588.0))
@@ -873,7 +1876,7 @@ fn main() -> eframe::Result<()> {
             // Install system CJK fallback (YaHei on Windows, PingFang on macOS, etc.)
             install_cjk_fallback(&cc.egui_ctx);
 
-            Ok(Box::new(App::new()))
+            Ok(Box::new(App::new(cc.storage)))
         }),
     )
 }
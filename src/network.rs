@@ -3,7 +3,8 @@
 
 use get_if_addrs::{IfAddr, get_if_addrs};
 use std::fmt;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::str::FromStr;
 
 /*
    some notes
@@ -14,41 +15,93 @@ use std::net::Ipv4Addr;
             - can be binded as local address
             - means to accept all traffic
             - cannot be used as remote
+
+       Ipv6Addr::UNSPECIFIED == ::
+            - same idea as INADDR_ANY (`IN6ADDR_ANY`)
+            - on most OS a socket bound here also accepts
+              v4 traffic (dual stack) unless IPV6_V6ONLY is set
+
+       IPv6 link-local (fe80::/10)
+            - the same address can exist on every netif, so
+              the interface index (scope id) has to go along
+              with it, written as fe80::1%3
 */
 
+/// one address of a local network interface
+///
+/// an interface with several addresses shows up once per address
 #[derive(Debug)]
 pub struct Netif {
+    /// interface name as the OS reports it, eg `eth0`
     pub name: String,
-    pub ip: Ipv4Addr,
+    /// the address, or the unspecified address for "all interfaces"
+    pub ip: IpAddr,
+    /// broadcast address of the subnet, v4 only
     pub bc: Option<Ipv4Addr>,
+
+    /// interface index, only meaningful for IPv6 link-local
+    /// addresses where it is needed as the scope id, 0 otherwise
+    pub scope_id: u32,
 }
 
 impl fmt::Display for Netif {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} - {} ( broadcast address = {:?} )",
-            self.ip, self.name, self.bc
-        )
+        match self.ip {
+            IpAddr::V4(_) => write!(
+                f,
+                "{} - {} ( broadcast address = {:?} )",
+                self.ip, self.name, self.bc
+            ),
+            IpAddr::V6(_) => write!(f, "{} - {} ( IPv6 )", self.ip_with_scope(), self.name),
+        }
     }
 }
 
 impl Netif {
+    /// the ip as it should be typed into a socket address,
+    /// with the `%scope` suffix for IPv6 link-local
+    pub fn ip_with_scope(&self) -> String {
+        match self.ip {
+            IpAddr::V6(ip) if ip.is_unicast_link_local() && self.scope_id != 0 => {
+                format!("{}%{}", ip, self.scope_id)
+            }
+            _ => self.ip.to_string(),
+        }
+    }
+
     /// generate a ip address prefix for neighbours
     /// on the network, & operation with mask is just
     /// too much over head, we will return the first
     /// octets for now
+    ///
+    /// for IPv6 the first 4 segments (the usual /64) are returned
     pub fn remote_ip_template(&self) -> String {
-        if self.ip.is_loopback() {
-            return self.ip.to_string();
-        }
+        match self.ip {
+            IpAddr::V4(ip) => {
+                if ip.is_loopback() {
+                    return ip.to_string();
+                }
 
-        if self.ip.is_unspecified() {
-            return Ipv4Addr::LOCALHOST.to_string();
-        }
+                if ip.is_unspecified() {
+                    return Ipv4Addr::LOCALHOST.to_string();
+                }
+
+                let octs = ip.octets();
+                format!("{}.{}.{}.", octs[0], octs[1], octs[2])
+            }
+            IpAddr::V6(ip) => {
+                if ip.is_loopback() || ip.is_unspecified() {
+                    return Ipv6Addr::LOCALHOST.to_string();
+                }
+
+                if ip.is_unicast_link_local() {
+                    return "fe80::".to_string();
+                }
 
-        let octs = self.ip.octets();
-        format!("{}.{}.{}.", octs[0], octs[1], octs[2])
+                let segs = ip.segments();
+                format!("{:x}:{:x}:{:x}:{:x}:", segs[0], segs[1], segs[2], segs[3])
+            }
+        }
     }
 
     /// netdev approach
@@ -60,43 +113,64 @@ impl Netif {
     /// localhost is recogonized as if_physical == false
     pub fn get_local_netif() -> Vec<Netif> {
         let mut res = vec![];
+        let mut res_v6 = vec![];
         let ifaces = netdev::get_interfaces();
 
         for iface in ifaces {
-            if !iface.ipv4.is_empty() {
-                let ipv4 = iface.ipv4[0];
+            if !iface.is_up() {
+                continue;
+            }
+
+            // test code
+            // dbg!(&iface.name);  // works for macos
+            // dbg!(&iface.friendly_name); // works for windows but it is an option
+            let name = {
+                #[cfg(target_os = "windows")]
+                {
+                    match iface.friendly_name.clone() {
+                        Some(n) => n,
+                        None => iface.name.clone(),
+                    }
+                }
+
+                #[cfg(not(target_os = "windows"))]
+                {
+                    iface.name.clone()
+                }
+            };
 
+            if let Some(ipv4) = iface.ipv4.first() {
                 // this is to filter utun4 on mac which is considered not physical
                 // notice localhost is also not physical which we'd like to keep
-                if !ipv4.addr.is_loopback() && !iface.is_physical() || !iface.is_up() {
-                    continue;
+                if ipv4.addr.is_loopback() || iface.is_physical() {
+                    res.push(Netif {
+                        name: name.clone(),
+                        ip: IpAddr::V4(ipv4.addr),
+                        bc: if ipv4.addr.is_loopback() {
+                            None
+                        } else {
+                            Some(ipv4.broadcast())
+                        },
+                        scope_id: 0,
+                    });
                 }
+            }
 
-                // test code
-                // dbg!(&iface.name);  // works for macos
-                // dbg!(&iface.friendly_name); // works for windows but it is an option
-                let name = {
-                    #[cfg(target_os = "windows")]
-                    {
-                        match iface.friendly_name {
-                            Some(n) => n,
-                            None => iface.name,
-                        }
-                    }
-
-                    #[cfg(not(target_os = "windows"))]
-                    {
-                        iface.name
-                    }
-                };
+            // unlike v4 an interface usually has several v6 addresses
+            // (link-local + global + temporary), list them all
+            for ipv6 in iface.ipv6.iter() {
+                if !ipv6.addr.is_loopback() && !iface.is_physical() {
+                    continue;
+                }
 
-                res.push(Netif {
-                    name,
-                    ip: ipv4.addr,
-                    bc: if ipv4.addr.is_loopback() {
-                        None
+                res_v6.push(Netif {
+                    name: name.clone(),
+                    ip: IpAddr::V6(ipv6.addr),
+                    bc: None,
+                    scope_id: if ipv6.addr.is_unicast_link_local() {
+                        iface.index
                     } else {
-                        Some(ipv4.broadcast())
+                        0
                     },
                 });
             }
@@ -105,8 +179,18 @@ impl Netif {
         // manually insert the INADDR_ANY (0.0.0.0)
         res.push(Netif {
             name: "INADDR_ANY".to_string(),
-            ip: Ipv4Addr::UNSPECIFIED,
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            bc: None,
+            scope_id: 0,
+        });
+
+        // v6 goes after v4 so that the default selection stays v4
+        res.extend(res_v6);
+        res.push(Netif {
+            name: "IN6ADDR_ANY".to_string(),
+            ip: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
             bc: None,
+            scope_id: 0,
         });
         res
     }
@@ -132,8 +216,9 @@ impl Netif {
 
                     Some(Netif {
                         name: iface_name,
-                        ip: ipv4_addr.ip,
+                        ip: IpAddr::V4(ipv4_addr.ip),
                         bc: ipv4_addr.broadcast,
+                        scope_id: 0,
                     })
                 } else {
                     None
@@ -187,8 +272,9 @@ impl Netif {
 
                     let netif = Netif {
                         name: iface.name,
-                        ip: ipv4net.addr,
+                        ip: IpAddr::V4(ipv4net.addr),
                         bc,
+                        scope_id: 0,
                     };
                     res.push(netif);
                 }
@@ -198,6 +284,105 @@ impl Netif {
     }
 }
 
+/// join a host and a port into something ToSocketAddrs accepts,
+/// IPv6 literals need brackets, eg [fe80::1%3]:8080
+/// a plain format!("{}:{}") would break them
+pub fn host_port(host: &str, port: &str) -> String {
+    let host = host.trim();
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port.trim())
+    } else {
+        format!("{}:{}", host, port.trim())
+    }
+}
+
+/// the address the OS would send from to reach `peer`, found with
+/// a connected udp socket, nothing is sent, None without a route
+pub fn source_ip_for(peer: IpAddr) -> Option<IpAddr> {
+    let peer = peer.to_canonical();
+    let any = match peer {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((any, 0)).ok()?;
+    // or connecting to a broadcast address is refused
+    socket.set_broadcast(peer.is_ipv4()).ok()?;
+    socket.connect((peer, 9)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+/// the ip part of a typed address, without the `%scope` suffix
+pub fn parse_ip(host: &str) -> Option<IpAddr> {
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+    let host = host.split('%').next().unwrap_or_default();
+    host.parse().ok()
+}
+
+/// an ip network like 192.168.1.0/24, a plain ip is a /32 (or /128)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    /// the network address, host bits are ignored
+    pub ip: IpAddr,
+    /// leading bits that have to match
+    pub prefix: u8,
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.ip, self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = match s.trim().split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let ip = parse_ip(ip).ok_or_else(|| format!("invalid ip in {s:?}"))?;
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix in {s:?}, 0..={max}"))?,
+            None => max,
+        };
+        Ok(Cidr { ip, prefix })
+    }
+}
+
+impl Cidr {
+    /// a list separated by commas or spaces, blank is an empty list
+    pub fn parse_list(text: &str) -> Result<Vec<Cidr>, String> {
+        text.split([',', ' '])
+            .filter(|s| !s.trim().is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    /// ipv4 mapped ipv6 addresses (from a dual stack listener)
+    /// are taken as the ipv4 they carry
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.ip, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -207,4 +392,78 @@ mod tests {
     fn test_netif() {
         let _netifs = Netif::get_local_netif();
     }
+
+    #[test]
+    fn test_host_port() {
+        assert_eq!(host_port("127.0.0.1", "80"), "127.0.0.1:80");
+        assert_eq!(host_port("::", "0"), "[::]:0");
+        assert_eq!(host_port("fe80::1%3", "80"), "[fe80::1%3]:80");
+        assert_eq!(host_port("[::1]", "80"), "[::1]:80");
+        assert_eq!(host_port("localhost", "80"), "localhost:80");
+
+        // scope ids survive the round trip through std parsing
+        let addr: std::net::SocketAddr = host_port("fe80::1%3", "80").parse().unwrap();
+        match addr {
+            std::net::SocketAddr::V6(v6) => assert_eq!(v6.scope_id(), 3),
+            _ => panic!("expected v6"),
+        }
+    }
+
+    #[test]
+    fn test_parse_ip() {
+        assert_eq!(
+            parse_ip("10.0.0.1"),
+            Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+        assert_eq!(parse_ip("fe80::1%3"), "fe80::1".parse().ok());
+        assert_eq!(parse_ip("[::1]"), Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(parse_ip("10.0.0."), None);
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "192.168.1.0/24".parse().unwrap();
+        assert!(net.contains("192.168.1.77".parse().unwrap()));
+        assert!(net.contains("::ffff:192.168.1.77".parse().unwrap()));
+        assert!(!net.contains("192.168.2.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let host: Cidr = "10.0.0.5".parse().unwrap();
+        assert_eq!(host.to_string(), "10.0.0.5/32");
+        assert!(host.contains("10.0.0.5".parse().unwrap()));
+        assert!(!host.contains("10.0.0.6".parse().unwrap()));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+        let v6: Cidr = "fe80::/10".parse().unwrap();
+        assert!(v6.contains("fe80::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert_eq!(
+            Cidr::parse_list(" 10.0.0.1, 10.1.0.0/16 ").unwrap().len(),
+            2
+        );
+        assert_eq!(Cidr::parse_list(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_remote_ip_template_v6() {
+        let netif = Netif {
+            name: "en0".to_string(),
+            ip: "2001:db8:1:2::10".parse().unwrap(),
+            bc: None,
+            scope_id: 0,
+        };
+        assert_eq!(netif.remote_ip_template(), "2001:db8:1:2:");
+
+        let netif = Netif {
+            name: "en0".to_string(),
+            ip: "fe80::1".parse().unwrap(),
+            bc: None,
+            scope_id: 4,
+        };
+        assert_eq!(netif.ip_with_scope(), "fe80::1%4");
+        assert_eq!(netif.remote_ip_template(), "fe80::");
+    }
 }
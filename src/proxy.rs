@@ -0,0 +1,1163 @@
+//! a proxy that makes the link worse on purpose, see [`Proxy`]
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+    UdpSocket,
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use socket2::SockRef;
+
+use crate::tcp::{TcpEvent, connect_from};
+use crate::udp::UdpEvent;
+
+/*
+    clients talk to the proxy, the proxy talks to the remote
+
+        udp     one upstream socket per client address so that the
+                replies find their way back, with a thread reading
+                the replies, both go after the idle timeout without
+                a datagram either way
+        tcp     one upstream connection per accepted client, made
+                in a thread of its own so that a dead remote does
+                not hold up the other accepts
+
+    udp datagrams of both directions go through a delay line, a
+    thread that sends each one when it is due, so latency and
+    jitter never hold up the receiving
+
+        loss        dropped
+        corrupt     one random bit flipped
+        duplicate   sent twice
+        reorder     held back and sent right after the next datagram
+                    of the same direction (or after REORDER_TIMEOUT)
+        jitter      latency +- a random part of this, reorders too
+
+    tcp has a reading and a writing thread per direction, the reader
+    stamps every read() and the writer sends it at stamp + latency,
+    cut into random pieces of up to max_chunk bytes and no faster
+    than the bandwidth, a reset aborts both legs with a zero linger
+    close so that both ends get a RST
+
+    at most TCP_IN_FLIGHT reads wait for their writer, then the reader
+    stops reading and the sender feels the slow link as a full
+    window, instead of the proxy buffering the whole stream
+
+    impairments can be changed while running, they apply from the
+    next datagram / read()
+
+    every datagram / read() and every datagram / piece sent on is
+    reported as the engines report theirs, so a capture shows the
+    traffic before and after the impairments
+*/
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// reads of up to 16 KiB a tcp writer can be behind its reader
+const TCP_IN_FLIGHT: usize = 64;
+
+/// a held back datagram goes out after this if nothing follows it
+const REORDER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// see Proxy::set_udp_idle_timeout()
+pub const DEFAULT_UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// what the proxy does to the traffic, zero / 0.0 is off,
+/// percentages are 0 to 100
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Impairments {
+    /// udp and tcp, added to every datagram / read()
+    pub latency: Duration,
+
+    /// udp, latency goes up or down by a random part of this
+    pub jitter: Duration,
+
+    /// udp, percent of the datagrams dropped
+    pub loss: f64,
+
+    /// udp, percent of the datagrams sent twice
+    pub duplicate: f64,
+
+    /// udp, percent of the datagrams sent after the next one
+    pub reorder: f64,
+
+    /// udp, percent of the datagrams with a bit flipped
+    pub corrupt: f64,
+
+    /// tcp, bytes per second in each direction of a connection, 0 is unlimited
+    pub bandwidth: u64,
+
+    /// tcp, writes are cut into random pieces of 1 to this many bytes, 0 keeps them
+    pub max_chunk: usize,
+
+    /// tcp, percent chance per piece of resetting the connection
+    pub reset: f64,
+}
+
+impl Impairments {
+    /// every percentage within 0..=100
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, p) in [
+            ("loss", self.loss),
+            ("duplicate", self.duplicate),
+            ("reorder", self.reorder),
+            ("corrupt", self.corrupt),
+            ("reset", self.reset),
+        ] {
+            if !(0.0..=100.0).contains(&p) {
+                return Err(format!("{name} must be within 0 to 100 %, not {p}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// counters since start
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProxyStats {
+    /// datagrams / pieces sent on, duplicates included
+    pub forwarded: u64,
+    /// payload bytes sent on
+    pub bytes: u64,
+    /// udp datagrams lost on purpose
+    pub dropped: u64,
+    /// udp datagrams sent twice
+    pub duplicated: u64,
+    /// udp datagrams held back
+    pub reordered: u64,
+    /// udp datagrams with a flipped bit
+    pub corrupted: u64,
+    /// tcp connections accepted
+    pub connections: u64,
+    /// tcp connections reset on purpose
+    pub resets: u64,
+}
+
+/// what the proxy threads count and report, see Proxy::poll_events()
+#[derive(Default)]
+struct Tally {
+    events: Option<mpsc::Sender<ProxyEvent>>,
+    forwarded: AtomicU64,
+    bytes: AtomicU64,
+    dropped: AtomicU64,
+    duplicated: AtomicU64,
+    reordered: AtomicU64,
+    corrupted: AtomicU64,
+    connections: AtomicU64,
+    resets: AtomicU64,
+}
+
+impl Tally {
+    fn report(&self, ev: ProxyEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(ev);
+        }
+    }
+
+    fn udp_packet(&self, local: SocketAddr, src: SocketAddr, data: &[u8]) {
+        let ev = UdpEvent::Packet {
+            src,
+            data: data.to_vec(),
+            timestamp: chrono::Local::now(),
+            truncated: None,
+        };
+        self.report(ProxyEvent::Udp { local, ev });
+    }
+
+    /// (peer, local) as computed once per leg
+    fn tcp(&self, sent: bool, (peer, local): (SocketAddr, SocketAddr), data: &[u8]) {
+        let (data, timestamp) = (data.to_vec(), chrono::Local::now());
+        self.report(ProxyEvent::Tcp(match sent {
+            true => TcpEvent::Sent {
+                peer,
+                local,
+                data,
+                timestamp,
+            },
+            false => TcpEvent::Packet {
+                peer,
+                local,
+                data,
+                timestamp,
+            },
+        }));
+    }
+}
+
+fn count(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+/// true `percent` % of the time
+fn chance(rng: &mut impl Rng, percent: f64) -> bool {
+    percent > 0.0 && rng.random::<f64>() * 100.0 < percent
+}
+
+fn jittered(imp: &Impairments, rng: &mut impl Rng) -> Duration {
+    if imp.jitter.is_zero() {
+        return imp.latency;
+    }
+    let jitter = imp.jitter.as_secs_f64() * rng.random_range(-1.0..=1.0);
+    Duration::from_secs_f64((imp.latency.as_secs_f64() + jitter).max(0.0))
+}
+
+/// the udp impairments of one direction, `T` is where a datagram goes
+struct Impairer<T> {
+    held: Option<(Instant, T, Vec<u8>)>,
+}
+
+impl<T> Impairer<T> {
+    fn new() -> Self {
+        Impairer { held: None }
+    }
+
+    /// what to send for one datagram, (delay, destination, data) in order
+    fn datagram(
+        &mut self,
+        imp: &Impairments,
+        to: T,
+        mut data: Vec<u8>,
+        now: Instant,
+        rng: &mut impl Rng,
+        tally: &Tally,
+    ) -> Vec<(Duration, T, Vec<u8>)>
+    where
+        T: Clone,
+    {
+        if chance(rng, imp.loss) {
+            count(&tally.dropped, 1);
+            return vec![];
+        }
+        if !data.is_empty() && chance(rng, imp.corrupt) {
+            let bit = rng.random_range(0..data.len() * 8);
+            data[bit / 8] ^= 1 << (bit % 8);
+            count(&tally.corrupted, 1);
+        }
+        if self.held.is_none() && chance(rng, imp.reorder) {
+            count(&tally.reordered, 1);
+            self.held = Some((now, to, data));
+            return vec![];
+        }
+
+        let delay = jittered(imp, rng);
+        let mut out = vec![];
+        if chance(rng, imp.duplicate) {
+            count(&tally.duplicated, 1);
+            out.push((delay, to.clone(), data.clone()));
+        }
+        out.push((delay, to, data));
+        if let Some((_, to, data)) = self.held.take() {
+            out.push((delay, to, data));
+        }
+        out
+    }
+
+    /// the held back datagram once nothing came after it for too long
+    fn expired(
+        &mut self,
+        imp: &Impairments,
+        now: Instant,
+        rng: &mut impl Rng,
+    ) -> Option<(Duration, T, Vec<u8>)> {
+        if self
+            .held
+            .as_ref()
+            .is_some_and(|(t, ..)| now.duration_since(*t) >= REORDER_TIMEOUT)
+        {
+            let (_, to, data) = self.held.take()?;
+            return Some((jittered(imp, rng), to, data));
+        }
+        None
+    }
+}
+
+/// the traffic through the proxy, as the engines report theirs
+#[derive(Debug)]
+pub enum ProxyEvent {
+    /// a datagram received or sent by the proxy socket bound to `local`
+    Udp {
+        /// the proxy's socket, unspecified for the upstream ones
+        local: SocketAddr,
+        /// Packet or Sent
+        ev: UdpEvent,
+    },
+    /// a read() from one leg, or a piece written to the other
+    Tcp(TcpEvent),
+}
+
+/// a datagram waiting in the delay line
+struct Delayed {
+    socket: Arc<UdpSocket>,
+    dst: SocketAddr,
+    data: Vec<u8>,
+}
+
+/// forwards between clients and one remote, impairing the traffic,
+/// see the module notes
+pub struct Proxy {
+    is_running: Arc<AtomicBool>,
+    impairments: Arc<Mutex<Impairments>>,
+    tally: Arc<Tally>,
+    local: Option<SocketAddr>,
+    workers: Vec<JoinHandle<()>>,
+    udp_idle: Duration,
+    event_tx: mpsc::Sender<ProxyEvent>,
+    event_rx: mpsc::Receiver<ProxyEvent>,
+}
+
+/// the upstream side of one udp client
+struct UdpClient {
+    upstream: Arc<UdpSocket>,
+    activity: Arc<Activity>,
+    replies: JoinHandle<()>,
+}
+
+/// shared by a udp client and its replies thread
+struct Activity {
+    // last datagram either way
+    last_seen: Mutex<Instant>,
+    // cleared when the client goes idle or the proxy stops,
+    // ends the replies thread
+    open: AtomicBool,
+}
+
+impl Activity {
+    fn seen(&self, now: Instant) {
+        *self
+            .last_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    fn idle_for(&self, now: Instant) -> Duration {
+        let last = *self
+            .last_seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        now.saturating_duration_since(last)
+    }
+}
+
+impl Default for Proxy {
+    fn default() -> Self {
+        let (event_tx, event_rx) = mpsc::channel();
+        Proxy {
+            is_running: Arc::new(AtomicBool::new(false)),
+            impairments: Arc::new(Mutex::new(Impairments::default())),
+            tally: Arc::new(Tally::default()),
+            local: None,
+            workers: vec![],
+            udp_idle: DEFAULT_UDP_IDLE_TIMEOUT,
+            event_tx,
+            event_rx,
+        }
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Proxy {
+    /// true while forwarding
+    pub fn is_up(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// the listening address, None while stopped
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local.filter(|_| self.is_up())
+    }
+
+    /// applies from the next datagram / read(), refused if a percentage is out of range
+    pub fn set_impairments(&self, imp: Impairments) -> Result<(), String> {
+        imp.validate()?;
+        *self
+            .impairments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = imp;
+        Ok(())
+    }
+
+    /// see set_impairments()
+    pub fn impairments(&self) -> Impairments {
+        self.impairments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// counters since the last start
+    pub fn stats(&self) -> ProxyStats {
+        let t = &self.tally;
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        ProxyStats {
+            forwarded: get(&t.forwarded),
+            bytes: get(&t.bytes),
+            dropped: get(&t.dropped),
+            duplicated: get(&t.duplicated),
+            reordered: get(&t.reordered),
+            corrupted: get(&t.corrupted),
+            connections: get(&t.connections),
+            resets: get(&t.resets),
+        }
+    }
+
+    /// what went through since the last call
+    pub fn poll_events(&self) -> Vec<ProxyEvent> {
+        self.event_rx.try_iter().collect()
+    }
+
+    fn begin(&mut self, local: SocketAddr) {
+        self.stop();
+        self.tally = Arc::new(Tally {
+            events: Some(self.event_tx.clone()),
+            ..Tally::default()
+        });
+        self.local = Some(local);
+        self.is_running.store(true, Ordering::Relaxed);
+    }
+
+    /// a udp client silent this long either way loses its upstream
+    /// socket, applies from the next start_udp()
+    pub fn set_udp_idle_timeout(&mut self, idle: Duration) {
+        self.udp_idle = idle;
+    }
+
+    /// receives datagrams on `listen` (eg "0.0.0.0:9000") and sends
+    /// them to `remote`, returns the address actually bound
+    pub fn start_udp(&mut self, listen: &str, remote: &str) -> io::Result<SocketAddr> {
+        let remote = resolve(remote)?;
+        let socket = Arc::new(UdpSocket::bind(listen)?);
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+        let local = socket.local_addr()?;
+        self.begin(local);
+
+        let (delay_tx, delay_rx) = mpsc::channel();
+        let is_running = self.is_running.clone();
+        let tally = self.tally.clone();
+        self.workers.push(thread::spawn(move || {
+            delay_line(delay_rx, &is_running, &tally);
+        }));
+
+        let is_running = self.is_running.clone();
+        let impairments = self.impairments.clone();
+        let tally = self.tally.clone();
+        let idle = self.udp_idle;
+        self.workers.push(thread::spawn(move || {
+            let mut clients: HashMap<SocketAddr, UdpClient> = HashMap::new();
+            let mut handles = vec![];
+            let mut impairer = Impairer::new();
+            let mut rng = rand::rng();
+            let mut buf = vec![0; u16::MAX as usize];
+
+            while is_running.load(Ordering::Relaxed) {
+                let received = socket.recv_from(&mut buf);
+                let now = Instant::now();
+                let imp = impairments
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone();
+                let mut out = vec![];
+                match received {
+                    Ok((n, client)) => {
+                        tally.udp_packet(local, client, &buf[..n]);
+                        let upstream = match clients.get(&client) {
+                            Some(c) => {
+                                c.activity.seen(now);
+                                c.upstream.clone()
+                            }
+                            None => match udp_upstream(remote) {
+                                Ok(upstream) => {
+                                    log::info!("[PROXY] new UDP client {client}");
+                                    let activity = Arc::new(Activity {
+                                        last_seen: Mutex::new(now),
+                                        open: AtomicBool::new(true),
+                                    });
+                                    let replies = spawn_udp_replies(
+                                        upstream.clone(),
+                                        socket.clone(),
+                                        client,
+                                        activity.clone(),
+                                        impairments.clone(),
+                                        tally.clone(),
+                                        delay_tx.clone(),
+                                    );
+                                    let c = UdpClient {
+                                        upstream: upstream.clone(),
+                                        activity,
+                                        replies,
+                                    };
+                                    clients.insert(client, c);
+                                    upstream
+                                }
+                                Err(e) => {
+                                    log::error!("[PROXY] no upstream socket for {client}, {e}");
+                                    continue;
+                                }
+                            },
+                        };
+                        out = impairer.datagram(
+                            &imp,
+                            (upstream, remote),
+                            buf[..n].to_vec(),
+                            now,
+                            &mut rng,
+                            &tally,
+                        );
+                    }
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut => {}
+                    // eg an icmp port unreachable from an earlier send on windows
+                    Err(e) => log::warn!("[PROXY] UDP receive error, {e}"),
+                }
+                out.extend(impairer.expired(&imp, now, &mut rng));
+                for (delay, (socket, dst), data) in out {
+                    let _ = delay_tx.send((now + delay, Delayed { socket, dst, data }));
+                }
+
+                // the replies threads of idle clients end on their own
+                clients.retain(|client, c| {
+                    if c.activity.idle_for(now) < idle {
+                        return true;
+                    }
+                    log::info!("[PROXY] UDP client {client} idle, upstream socket closed");
+                    c.activity.open.store(false, Ordering::Relaxed);
+                    false
+                });
+                handles.retain(|h: &JoinHandle<()>| !h.is_finished());
+            }
+            for c in clients.into_values() {
+                c.activity.open.store(false, Ordering::Relaxed);
+                handles.push(c.replies);
+            }
+            for handle in handles {
+                let _ = handle.join();
+            }
+            log::debug!("UDP proxy thread ended");
+        }));
+
+        log::info!("[PROXY] UDP {local} -> {remote} started");
+        Ok(local)
+    }
+
+    /// accepts connections on `listen` and opens one to `remote`
+    /// for each, returns the address actually bound
+    pub fn start_tcp(&mut self, listen: &str, remote: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(listen)?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?;
+        self.begin(local);
+
+        log::info!("[PROXY] TCP {local} -> {remote} started");
+        let remote = remote.to_string();
+        let is_running = self.is_running.clone();
+        let impairments = self.impairments.clone();
+        let tally = self.tally.clone();
+        self.workers.push(thread::spawn(move || {
+            let mut handles = vec![];
+            while is_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((client, peer)) => {
+                        count(&tally.connections, 1);
+                        let remote = remote.clone();
+                        let is_running = is_running.clone();
+                        let impairments = impairments.clone();
+                        let tally = tally.clone();
+                        handles.push(thread::spawn(move || {
+                            let legs = connect_from(&remote, None, Some(CONNECT_TIMEOUT)).and_then(
+                                |upstream| {
+                                    spawn_tcp_legs(
+                                        client,
+                                        upstream,
+                                        &is_running,
+                                        &impairments,
+                                        &tally,
+                                    )
+                                },
+                            );
+                            match legs {
+                                Ok(legs) => {
+                                    log::info!("[PROXY] {peer} connected, forwarding to {remote}");
+                                    for leg in legs {
+                                        let _ = leg.join();
+                                    }
+                                }
+                                Err(e) => {
+                                    log::error!("[PROXY] cannot forward {peer} to {remote}, {e}")
+                                }
+                            }
+                        }));
+                        handles.retain(|h: &JoinHandle<()>| !h.is_finished());
+                    }
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        thread::sleep(READ_TIMEOUT);
+                    }
+                    Err(e) => {
+                        log::error!("[PROXY] accept error, {e}");
+                        break;
+                    }
+                }
+            }
+            for handle in handles {
+                let _ = handle.join();
+            }
+            log::debug!("TCP proxy thread ended");
+        }));
+        Ok(local)
+    }
+
+    /// stops forwarding and closes every socket, queued data is lost
+    pub fn stop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        let was_running = !self.workers.is_empty();
+        for worker in self.workers.drain(..) {
+            if let Err(e) = worker.join() {
+                log::error!("proxy thread error: {e:?}");
+            }
+        }
+        if was_running {
+            log::info!("[PROXY] stopped");
+        }
+    }
+}
+
+/// the first address of `remote`
+fn resolve(remote: &str) -> io::Result<SocketAddr> {
+    remote.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot resolve {remote}"),
+        )
+    })
+}
+
+/// a socket of the remote's family on any port
+fn udp_upstream(remote: SocketAddr) -> io::Result<Arc<UdpSocket>> {
+    let any = match remote.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(any, 0))?;
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+    Ok(Arc::new(socket))
+}
+
+/// what the remote sends to a client's upstream socket goes back to the client,
+/// until the proxy stops or the client is closed as idle
+fn spawn_udp_replies(
+    upstream: Arc<UdpSocket>,
+    listen: Arc<UdpSocket>,
+    client: SocketAddr,
+    activity: Arc<Activity>,
+    impairments: Arc<Mutex<Impairments>>,
+    tally: Arc<Tally>,
+    delay_tx: mpsc::Sender<(Instant, Delayed)>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut impairer = Impairer::new();
+        let mut rng = rand::rng();
+        let mut buf = vec![0; u16::MAX as usize];
+        let local = upstream.local_addr().ok();
+        while activity.open.load(Ordering::Relaxed) {
+            let received = upstream.recv_from(&mut buf);
+            let now = Instant::now();
+            let imp = impairments
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            let mut out = vec![];
+            match received {
+                Ok((n, from)) => {
+                    if let Some(local) = local {
+                        tally.udp_packet(local, from, &buf[..n]);
+                    }
+                    activity.seen(now);
+                    out = impairer.datagram(&imp, (), buf[..n].to_vec(), now, &mut rng, &tally);
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => log::warn!("[PROXY] UDP receive error from upstream, {e}"),
+            }
+            out.extend(impairer.expired(&imp, now, &mut rng));
+            for (delay, (), data) in out {
+                let delayed = Delayed {
+                    socket: listen.clone(),
+                    dst: client,
+                    data,
+                };
+                let _ = delay_tx.send((now + delay, delayed));
+            }
+        }
+    })
+}
+
+/// sends every datagram when it is due
+fn delay_line(rx: mpsc::Receiver<(Instant, Delayed)>, is_running: &AtomicBool, tally: &Tally) {
+    // the sequence number keeps datagrams due at the same time in order
+    let mut queue: BTreeMap<(Instant, u64), Delayed> = BTreeMap::new();
+    let mut seq = 0u64;
+    while is_running.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        while let Some(entry) = queue.first_entry()
+            && entry.key().0 <= now
+        {
+            let d = entry.remove();
+            match d.socket.send_to(&d.data, d.dst) {
+                Ok(n) => {
+                    count(&tally.forwarded, 1);
+                    count(&tally.bytes, n as u64);
+                    if let Ok(local) = d.socket.local_addr() {
+                        let ev = UdpEvent::Sent {
+                            dst: d.dst,
+                            data: d.data,
+                            timestamp: chrono::Local::now(),
+                        };
+                        tally.report(ProxyEvent::Udp { local, ev });
+                    }
+                }
+                Err(e) => log::warn!("[PROXY] cannot send to {}, {e}", d.dst),
+            }
+        }
+        let wait = queue
+            .first_key_value()
+            .map_or(READ_TIMEOUT, |(k, _)| k.0.saturating_duration_since(now))
+            .min(READ_TIMEOUT);
+        match rx.recv_timeout(wait) {
+            Ok((due, delayed)) => {
+                seq += 1;
+                queue.insert((due, seq), delayed);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// sleeps until `t` unless `keep_going` turns false, which is returned
+fn sleep_until(t: Instant, keep_going: &impl Fn() -> bool) -> bool {
+    loop {
+        if !keep_going() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= t {
+            return true;
+        }
+        thread::sleep((t - now).min(Duration::from_millis(50)));
+    }
+}
+
+/// reader and writer threads for both directions of one connection
+fn spawn_tcp_legs(
+    client: TcpStream,
+    upstream: TcpStream,
+    is_running: &Arc<AtomicBool>,
+    impairments: &Arc<Mutex<Impairments>>,
+    tally: &Arc<Tally>,
+) -> io::Result<Vec<JoinHandle<()>>> {
+    for s in [&client, &upstream] {
+        // accepted streams inherit the listener's non blocking mode on some platforms
+        s.set_nonblocking(false)?;
+        s.set_read_timeout(Some(READ_TIMEOUT))?;
+        s.set_write_timeout(Some(READ_TIMEOUT))?;
+        // so that every piece goes out as a segment of its own
+        s.set_nodelay(true)?;
+    }
+    let client = Arc::new(client);
+    let upstream = Arc::new(upstream);
+    // false once either leg is gone, ends all four threads
+    let alive = Arc::new(AtomicBool::new(true));
+
+    let mut handles = vec![];
+    for (from, to) in [
+        (client.clone(), upstream.clone()),
+        (upstream.clone(), client.clone()),
+    ] {
+        let (tx, rx) = mpsc::sync_channel::<(Instant, Vec<u8>)>(TCP_IN_FLIGHT);
+        let keep_going = {
+            let alive = alive.clone();
+            let is_running = is_running.clone();
+            move || alive.load(Ordering::Relaxed) && is_running.load(Ordering::Relaxed)
+        };
+
+        // (peer, local) of each end, for the events
+        let from_addrs = (from.peer_addr()?, from.local_addr()?);
+        let to_addrs = (to.peer_addr()?, to.local_addr()?);
+
+        let reader_keep_going = keep_going.clone();
+        let reader_alive = alive.clone();
+        let reader_tally = tally.clone();
+        handles.push(thread::spawn(move || {
+            let mut buf = vec![0; 16 * 1024];
+            while reader_keep_going() {
+                match (&*from).read(&mut buf) {
+                    // dropping tx lets the writer pass the EOF on
+                    Ok(0) => break,
+                    // blocks while the writer is TCP_IN_FLIGHT behind,
+                    // fails once it is gone
+                    Ok(n) => {
+                        reader_tally.tcp(false, from_addrs, &buf[..n]);
+                        if tx.send((Instant::now(), buf[..n].to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut => {}
+                    Err(e) => {
+                        log::info!("[PROXY] connection {:?} lost, {e}", from.peer_addr());
+                        reader_alive.store(false, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            }
+        }));
+
+        let (client, upstream) = (client.clone(), upstream.clone());
+        let (alive, impairments, tally) = (alive.clone(), impairments.clone(), tally.clone());
+        handles.push(thread::spawn(move || {
+            let mut rng = rand::rng();
+            let mut next_free = Instant::now();
+            'reads: loop {
+                let (stamp, data) = match rx.recv_timeout(READ_TIMEOUT) {
+                    Ok(read) => read,
+                    Err(mpsc::RecvTimeoutError::Timeout) if keep_going() => continue,
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        if keep_going() {
+                            let _ = to.shutdown(Shutdown::Write);
+                        }
+                        break;
+                    }
+                };
+                let imp = impairments
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone();
+                if !sleep_until(stamp + imp.latency, &keep_going) {
+                    break;
+                }
+
+                let mut rest = data.as_slice();
+                while !rest.is_empty() {
+                    let n = match imp.max_chunk {
+                        0 => rest.len(),
+                        max => rng.random_range(1..=max).min(rest.len()),
+                    };
+                    let (piece, tail) = rest.split_at(n);
+                    rest = tail;
+
+                    if chance(&mut rng, imp.reset) {
+                        log::warn!(
+                            "[PROXY] resetting {:?} <-> {:?}",
+                            client.peer_addr(),
+                            upstream.peer_addr()
+                        );
+                        // a zero linger close sends RST instead of FIN
+                        for s in [&*client, &*upstream] {
+                            let _ = SockRef::from(s).set_linger(Some(Duration::ZERO));
+                        }
+                        count(&tally.resets, 1);
+                        alive.store(false, Ordering::Relaxed);
+                        break 'reads;
+                    }
+                    if imp.bandwidth > 0 {
+                        if !sleep_until(next_free, &keep_going) {
+                            break 'reads;
+                        }
+                        next_free = next_free.max(Instant::now())
+                            + Duration::from_secs_f64(n as f64 / imp.bandwidth as f64);
+                    }
+                    if let Err(e) = write_fully(&to, piece, &keep_going) {
+                        log::info!("[PROXY] cannot write to {:?}, {e}", to.peer_addr());
+                        alive.store(false, Ordering::Relaxed);
+                        break 'reads;
+                    }
+                    count(&tally.forwarded, 1);
+                    count(&tally.bytes, n as u64);
+                    tally.tcp(true, to_addrs, piece);
+                }
+            }
+        }));
+    }
+    Ok(handles)
+}
+
+/// write_all() that gives up once `keep_going` turns false,
+/// the stream has a write timeout so that a stuck peer cannot block stop()
+fn write_fully(
+    mut to: &TcpStream,
+    mut data: &[u8],
+    keep_going: &impl Fn() -> bool,
+) -> io::Result<()> {
+    while !data.is_empty() {
+        match to.write(data) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::Interrupted =>
+            {
+                if !keep_going() {
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn all(percent: f64) -> Impairments {
+        Impairments {
+            loss: percent,
+            ..Impairments::default()
+        }
+    }
+
+    #[test]
+    fn test_udp_impairer() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let tally = Tally::default();
+        let now = Instant::now();
+        let mut imp = Impairer::new();
+        let data = || b"hello".to_vec();
+
+        assert!(
+            imp.datagram(&all(100.0), 1, data(), now, &mut rng, &tally)
+                .is_empty()
+        );
+        assert_eq!(
+            imp.datagram(&all(0.0), 1, data(), now, &mut rng, &tally),
+            [(Duration::ZERO, 1, data())]
+        );
+
+        let corrupt = Impairments {
+            corrupt: 100.0,
+            latency: Duration::from_millis(5),
+            ..Impairments::default()
+        };
+        let out = imp.datagram(&corrupt, 1, data(), now, &mut rng, &tally);
+        let flipped: u32 = out[0]
+            .2
+            .iter()
+            .zip(data())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        assert_eq!(flipped, 1);
+        assert_eq!(out[0].0, Duration::from_millis(5));
+
+        let dup = Impairments {
+            duplicate: 100.0,
+            ..Impairments::default()
+        };
+        assert_eq!(
+            imp.datagram(&dup, 1, data(), now, &mut rng, &tally).len(),
+            2
+        );
+
+        // the first one waits for the second
+        let reorder = Impairments {
+            reorder: 100.0,
+            ..Impairments::default()
+        };
+        assert!(
+            imp.datagram(&reorder, 1, b"a".to_vec(), now, &mut rng, &tally)
+                .is_empty()
+        );
+        let out = imp.datagram(&reorder, 2, b"b".to_vec(), now, &mut rng, &tally);
+        let order: Vec<i32> = out.iter().map(|o| o.1).collect();
+        assert_eq!(order, [2, 1]);
+
+        // or goes out on its own after a while
+        imp.datagram(&reorder, 3, b"c".to_vec(), now, &mut rng, &tally);
+        assert!(imp.expired(&reorder, now, &mut rng).is_none());
+        assert_eq!(
+            imp.expired(&reorder, now + REORDER_TIMEOUT, &mut rng)
+                .map(|o| o.1),
+            Some(3)
+        );
+
+        let stats = Tally::default();
+        let jitter = Impairments {
+            latency: Duration::from_millis(100),
+            jitter: Duration::from_millis(20),
+            ..Impairments::default()
+        };
+        for _ in 0..50 {
+            let d = imp.datagram(&jitter, 1, data(), now, &mut rng, &stats)[0].0;
+            assert!((80..=120).contains(&d.as_millis()), "{d:?}");
+        }
+
+        assert_eq!(tally.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(tally.reordered.load(Ordering::Relaxed), 2);
+        assert!(all(101.0).validate().is_err());
+    }
+
+    #[test]
+    fn test_udp_proxy_with_latency() {
+        let echo = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 100];
+            let (n, from) = echo.recv_from(&mut buf).unwrap();
+            echo.send_to(&buf[..n], from).unwrap();
+        });
+
+        let mut proxy = Proxy::default();
+        proxy
+            .set_impairments(Impairments {
+                latency: Duration::from_millis(100),
+                ..Impairments::default()
+            })
+            .unwrap();
+        let local = proxy
+            .start_udp("127.0.0.1:0", &echo_addr.to_string())
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let t0 = Instant::now();
+        client.send_to(b"ping", local).unwrap();
+        let mut buf = [0; 100];
+        let (n, from) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        assert_eq!(from, local);
+        // latency on the way there and back
+        assert!(t0.elapsed() >= Duration::from_millis(200));
+        assert_eq!(proxy.stats().forwarded, 2);
+
+        proxy.stop();
+        assert!(!proxy.is_up());
+
+        // as received and as sent on, both ways, the echo can answer
+        // before the send is reported
+        let client = client.local_addr().unwrap();
+        let mut events: Vec<_> = proxy
+            .poll_events()
+            .into_iter()
+            .map(|ev| match ev {
+                ProxyEvent::Udp {
+                    ev: UdpEvent::Packet { src, .. },
+                    ..
+                } => (false, src),
+                ProxyEvent::Udp {
+                    ev: UdpEvent::Sent { dst, .. },
+                    ..
+                } => (true, dst),
+                ev => panic!("{ev:?}"),
+            })
+            .collect();
+        events.sort();
+        let mut expected = [
+            (false, client),
+            (true, echo_addr),
+            (false, echo_addr),
+            (true, client),
+        ];
+        expected.sort();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_udp_client_idle() {
+        let remote = UdpSocket::bind("127.0.0.1:0").unwrap();
+        remote
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut proxy = Proxy::default();
+        proxy.set_udp_idle_timeout(Duration::from_millis(200));
+        let local = proxy
+            .start_udp("127.0.0.1:0", &remote.local_addr().unwrap().to_string())
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut buf = [0; 100];
+        let mut upstream_port = || {
+            client.send_to(b"hi", local).unwrap();
+            remote.recv_from(&mut buf).unwrap().1.port()
+        };
+        let first = upstream_port();
+        assert_eq!(upstream_port(), first);
+        // silent long enough, the next datagram gets a new upstream socket
+        thread::sleep(Duration::from_millis(500));
+        assert_ne!(upstream_port(), first);
+        proxy.stop();
+    }
+
+    #[test]
+    fn test_tcp_proxy_chunks_and_reset() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let remote = server.local_addr().unwrap().to_string();
+        let mut proxy = Proxy::default();
+        proxy
+            .set_impairments(Impairments {
+                max_chunk: 3,
+                ..Impairments::default()
+            })
+            .unwrap();
+        let local = proxy.start_tcp("127.0.0.1:0", &remote).unwrap();
+
+        let mut client = TcpStream::connect(local).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let (mut upstream, _) = server.accept().unwrap();
+        upstream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+
+        client.write_all(b"hello world").unwrap();
+        let mut got = vec![0; 11];
+        upstream.read_exact(&mut got).unwrap();
+        assert_eq!(got, b"hello world");
+        // the last piece is counted after it is written
+        let t0 = Instant::now();
+        while proxy.stats().forwarded < 4 {
+            assert!(t0.elapsed() < Duration::from_secs(2));
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        upstream.write_all(b"back").unwrap();
+        let mut got = [0; 4];
+        client.read_exact(&mut got).unwrap();
+        assert_eq!(&got, b"back");
+
+        proxy
+            .set_impairments(Impairments {
+                reset: 100.0,
+                ..Impairments::default()
+            })
+            .unwrap();
+        client.write_all(b"boom").unwrap();
+        let mut buf = [0; 10];
+        assert!(client.read(&mut buf).is_err());
+        assert_eq!(proxy.stats().resets, 1);
+        assert_eq!(proxy.stats().connections, 1);
+    }
+}
@@ -0,0 +1,857 @@
+//! tcp relay between clients and an upstream server, see [`Relay`]
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::tcp::{TcpEvent, connect_from};
+
+/*
+    client  --->  relay  --->  upstream
+            <---         <---
+
+    the relay accepts like the TcpServer and connects out like the
+    TcpClient, one upstream connection per accepted client, one
+    reading thread per leg writes what it reads to the other leg
+    and reports it, so the log shows both directions in order
+
+    a paused direction keeps reading but holds the bytes instead of
+    writing them, the owner can look at / replace the held bytes and
+    resuming writes them out, inject() writes extra bytes into a
+    direction at any time, at most MAX_HELD bytes are held, what
+    does not fit is dropped (and reported) since a paused direction
+    that kept reading could otherwise grow without limit
+
+    the upstream is connected from a thread per accepted client so
+    that a slow or dead upstream does not hold up the accept loop
+
+    an EOF on one leg is passed on as a half close (shutdown write)
+    to the other, kill() shuts a leg down both ways, a connection is
+    gone once both of its reading threads have ended
+
+    only the reading thread of a direction writes to its destination,
+    resumed and injected bytes are handed to it through a channel and
+    written before its next read() is passed on, the reads time out
+    so that they do not wait for the peer to talk first, the held
+    lock is never kept during a write and the writes time out, so a
+    peer that stops reading ends its connection instead of blocking
+    the owner
+*/
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// how long a leg thread can take to pick up resumed / injected bytes
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+/// a peer not reading for this long is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// the most bytes a paused direction holds, later reads are dropped
+pub const MAX_HELD: usize = 1024 * 1024;
+
+/// which way bytes travel through the relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// from the accepted client to the upstream server
+    ToUpstream,
+    /// from the upstream server back to the client
+    ToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::ToUpstream => "client→upstream",
+            Direction::ToClient => "upstream→client",
+        })
+    }
+}
+
+impl Direction {
+    /// both, client to upstream first
+    pub const ALL: [Direction; 2] = [Direction::ToUpstream, Direction::ToClient];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// the leg the bytes are read from
+    pub fn source(self) -> Leg {
+        match self {
+            Direction::ToUpstream => Leg::Client,
+            Direction::ToClient => Leg::Upstream,
+        }
+    }
+
+    /// the leg the bytes are written to
+    pub fn dest(self) -> Leg {
+        match self {
+            Direction::ToUpstream => Leg::Upstream,
+            Direction::ToClient => Leg::Client,
+        }
+    }
+}
+
+/// one of the two connections making up a relayed connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leg {
+    /// the accepted one
+    Client,
+    /// the one the relay opened
+    Upstream,
+}
+
+impl fmt::Display for Leg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Leg::Client => "client",
+            Leg::Upstream => "upstream",
+        })
+    }
+}
+
+/// a relayed connection as the owner sees it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connection {
+    /// unique while the Relay lives, across restarts
+    pub id: usize,
+    /// the accepted client
+    pub client: SocketAddr,
+    /// the upstream server as connected
+    pub upstream: SocketAddr,
+    /// the relay's end of the client leg
+    pub client_local: SocketAddr,
+    /// the relay's end of the upstream leg
+    pub upstream_local: SocketAddr,
+}
+
+impl Connection {
+    /// (peer, local) of a leg
+    fn addrs(&self, leg: Leg) -> (SocketAddr, SocketAddr) {
+        match leg {
+            Leg::Client => (self.client, self.client_local),
+            Leg::Upstream => (self.upstream, self.upstream_local),
+        }
+    }
+}
+
+/// what the relay threads hand over to the owner
+#[derive(Debug)]
+pub enum RelayEvent {
+    /// a client was accepted and the upstream connected
+    Opened(Connection),
+
+    /// one read() worth of bytes from either leg
+    Data {
+        /// the connection
+        conn: Connection,
+        /// which way
+        dir: Direction,
+        /// as read
+        data: Vec<u8>,
+        /// when it was read
+        timestamp: chrono::DateTime<chrono::Local>,
+        /// the direction was paused, the bytes are held instead of sent on
+        held: bool,
+        /// how many of the bytes did not fit in the held ones (see
+        /// [`MAX_HELD`]) and were dropped, from the end of `data`
+        dropped: usize,
+    },
+
+    /// bytes written by inject(), or held ones written on resume
+    Injected {
+        /// the connection
+        conn: Connection,
+        /// which way
+        dir: Direction,
+        /// as written
+        data: Vec<u8>,
+        /// when it was written
+        timestamp: chrono::DateTime<chrono::Local>,
+    },
+
+    /// nothing more comes from this leg (EOF, error or kill())
+    Closed {
+        /// the connection id
+        id: usize,
+        /// the leg that ended
+        leg: Leg,
+    },
+}
+
+impl RelayEvent {
+    /// the same traffic as seen by each leg, for a capture, what was
+    /// read from one leg and what was written to the other
+    pub fn tcp_events(&self) -> Vec<TcpEvent> {
+        let (conn, dir, data, timestamp, read, written) = match self {
+            RelayEvent::Data {
+                conn,
+                dir,
+                data,
+                timestamp,
+                held,
+                ..
+            } => (conn, dir, data, timestamp, true, !held),
+            RelayEvent::Injected {
+                conn,
+                dir,
+                data,
+                timestamp,
+            } => (conn, dir, data, timestamp, false, true),
+            RelayEvent::Opened(_) | RelayEvent::Closed { .. } => return vec![],
+        };
+        let mut out = vec![];
+        if read {
+            let (peer, local) = conn.addrs(dir.source());
+            out.push(TcpEvent::Packet {
+                peer,
+                local,
+                data: data.clone(),
+                timestamp: *timestamp,
+            });
+        }
+        if written {
+            let (peer, local) = conn.addrs(dir.dest());
+            out.push(TcpEvent::Sent {
+                peer,
+                local,
+                data: data.clone(),
+                timestamp: *timestamp,
+            });
+        }
+        out
+    }
+}
+
+/// what the listener / leg threads send, same idea as in tcp.rs
+enum Internal {
+    Add(Arc<Conn>),
+    Del(usize),
+    Event(RelayEvent),
+}
+
+#[derive(Default)]
+struct Held {
+    paused: bool,
+    data: Vec<u8>,
+}
+
+struct Conn {
+    info: Connection,
+    client: TcpStream,
+    upstream: TcpStream,
+    // per direction, the flag and the bytes share a lock so that
+    // nothing slips past a pause / resume
+    held: [Mutex<Held>; 2],
+    // per direction, bytes for its leg thread to write
+    writes: [mpsc::Sender<Vec<u8>>; 2],
+}
+
+impl Conn {
+    fn leg(&self, leg: Leg) -> &TcpStream {
+        match leg {
+            Leg::Client => &self.client,
+            Leg::Upstream => &self.upstream,
+        }
+    }
+
+    fn dest(&self, dir: Direction) -> &TcpStream {
+        match dir {
+            Direction::ToUpstream => &self.upstream,
+            Direction::ToClient => &self.client,
+        }
+    }
+
+    fn held(&self, dir: Direction) -> MutexGuard<'_, Held> {
+        self.held[dir.index()]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// the leg thread of `dir` writes `data` next
+    fn write(&self, dir: Direction, data: Vec<u8>) -> io::Result<()> {
+        self.writes[dir.index()].send(data).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("relayed connection #{} {dir} has ended", self.info.id),
+            )
+        })
+    }
+
+    fn shutdown(&self) {
+        let _ = self.client.shutdown(Shutdown::Both);
+        let _ = self.upstream.shutdown(Shutdown::Both);
+    }
+}
+
+/// accepts clients and relays each one to the upstream,
+/// see the module notes
+pub struct Relay {
+    is_running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+    local: Option<SocketAddr>,
+    next_id: Arc<AtomicUsize>,
+    event_tx: mpsc::Sender<Internal>,
+    event_rx: mpsc::Receiver<Internal>,
+    // kept up to date by poll_events()
+    conns: BTreeMap<usize, Arc<Conn>>,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        let (event_tx, event_rx) = mpsc::channel();
+        Relay {
+            is_running: Arc::new(AtomicBool::new(false)),
+            worker: None,
+            local: None,
+            next_id: Arc::new(AtomicUsize::new(1)),
+            event_tx,
+            event_rx,
+            conns: BTreeMap::new(),
+        }
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Relay {
+    /// true while accepting
+    pub fn is_up(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// the listening address, None while stopped
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local.filter(|_| self.is_up())
+    }
+
+    /// listens on `listen` and relays every client to `upstream`
+    /// (host:port), returns the address actually bound
+    pub fn begin(&mut self, listen: &str, upstream: &str) -> io::Result<SocketAddr> {
+        self.stop();
+        let listener = TcpListener::bind(listen)?;
+        listener.set_nonblocking(true)?;
+        let local = listener.local_addr()?;
+        self.local = Some(local);
+        log::info!("[RELAY] {local} -> {upstream} started");
+
+        let upstream = upstream.to_string();
+        let is_running = self.is_running.clone();
+        let next_id = self.next_id.clone();
+        let tx = self.event_tx.clone();
+        self.is_running.store(true, Ordering::Relaxed);
+        self.worker = Some(thread::spawn(move || {
+            // to shut down on stop, including those the owner has not polled yet
+            let conns: Arc<Mutex<Vec<Weak<Conn>>>> = Arc::default();
+            while is_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((client, peer)) => {
+                        let (upstream, is_running) = (upstream.clone(), is_running.clone());
+                        let (next_id, tx, conns) = (next_id.clone(), tx.clone(), conns.clone());
+                        thread::spawn(move || {
+                            let (client, upstream) = match open(client, &upstream) {
+                                Ok(legs) => legs,
+                                Err(e) => {
+                                    log::error!("[RELAY] cannot relay {peer} to {upstream}, {e}");
+                                    return;
+                                }
+                            };
+                            // checked under the lock so that stop() cannot miss it
+                            let mut conns = conns.lock().unwrap_or_else(PoisonError::into_inner);
+                            if !is_running.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            let id = next_id.fetch_add(1, Ordering::Relaxed);
+                            conns.retain(|c| c.strong_count() > 0);
+                            conns.extend(start_conn(id, client, upstream, &tx));
+                        });
+                    }
+                    Err(e)
+                        if e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        log::error!("[RELAY] accept error, {e}");
+                        break;
+                    }
+                }
+            }
+            // a connect still running sees is_running false and drops its legs
+            is_running.store(false, Ordering::Relaxed);
+            let conns = conns.lock().unwrap_or_else(PoisonError::into_inner);
+            for conn in conns.iter().filter_map(Weak::upgrade) {
+                conn.shutdown();
+            }
+            log::debug!("relay thread ended");
+        }));
+        Ok(local)
+    }
+
+    /// stops accepting and shuts every relayed connection down
+    pub fn stop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            if let Err(e) = worker.join() {
+                log::error!("relay thread error: {e:?}");
+            }
+            log::info!("[RELAY] stopped");
+        }
+        self.conns.clear();
+    }
+
+    /// keeps the connection list up to date and returns the events
+    pub fn poll_events(&mut self) -> Vec<RelayEvent> {
+        let mut out = vec![];
+        for ev in self.event_rx.try_iter() {
+            match ev {
+                Internal::Add(conn) => {
+                    self.conns.insert(conn.info.id, conn);
+                }
+                Internal::Del(id) => {
+                    self.conns.remove(&id);
+                }
+                Internal::Event(ev) => out.push(ev),
+            }
+        }
+        out
+    }
+
+    /// relayed connections, oldest first
+    pub fn connections(&self) -> Vec<Connection> {
+        self.conns.values().map(|c| c.info.clone()).collect()
+    }
+
+    fn conn(&self, id: usize) -> io::Result<&Arc<Conn>> {
+        self.conns.get(&id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no relayed connection #{id}"),
+            )
+        })
+    }
+
+    /// a paused direction holds what it reads, resuming writes the
+    /// held bytes out first (as edited with set_held())
+    pub fn pause(&self, id: usize, dir: Direction, paused: bool) -> io::Result<()> {
+        let conn = self.conn(id)?;
+        let mut held = conn.held(dir);
+        if held.paused == paused {
+            return Ok(());
+        }
+        if !paused && !held.data.is_empty() {
+            // queued before the reads that follow the resume
+            conn.write(dir, std::mem::take(&mut held.data))?;
+        }
+        held.paused = paused;
+        log::info!(
+            "[RELAY] #{id} {dir} {}",
+            if paused { "paused" } else { "resumed" }
+        );
+        Ok(())
+    }
+
+    /// see pause()
+    pub fn is_paused(&self, id: usize, dir: Direction) -> bool {
+        self.conn(id).is_ok_and(|c| c.held(dir).paused)
+    }
+
+    /// bytes held back by a paused direction
+    pub fn held(&self, id: usize, dir: Direction) -> Vec<u8> {
+        self.conn(id)
+            .map(|c| c.held(dir).data.clone())
+            .unwrap_or_default()
+    }
+
+    /// replaces the held bytes, an empty `data` drops them
+    pub fn set_held(&self, id: usize, dir: Direction, data: Vec<u8>) -> io::Result<()> {
+        self.conn(id)?.held(dir).data = data;
+        Ok(())
+    }
+
+    /// writes `data` into a direction right away, a pause does not
+    /// apply, the write itself is reported by an Injected event
+    pub fn inject(&self, id: usize, dir: Direction, data: &[u8]) -> io::Result<()> {
+        self.conn(id)?.write(dir, data.to_vec())
+    }
+
+    /// shuts one leg down both ways, the other side sees the
+    /// EOF as a half close and can still talk
+    pub fn kill(&self, id: usize, leg: Leg) -> io::Result<()> {
+        self.conn(id)?.leg(leg).shutdown(Shutdown::Both)?;
+        log::info!("[RELAY] #{id} {leg} leg killed");
+        Ok(())
+    }
+}
+
+/// connects the upstream for a freshly accepted client
+fn open(client: TcpStream, upstream: &str) -> io::Result<(TcpStream, TcpStream)> {
+    // accepted streams inherit the listener's non blocking mode on some platforms
+    client.set_nonblocking(false)?;
+    let upstream = connect_from(upstream, None, Some(CONNECT_TIMEOUT))?;
+    for s in [&client, &upstream] {
+        s.set_read_timeout(Some(READ_TIMEOUT))?;
+        s.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    }
+    Ok((client, upstream))
+}
+
+fn connection(id: usize, client: &TcpStream, upstream: &TcpStream) -> io::Result<Connection> {
+    Ok(Connection {
+        id,
+        client: client.peer_addr()?,
+        upstream: upstream.peer_addr()?,
+        client_local: client.local_addr()?,
+        upstream_local: upstream.local_addr()?,
+    })
+}
+
+fn start_conn(
+    id: usize,
+    client: TcpStream,
+    upstream: TcpStream,
+    tx: &mpsc::Sender<Internal>,
+) -> Option<Weak<Conn>> {
+    let info = match connection(id, &client, &upstream) {
+        Ok(info) => info,
+        Err(e) => {
+            log::error!("[RELAY] connection #{id} lost before it started, {e}");
+            return None;
+        }
+    };
+    log::info!("[RELAY] #{id} {} <-> {} opened", info.client, info.upstream);
+    let (to_upstream, to_upstream_rx) = mpsc::channel();
+    let (to_client, to_client_rx) = mpsc::channel();
+    let conn = Arc::new(Conn {
+        info: info.clone(),
+        client,
+        upstream,
+        held: Default::default(),
+        writes: [to_upstream, to_client],
+    });
+    // before any data so that the owner knows the id first
+    let _ = tx.send(Internal::Add(conn.clone()));
+    let _ = tx.send(Internal::Event(RelayEvent::Opened(info)));
+
+    let legs = Arc::new(AtomicUsize::new(Direction::ALL.len()));
+    for (dir, writes) in Direction::ALL
+        .into_iter()
+        .zip([to_upstream_rx, to_client_rx])
+    {
+        let (conn, tx, legs) = (conn.clone(), tx.clone(), legs.clone());
+        thread::spawn(move || {
+            relay(&conn, dir, &writes, &tx);
+            let _ = tx.send(Internal::Event(RelayEvent::Closed {
+                id,
+                leg: dir.source(),
+            }));
+            if legs.fetch_sub(1, Ordering::Relaxed) == 1 {
+                log::info!("[RELAY] #{id} closed");
+                let _ = tx.send(Internal::Del(id));
+            }
+        });
+    }
+    Some(Arc::downgrade(&conn))
+}
+
+/// reads one leg until it ends, writing to the other unless paused,
+/// and writes what resume / inject handed over in `writes`
+fn relay(
+    conn: &Conn,
+    dir: Direction,
+    writes: &mpsc::Receiver<Vec<u8>>,
+    tx: &mpsc::Sender<Internal>,
+) {
+    let id = conn.info.id;
+    let leg = dir.source();
+    let mut source = conn.leg(leg);
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let n = match source.read(&mut buf) {
+            Ok(0) => {
+                log::info!("[RELAY] #{id} {leg} closed");
+                // pass the half close on, after what is still to be written
+                if write_handed(conn, dir, writes, tx) {
+                    let _ = conn.dest(dir).shutdown(Shutdown::Write);
+                }
+                return;
+            }
+            Ok(n) => n,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                if !write_handed(conn, dir, writes, tx) {
+                    return;
+                }
+                continue;
+            }
+            Err(e) => {
+                log::info!("[RELAY] #{id} {leg} lost, {e}");
+                conn.shutdown();
+                return;
+            }
+        };
+        let data = buf[..n].to_vec();
+        let mut held = conn.held(dir);
+        let is_held = held.paused;
+        let mut dropped = 0;
+        if is_held {
+            let keep = n.min(MAX_HELD.saturating_sub(held.data.len()));
+            held.data.extend_from_slice(&data[..keep]);
+            dropped = n - keep;
+            if dropped > 0 {
+                log::warn!("[RELAY] #{id} {dir} held bytes full, {dropped} bytes dropped");
+            }
+        }
+        drop(held);
+        // resumed bytes before the ones read after the resume
+        if !write_handed(conn, dir, writes, tx) {
+            return;
+        }
+        if !is_held && let Err(e) = conn.dest(dir).write_all(&data) {
+            log::error!("[RELAY] #{id} {dir} write failed, {e}");
+            conn.shutdown();
+            return;
+        }
+        let _ = tx.send(Internal::Event(RelayEvent::Data {
+            conn: conn.info.clone(),
+            dir,
+            data,
+            timestamp: chrono::Local::now(),
+            held: is_held,
+            dropped,
+        }));
+    }
+}
+
+/// writes the bytes resume / inject handed over, false (and the
+/// connection shut down) if a write failed
+fn write_handed(
+    conn: &Conn,
+    dir: Direction,
+    writes: &mpsc::Receiver<Vec<u8>>,
+    tx: &mpsc::Sender<Internal>,
+) -> bool {
+    let id = conn.info.id;
+    for data in writes.try_iter() {
+        if let Err(e) = conn.dest(dir).write_all(&data) {
+            log::error!("[RELAY] #{id} {dir} write failed, {e}");
+            conn.shutdown();
+            return false;
+        }
+        log::info!(
+            "[RELAY SEND] #{id} {dir} {:?}",
+            String::from_utf8_lossy(&data)
+        );
+        let _ = tx.send(Internal::Event(RelayEvent::Injected {
+            conn: conn.info.clone(),
+            dir,
+            data,
+            timestamp: chrono::Local::now(),
+        }));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    /// polls until `done` sees what it waits for, all events are kept
+    fn wait_for(
+        relay: &mut Relay,
+        events: &mut Vec<RelayEvent>,
+        done: impl Fn(&Relay, &[RelayEvent]) -> bool,
+    ) {
+        let t0 = Instant::now();
+        loop {
+            events.extend(relay.poll_events());
+            if done(relay, events) {
+                return;
+            }
+            assert!(t0.elapsed() < Duration::from_secs(2), "{events:?}");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn start() -> (Relay, TcpStream, TcpStream, usize) {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut relay = Relay::default();
+        let local = relay
+            .begin("127.0.0.1:0", &server.local_addr().unwrap().to_string())
+            .unwrap();
+        let client = TcpStream::connect(local).unwrap();
+        let (upstream, _) = server.accept().unwrap();
+        for s in [&client, &upstream] {
+            s.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        }
+        let mut events = vec![];
+        wait_for(&mut relay, &mut events, |r, _| !r.connections().is_empty());
+        let id = relay.connections()[0].id;
+        (relay, client, upstream, id)
+    }
+
+    fn read_n(mut s: &TcpStream, n: usize) -> Vec<u8> {
+        let mut buf = vec![0; n];
+        s.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_relay_both_directions() {
+        let (mut relay, mut client, mut upstream, id) = start();
+        client.write_all(b"hi").unwrap();
+        assert_eq!(read_n(&upstream, 2), b"hi");
+        upstream.write_all(b"yo").unwrap();
+        assert_eq!(read_n(&client, 2), b"yo");
+
+        let mut events = vec![];
+        wait_for(&mut relay, &mut events, |_, evs| {
+            evs.iter()
+                .filter(|ev| matches!(ev, RelayEvent::Data { .. }))
+                .count()
+                == 2
+        });
+        let mut data: Vec<(Direction, &[u8])> = events
+            .iter()
+            .filter_map(|ev| match ev {
+                RelayEvent::Data {
+                    conn,
+                    dir,
+                    data,
+                    held: false,
+                    ..
+                } if conn.id == id => Some((*dir, data.as_slice())),
+                _ => None,
+            })
+            .collect();
+        // each direction reports after its write, in either order
+        data.sort_by_key(|(_, data)| *data);
+        assert_eq!(
+            data,
+            [
+                (Direction::ToUpstream, &b"hi"[..]),
+                (Direction::ToClient, &b"yo"[..])
+            ]
+        );
+        // read from the client leg, written to the upstream one
+        let to_upstream = events
+            .iter()
+            .find(|ev| {
+                matches!(
+                    ev,
+                    RelayEvent::Data {
+                        dir: Direction::ToUpstream,
+                        ..
+                    }
+                )
+            })
+            .unwrap();
+        let peers: Vec<_> = to_upstream
+            .tcp_events()
+            .iter()
+            .map(|ev| match ev {
+                TcpEvent::Packet { peer, .. } => (false, *peer),
+                TcpEvent::Sent { peer, .. } => (true, *peer),
+            })
+            .collect();
+        assert_eq!(
+            peers,
+            [
+                (false, client.local_addr().unwrap()),
+                (true, upstream.local_addr().unwrap())
+            ]
+        );
+
+        // the client's EOF reaches the upstream, which can still answer
+        client.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(upstream.read(&mut [0; 4]).unwrap(), 0);
+        upstream.write_all(b"bye").unwrap();
+        assert_eq!(read_n(&client, 3), b"bye");
+        drop(upstream);
+        wait_for(&mut relay, &mut events, |r, _| r.connections().is_empty());
+    }
+
+    #[test]
+    fn test_relay_pause_edit_inject_kill() {
+        let (mut relay, mut client, upstream, id) = start();
+        relay.pause(id, Direction::ToUpstream, true).unwrap();
+        assert!(relay.is_paused(id, Direction::ToUpstream));
+        client.write_all(b"abc").unwrap();
+        let mut events = vec![];
+        wait_for(&mut relay, &mut events, |r, _| {
+            r.held(id, Direction::ToUpstream) == b"abc"
+        });
+        assert!(matches!(
+            events.last(),
+            Some(RelayEvent::Data { held: true, .. })
+        ));
+
+        relay
+            .set_held(id, Direction::ToUpstream, b"xyz".to_vec())
+            .unwrap();
+        relay.inject(id, Direction::ToClient, b"hello").unwrap();
+        assert_eq!(read_n(&client, 5), b"hello");
+        relay.pause(id, Direction::ToUpstream, false).unwrap();
+        assert_eq!(read_n(&upstream, 3), b"xyz");
+        assert!(relay.held(id, Direction::ToUpstream).is_empty());
+
+        // the client sees the upstream leg go away as an EOF
+        relay.kill(id, Leg::Upstream).unwrap();
+        assert_eq!(client.read(&mut [0; 4]).unwrap(), 0);
+        assert!(relay.inject(id + 100, Direction::ToClient, b"x").is_err());
+        relay.stop();
+        assert!(relay.connections().is_empty());
+    }
+
+    #[test]
+    fn test_relay_held_limit() {
+        let (mut relay, mut client, _upstream, id) = start();
+        relay.pause(id, Direction::ToUpstream, true).unwrap();
+        client.write_all(&vec![b'x'; MAX_HELD + 100]).unwrap();
+        let mut events = vec![];
+        wait_for(&mut relay, &mut events, |r, _| {
+            r.held(id, Direction::ToUpstream).len() == MAX_HELD
+        });
+        wait_for(&mut relay, &mut events, |_, evs| {
+            evs.iter()
+                .map(|ev| match ev {
+                    RelayEvent::Data { dropped, .. } => *dropped,
+                    _ => 0,
+                })
+                .sum::<usize>()
+                == 100
+        });
+    }
+    #[test]
+    fn test_relay_stalled_peer() {
+        // the upstream never reads, the leg thread ends up stuck in a write
+        let (mut relay, client, _upstream, id) = start();
+        let writer = thread::spawn(move || {
+            let _ = (&client).write_all(&vec![b'x'; 64 * 1024 * 1024]);
+        });
+        thread::sleep(Duration::from_millis(500));
+
+        let t0 = Instant::now();
+        relay.pause(id, Direction::ToUpstream, true).unwrap();
+        assert!(relay.is_paused(id, Direction::ToUpstream));
+        let _ = relay.held(id, Direction::ToUpstream);
+        let _ = relay.inject(id, Direction::ToUpstream, b"x");
+        relay.pause(id, Direction::ToUpstream, false).unwrap();
+        assert!(t0.elapsed() < Duration::from_millis(100));
+
+        relay.stop();
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,805 @@
+//! resending the payloads of a pcap / pcapng file
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/*
+    loading turns a capture into a list of messages
+
+        udp     one datagram is one message
+        tcp     the stream of each direction is put back in order,
+                retransmissions dropped, every segment that moves
+                the stream forward is one message
+
+    link types: ethernet (with vlan tags), raw ip, linux cooked
+    (v1 and v2) and bsd loopback, ip fragments are skipped, so
+    is tcp data after a gap the capture missed, and so is a last
+    record cut short, eg of a capture still being written
+
+    like the Scheduler, a Replay does not send anything itself,
+    the owner polls it and sends wherever it likes
+
+        while let Some(msg) = replay.poll(Instant::now()) {
+            let ok = send(&msg.data);
+            replay.record(ok);
+        }
+*/
+
+const PCAP_MICRO: u32 = 0xA1B2_C3D4;
+const PCAP_NANO: u32 = 0xA1B2_3C4D;
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER: u32 = 0x1A2B_3C4D;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+
+/// transport of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// a datagram
+    Udp,
+    /// a piece of a reassembled stream
+    Tcp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Udp => f.write_str("UDP"),
+            Protocol::Tcp => f.write_str("TCP"),
+        }
+    }
+}
+
+/// one payload from the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// udp or tcp
+    pub protocol: Protocol,
+    /// sender in the capture
+    pub src: SocketAddr,
+    /// receiver in the capture
+    pub dst: SocketAddr,
+    /// capture timestamp, since the unix epoch
+    pub time: Duration,
+    /// payload
+    pub data: Vec<u8>,
+}
+
+/// which messages to replay, None matches anything
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Filter {
+    /// udp or tcp only
+    pub protocol: Option<Protocol>,
+    /// the sender's port in the capture
+    pub src_port: Option<u16>,
+    /// the receiver's port in the capture
+    pub dst_port: Option<u16>,
+}
+
+impl Filter {
+    /// true if `msg` passes every set condition
+    pub fn matches(&self, msg: &Message) -> bool {
+        self.protocol.is_none_or(|p| p == msg.protocol)
+            && self.src_port.is_none_or(|p| p == msg.src.port())
+            && self.dst_port.is_none_or(|p| p == msg.dst.port())
+    }
+}
+
+/// when the messages go out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// the gaps of the capture
+    Original,
+
+    /// the gaps of the capture divided by this, 2.0 is twice as fast
+    Speed(f64),
+
+    /// the same gap between every two messages
+    Interval(Duration),
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// reads the whole file, see parse()
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<Message>> {
+    parse(&fs::read(path)?)
+}
+
+/// every udp and tcp payload of a pcap or pcapng file, in time order
+pub fn parse(file: &[u8]) -> io::Result<Vec<Message>> {
+    let mut packets = Packets::default();
+    let magic = file
+        .get(..4)
+        .ok_or_else(|| invalid_data("file too short"))?;
+    if u32::from_le_bytes(magic.try_into().unwrap()) == PCAPNG_SECTION_HEADER {
+        parse_pcapng(file, &mut packets)?;
+    } else {
+        parse_pcap(file, &mut packets)?;
+    }
+    Ok(packets.finish())
+}
+
+/// reads integers in the byte order of the file
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16(&self, at: usize) -> io::Result<u16> {
+        let b: [u8; 2] = self
+            .data
+            .get(at..at + 2)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| invalid_data("unexpected end of file"))?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32(&self, at: usize) -> io::Result<u32> {
+        let b: [u8; 4] = self
+            .data
+            .get(at..at + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| invalid_data("unexpected end of file"))?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn bytes(&self, at: usize, len: usize) -> io::Result<&[u8]> {
+        self.data
+            .get(at..at.saturating_add(len))
+            .ok_or_else(|| invalid_data("unexpected end of file"))
+    }
+}
+
+fn parse_pcap(file: &[u8], packets: &mut Packets) -> io::Result<()> {
+    let magic = u32::from_le_bytes(file[..4].try_into().unwrap());
+    let (big_endian, nano) = match magic {
+        PCAP_MICRO => (false, false),
+        PCAP_NANO => (false, true),
+        m if m.swap_bytes() == PCAP_MICRO => (true, false),
+        m if m.swap_bytes() == PCAP_NANO => (true, true),
+        _ => return Err(invalid_data("not a pcap or pcapng file")),
+    };
+    let r = Reader {
+        data: file,
+        big_endian,
+    };
+    let linktype = r.u32(20)?;
+
+    let mut at = 24;
+    while at < file.len() {
+        let rest = file.len() - at;
+        if rest < 16 || r.u32(at + 8)? as usize > rest - 16 {
+            truncated(rest);
+            break;
+        }
+        let secs = r.u32(at)? as u64;
+        let frac = r.u32(at + 4)? as u64;
+        let len = r.u32(at + 8)? as usize;
+        let frame = r.bytes(at + 16, len)?;
+        let time = Duration::from_secs(secs)
+            + if nano {
+                Duration::from_nanos(frac)
+            } else {
+                Duration::from_micros(frac)
+            };
+        packets.add(linktype, time, frame);
+        at += 16 + len;
+    }
+    Ok(())
+}
+
+fn parse_pcapng(file: &[u8], packets: &mut Packets) -> io::Result<()> {
+    // (link type, timestamp units per second) per interface of the section
+    let mut interfaces: Vec<(u32, u64)> = vec![];
+    let mut r = Reader {
+        data: file,
+        big_endian: false,
+    };
+
+    let mut at = 0;
+    while at < file.len() {
+        // no block is shorter than 12 bytes
+        let rest = file.len() - at;
+        if rest < 12 {
+            truncated(rest);
+            break;
+        }
+        if r.u32(at)? == PCAPNG_SECTION_HEADER {
+            // the byte order magic tells the order of everything else
+            r.big_endian = match r.bytes(at + 8, 4)? {
+                b if u32::from_le_bytes(b.try_into().unwrap()) == PCAPNG_BYTE_ORDER => false,
+                b if u32::from_be_bytes(b.try_into().unwrap()) == PCAPNG_BYTE_ORDER => true,
+                _ => return Err(invalid_data("bad pcapng byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let kind = r.u32(at)?;
+        let len = r.u32(at + 4)? as usize;
+        if len < 12 || !len.is_multiple_of(4) {
+            return Err(invalid_data(format!("bad pcapng block length {len}")));
+        }
+        if len > rest {
+            truncated(rest);
+            break;
+        }
+        let block = r.bytes(at, len)?;
+        let body = Reader {
+            data: &block[8..len - 4],
+            big_endian: r.big_endian,
+        };
+        match kind {
+            // interface description
+            1 => {
+                let linktype = body.u16(0)? as u32;
+                interfaces.push((linktype, ts_resolution(body)?));
+            }
+            // enhanced packet
+            6 => {
+                let iface = body.u32(0)? as usize;
+                let &(linktype, per_sec) = interfaces
+                    .get(iface)
+                    .ok_or_else(|| invalid_data(format!("packet on unknown interface {iface}")))?;
+                let ts = ((body.u32(4)? as u64) << 32) | body.u32(8)? as u64;
+                let time = Duration::from_secs(ts / per_sec)
+                    + Duration::from_nanos(
+                        ((ts % per_sec) as u128 * 1_000_000_000 / per_sec as u128) as u64,
+                    );
+                let caplen = body.u32(12)? as usize;
+                packets.add(linktype, time, body.bytes(20, caplen)?);
+            }
+            // name resolution, statistics, simple packets (no timestamp)...
+            _ => {}
+        }
+        at += len;
+    }
+    Ok(())
+}
+
+fn truncated(rest: usize) {
+    log::warn!("the capture ends with a truncated record, its last {rest} bytes are ignored");
+}
+
+/// units per second from the if_tsresol option, microseconds by default
+fn ts_resolution(idb: Reader) -> io::Result<u64> {
+    let mut at = 8;
+    while at + 4 <= idb.data.len() {
+        let code = idb.u16(at)?;
+        let len = idb.u16(at + 2)? as usize;
+        if code == 0 {
+            break;
+        }
+        if code == 9 && len == 1 {
+            let v = idb.bytes(at + 4, 1)?[0];
+            let exp = (v & 0x7f) as u32;
+            let base: u64 = if v & 0x80 == 0 { 10 } else { 2 };
+            return base
+                .checked_pow(exp)
+                .ok_or_else(|| invalid_data(format!("unsupported if_tsresol {v:#x}")));
+        }
+        at += 4 + len.next_multiple_of(4);
+    }
+    Ok(1_000_000)
+}
+
+/// one direction of a tcp connection
+#[derive(Default)]
+struct Stream {
+    // sequence number of the first byte, and the next one
+    // expected, relative to it
+    base: u32,
+    next: u32,
+
+    // early segments by relative sequence number
+    pending: BTreeMap<u32, Vec<u8>>,
+}
+
+/// collects the messages, packet by packet
+#[derive(Default)]
+struct Packets {
+    messages: Vec<Message>,
+    streams: HashMap<(SocketAddr, SocketAddr), Stream>,
+}
+
+impl Packets {
+    fn add(&mut self, linktype: u32, time: Duration, frame: &[u8]) {
+        let Some(ip) = link_payload(linktype, frame) else {
+            return;
+        };
+        let Some((proto, src, dst, segment)) = ip_payload(ip) else {
+            return;
+        };
+        match proto {
+            17 if segment.len() >= 8 => {
+                let len =
+                    (u16::from_be_bytes([segment[4], segment[5]]) as usize).clamp(8, segment.len());
+                self.messages.push(Message {
+                    protocol: Protocol::Udp,
+                    src: SocketAddr::new(src, u16::from_be_bytes([segment[0], segment[1]])),
+                    dst: SocketAddr::new(dst, u16::from_be_bytes([segment[2], segment[3]])),
+                    time,
+                    data: segment[8..len].to_vec(),
+                });
+            }
+            6 if segment.len() >= 20 => {
+                let offset = (segment[12] >> 4) as usize * 4;
+                let Some(data) = segment.get(offset..) else {
+                    return;
+                };
+                let src = SocketAddr::new(src, u16::from_be_bytes([segment[0], segment[1]]));
+                let dst = SocketAddr::new(dst, u16::from_be_bytes([segment[2], segment[3]]));
+                let seq = u32::from_be_bytes(segment[4..8].try_into().unwrap());
+                self.add_segment(src, dst, seq, segment[13], data, time);
+            }
+            _ => {}
+        }
+    }
+
+    fn add_segment(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        seq: u32,
+        flags: u8,
+        data: &[u8],
+        time: Duration,
+    ) {
+        if flags & TCP_SYN != 0 {
+            // a new connection, the syn takes one sequence number
+            self.flush_stream(src, dst);
+            self.streams.insert(
+                (src, dst),
+                Stream {
+                    base: seq.wrapping_add(1),
+                    ..Stream::default()
+                },
+            );
+            return;
+        }
+        if data.is_empty() {
+            if flags & (TCP_FIN | TCP_RST) != 0 {
+                self.flush_stream(src, dst);
+            }
+            return;
+        }
+        // no syn in the capture, start from whatever comes first
+        let stream = self.streams.entry((src, dst)).or_insert(Stream {
+            base: seq,
+            ..Stream::default()
+        });
+        let rel = seq.wrapping_sub(stream.base);
+        // before the start, eg a retransmission of data the
+        // capture missed the first time
+        if rel > u32::MAX / 2 {
+            return;
+        }
+        let slot = stream.pending.entry(rel).or_default();
+        if data.len() > slot.len() {
+            *slot = data.to_vec();
+        }
+
+        // everything that is now in order goes out with this timestamp
+        let mut delivered = vec![];
+        while let Some(entry) = stream.pending.first_entry() {
+            let rel = *entry.key();
+            if rel > stream.next {
+                break;
+            }
+            let data = entry.remove();
+            let end = rel + data.len() as u32;
+            if end > stream.next {
+                delivered.extend_from_slice(&data[(stream.next - rel) as usize..]);
+                stream.next = end;
+            }
+        }
+        if !delivered.is_empty() {
+            self.messages.push(Message {
+                protocol: Protocol::Tcp,
+                src,
+                dst,
+                time,
+                data: delivered,
+            });
+        }
+    }
+
+    /// forgets a stream, warning about data stuck behind a gap
+    fn flush_stream(&mut self, src: SocketAddr, dst: SocketAddr) {
+        if let Some(stream) = self.streams.remove(&(src, dst)) {
+            let lost: usize = stream.pending.values().map(Vec::len).sum();
+            if lost > 0 {
+                log::warn!(
+                    "{lost} bytes from {src} to {dst} dropped, missing tcp data before them"
+                );
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<Message> {
+        let keys: Vec<_> = self.streams.keys().copied().collect();
+        for (src, dst) in keys {
+            self.flush_stream(src, dst);
+        }
+        // stable, so equal timestamps keep the file order
+        self.messages.sort_by_key(|m| m.time);
+        self.messages
+    }
+}
+
+/// the ip packet inside a link layer frame
+fn link_payload(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+    match linktype {
+        // ethernet
+        1 => {
+            let mut at = 12;
+            let mut ethertype = u16::from_be_bytes(frame.get(at..at + 2)?.try_into().ok()?);
+            // 802.1Q / 802.1ad tags
+            while ethertype == 0x8100 || ethertype == 0x88A8 {
+                at += 4;
+                ethertype = u16::from_be_bytes(frame.get(at..at + 2)?.try_into().ok()?);
+            }
+            matches!(ethertype, 0x0800 | 0x86DD).then(|| &frame[at + 2..])
+        }
+        // bsd loopback, 4 bytes of address family
+        0 | 108 => frame.get(4..),
+        // raw ip
+        101 | 12 | 14 | 228 | 229 => Some(frame),
+        // linux cooked v1 and v2
+        113 => frame.get(16..),
+        276 => frame.get(20..),
+        _ => None,
+    }
+}
+
+/// (protocol, source, destination, payload) of an ipv4 or ipv6 packet,
+/// None for fragments and anything unreadable
+fn ip_payload(ip: &[u8]) -> Option<(u8, IpAddr, IpAddr, &[u8])> {
+    match ip.first()? >> 4 {
+        4 => {
+            let ihl = (ip[0] & 0x0f) as usize * 4;
+            if ip.len() < 20 || ihl < 20 {
+                return None;
+            }
+            // more fragments or not the first one
+            if u16::from_be_bytes([ip[6], ip[7]]) & 0x3fff != 0 {
+                return None;
+            }
+            // 0 when the nic does segmentation offload
+            let total = match u16::from_be_bytes([ip[2], ip[3]]) as usize {
+                0 => ip.len(),
+                n => n.min(ip.len()),
+            };
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(&ip[12..16]).ok()?);
+            let dst = Ipv4Addr::from(<[u8; 4]>::try_from(&ip[16..20]).ok()?);
+            Some((ip[9], src.into(), dst.into(), ip.get(ihl..total)?))
+        }
+        6 => {
+            if ip.len() < 40 {
+                return None;
+            }
+            let end = (40 + u16::from_be_bytes([ip[4], ip[5]]) as usize).min(ip.len());
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(&ip[8..24]).ok()?);
+            let dst = Ipv6Addr::from(<[u8; 16]>::try_from(&ip[24..40]).ok()?);
+            let mut next = ip[6];
+            let mut at = 40;
+            // hop by hop, routing, destination options
+            while matches!(next, 0 | 43 | 60) {
+                let ext = ip.get(at..at + 2)?;
+                next = ext[0];
+                at += (ext[1] as usize + 1) * 8;
+            }
+            Some((next, src.into(), dst.into(), ip.get(at..end)?))
+        }
+        _ => None,
+    }
+}
+
+/// a running replay of some messages plus its counters
+#[derive(Debug)]
+pub struct Replay {
+    messages: Vec<Message>,
+    timing: Timing,
+    started: Instant,
+
+    // index of the next message to hand out
+    next: usize,
+    done: bool,
+
+    /// sends reported ok by the owner
+    pub sent: u64,
+
+    /// sends reported failed by the owner
+    pub failed: u64,
+}
+
+impl Replay {
+    /// `messages` in time order as from parse(), the first one is due at `now`
+    pub fn new(messages: Vec<Message>, timing: Timing, now: Instant) -> Result<Self, String> {
+        if messages.is_empty() {
+            return Err("nothing to replay".to_string());
+        }
+        match timing {
+            Timing::Speed(s) if !(s.is_finite() && s > 0.0) => {
+                return Err(format!("invalid speed {s}, it must be above 0"));
+            }
+            Timing::Interval(d) if d.is_zero() => {
+                return Err("the interval must be at least 1 ms".to_string());
+            }
+            _ => {}
+        }
+        Ok(Replay {
+            messages,
+            timing,
+            started: now,
+            next: 0,
+            done: false,
+            sent: 0,
+            failed: 0,
+        })
+    }
+
+    /// None if it is too far off for an Instant, eg at a tiny speed
+    fn due(&self, index: usize) -> Option<Instant> {
+        let gap = self.messages[index]
+            .time
+            .saturating_sub(self.messages[0].time);
+        let offset = match self.timing {
+            Timing::Original => Some(gap),
+            Timing::Speed(s) => Duration::try_from_secs_f64(gap.as_secs_f64() / s).ok(),
+            Timing::Interval(d) => u32::try_from(index).ok().and_then(|i| d.checked_mul(i)),
+        };
+        offset.and_then(|d| self.started.checked_add(d))
+    }
+
+    /// the next message if it is due at `now`, call again until None,
+    /// a late poll catches up on everything due since, a message
+    /// that would never be due ends the replay
+    pub fn poll(&mut self, now: Instant) -> Option<Message> {
+        if self.done {
+            return None;
+        }
+        let Some(due) = self.due(self.next) else {
+            log::warn!("replay stopped, message {} is too far off", self.next + 1);
+            self.done = true;
+            return None;
+        };
+        if now < due {
+            return None;
+        }
+        let msg = self.messages[self.next].clone();
+        self.next += 1;
+        self.done = self.next == self.messages.len();
+        Some(msg)
+    }
+
+    /// how long until the next message, None once done
+    pub fn time_to_next(&self, now: Instant) -> Option<Duration> {
+        match self.done {
+            true => None,
+            // never due, the next poll ends it
+            false => Some(
+                self.due(self.next)
+                    .map_or(Duration::ZERO, |due| due.saturating_duration_since(now)),
+            ),
+        }
+    }
+
+    /// (messages handed out, total)
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.messages.len())
+    }
+
+    /// count the outcome of one send
+    pub fn record(&mut self, ok: bool) {
+        if ok {
+            self.sent += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+
+    /// no more messages, the counters stay
+    pub fn stop(&mut self) {
+        self.done = true;
+    }
+
+    /// true after a stop() or once every message went out
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Local, TimeZone};
+
+    use crate::capture::Capture;
+    use crate::tcp::TcpEvent;
+    use crate::udp::UdpEvent;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip_through_capture() {
+        let t0 = Local.timestamp_opt(1_700_000_000, 0).unwrap();
+        let t1 = Local.timestamp_opt(1_700_000_001, 500_000_000).unwrap();
+        let local = addr("10.0.0.1:5000");
+        let peer = addr("10.0.0.2:6000");
+        let mut cap = Capture::new(vec![]).unwrap();
+        cap.write_udp(
+            local,
+            &UdpEvent::Sent {
+                dst: peer,
+                data: b"ping".to_vec(),
+                timestamp: t0,
+            },
+        )
+        .unwrap();
+        cap.write_tcp(&TcpEvent::Packet {
+            peer,
+            local,
+            data: b"hello".to_vec(),
+            timestamp: t1,
+        })
+        .unwrap();
+        let file = cap.finish().unwrap();
+
+        let messages = parse(&file).unwrap();
+        assert_eq!(
+            messages,
+            [
+                Message {
+                    protocol: Protocol::Udp,
+                    src: local,
+                    dst: peer,
+                    time: Duration::from_secs(1_700_000_000),
+                    data: b"ping".to_vec(),
+                },
+                Message {
+                    protocol: Protocol::Tcp,
+                    src: peer,
+                    dst: local,
+                    time: Duration::from_millis(1_700_000_001_500),
+                    data: b"hello".to_vec(),
+                },
+            ]
+        );
+
+        let filter = Filter {
+            dst_port: Some(5000),
+            ..Filter::default()
+        };
+        let kept: Vec<_> = messages.iter().filter(|m| filter.matches(m)).collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].protocol, Protocol::Tcp);
+
+        assert!(parse(b"nope").is_err());
+        // a record cut short is left out, the complete ones are kept
+        assert_eq!(parse(&file[..file.len() - 2]).unwrap(), messages[..1]);
+    }
+
+    /// classic pcap, raw ip, one tcp segment in ipv4
+    fn raw_tcp(seq: u32, flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend((40 + data.len() as u16).to_be_bytes());
+        ip.extend([0, 0, 0x40, 0, 64, 6, 0, 0, 192, 168, 0, 1, 192, 168, 0, 2]);
+        ip.extend(1234u16.to_be_bytes());
+        ip.extend(80u16.to_be_bytes());
+        ip.extend(seq.to_be_bytes());
+        ip.extend([0, 0, 0, 0, 5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        ip.extend(data);
+        ip
+    }
+
+    #[test]
+    fn test_tcp_reassembly() {
+        let mut file = vec![];
+        file.extend(PCAP_MICRO.to_be_bytes()); // big endian file
+        file.extend([0, 2, 0, 4]);
+        file.extend([0; 8]);
+        file.extend(65535u32.to_be_bytes());
+        file.extend(101u32.to_be_bytes()); // raw ip
+        let base = 1000u32;
+        let segments = [
+            raw_tcp(base, TCP_SYN, b""),
+            raw_tcp(base + 1, 0x18, b"abc"),
+            raw_tcp(base + 7, 0x18, b"gh"),  // early
+            raw_tcp(base + 1, 0x18, b"abc"), // retransmission
+            raw_tcp(base + 4, 0x18, b"def"), // fills the gap
+            raw_tcp(base + 20, 0x18, b"zz"), // never completed
+        ];
+        for (i, seg) in segments.iter().enumerate() {
+            file.extend((i as u32).to_be_bytes());
+            file.extend(0u32.to_be_bytes());
+            file.extend((seg.len() as u32).to_be_bytes());
+            file.extend((seg.len() as u32).to_be_bytes());
+            file.extend(seg);
+        }
+
+        let messages = parse(&file).unwrap();
+        let data: Vec<&[u8]> = messages.iter().map(|m| m.data.as_slice()).collect();
+        assert_eq!(data, [b"abc".as_slice(), b"defgh"]);
+        assert_eq!(messages[1].time, Duration::from_secs(4));
+        assert_eq!(messages[0].dst, addr("192.168.0.2:80"));
+    }
+
+    fn message(secs: u64) -> Message {
+        Message {
+            protocol: Protocol::Udp,
+            src: addr("127.0.0.1:1"),
+            dst: addr("127.0.0.1:2"),
+            time: Duration::from_secs(secs),
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn test_timing() {
+        let t0 = Instant::now();
+        let s = Duration::from_secs;
+        let messages = vec![message(100), message(102), message(110)];
+
+        let mut r = Replay::new(messages.clone(), Timing::Speed(2.0), t0).unwrap();
+        assert!(r.poll(t0).is_some());
+        assert_eq!(r.poll(t0 + s(0)), None);
+        assert_eq!(r.time_to_next(t0), Some(s(1)));
+        // late, both go
+        assert!(r.poll(t0 + s(5)).is_some());
+        assert!(r.poll(t0 + s(5)).is_some());
+        assert!(r.is_done());
+        assert_eq!(r.progress(), (3, 3));
+
+        let mut r = Replay::new(messages.clone(), Timing::Interval(s(1)), t0).unwrap();
+        let sent = (0..4).filter_map(|i| r.poll(t0 + s(i))).count();
+        assert_eq!(sent, 3);
+
+        let mut r = Replay::new(messages.clone(), Timing::Original, t0).unwrap();
+        r.poll(t0);
+        assert_eq!(r.time_to_next(t0), Some(s(2)));
+        r.stop();
+        assert_eq!(r.poll(t0 + s(60)), None);
+
+        assert!(Replay::new(vec![], Timing::Original, t0).is_err());
+        assert!(Replay::new(messages.clone(), Timing::Speed(0.0), t0).is_err());
+        assert!(Replay::new(messages.clone(), Timing::Interval(Duration::ZERO), t0).is_err());
+    }
+
+    #[test]
+    fn test_timing_out_of_range() {
+        let t0 = Instant::now();
+        let messages = vec![message(100), message(102)];
+
+        // 2 s at this speed is beyond any Instant, the replay ends
+        let mut r = Replay::new(messages.clone(), Timing::Speed(1e-300), t0).unwrap();
+        assert!(r.poll(t0).is_some());
+        assert_eq!(r.poll(t0), None);
+        assert!(r.is_done());
+
+        let mut r = Replay::new(messages, Timing::Interval(Duration::MAX), t0).unwrap();
+        assert!(r.poll(t0).is_some());
+        assert_eq!(r.poll(t0), None);
+        assert!(r.is_done());
+    }
+}
@@ -0,0 +1,312 @@
+//! periodic sending, eg for overnight soak tests
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/*
+    the scheduler does not send anything by itself, it only
+    tells its owner when a send is due, so it works with any
+    mix of engines, the GUI drives it from a thread of its own
+    so that a minimized window does not hold it up
+
+        while let Some(seq) = scheduler.poll(Instant::now()) {
+            let ok = send(&scheduler.payload(&base, seq));
+            scheduler.record(ok);
+        }
+
+    the sends are planned at start + n * interval, a late poll
+    catches up instead of drifting, unless it is so late (a
+    sleeping laptop) that the missed ones are better skipped,
+    they are counted in `skipped` for the owner to report
+*/
+
+/// a poll this many intervals late skips the missed sends
+const MAX_CATCH_UP: u32 = 100;
+
+/// the longest interval Scheduler::new() accepts
+pub const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// what is appended to every payload, after a space
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Stamp {
+    /// the payload goes as is
+    #[default]
+    None,
+
+    /// 1, 2, 3... as text
+    Counter,
+
+    /// local time with milliseconds, eg 2024-05-01T22:10:03.123
+    Timestamp,
+}
+
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Stamp::None => "None",
+            Stamp::Counter => "Counter",
+            Stamp::Timestamp => "Timestamp",
+        };
+        f.write_str(s)
+    }
+}
+
+impl Stamp {
+    /// for combo boxes
+    pub const ALL: [Stamp; 3] = [Stamp::None, Stamp::Counter, Stamp::Timestamp];
+}
+
+/// when to send and when to stop
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    /// time between two sends, the first one goes right away
+    pub interval: Duration,
+
+    /// stop after this many sends, 0 for no limit
+    pub max_sends: u64,
+
+    /// stop after this long, None for no limit
+    pub max_duration: Option<Duration>,
+
+    /// see Stamp
+    pub stamp: Stamp,
+}
+
+/// a running Schedule plus its counters
+#[derive(Debug)]
+pub struct Scheduler {
+    schedule: Schedule,
+    started: Instant,
+    next: Instant,
+
+    // sends handed out by poll() so far
+    seq: u64,
+    done: bool,
+
+    /// sends reported ok by the owner
+    pub sent: u64,
+
+    /// sends reported failed by the owner
+    pub failed: u64,
+
+    /// planned sends left out after a long pause, see MAX_CATCH_UP
+    pub skipped: u64,
+}
+
+impl Scheduler {
+    /// `interval` must not be zero nor above MAX_INTERVAL
+    pub fn new(schedule: Schedule, now: Instant) -> Result<Self, String> {
+        if schedule.interval.is_zero() {
+            return Err("the interval must be at least 1 ms".to_string());
+        }
+        if schedule.interval > MAX_INTERVAL {
+            return Err(format!(
+                "the interval must be at most {} h",
+                MAX_INTERVAL.as_secs() / 3600
+            ));
+        }
+        Ok(Scheduler {
+            schedule,
+            started: now,
+            next: now,
+            seq: 0,
+            done: false,
+            sent: 0,
+            failed: 0,
+            skipped: 0,
+        })
+    }
+
+    /// Some(sequence number, from 1) if a send is due at `now`,
+    /// call again until None, there can be several after a slow frame
+    pub fn poll(&mut self, now: Instant) -> Option<u64> {
+        if self.done {
+            return None;
+        }
+        if let Some(max) = self.schedule.max_duration
+            && now.duration_since(self.started) >= max
+        {
+            self.done = true;
+            return None;
+        }
+        if now < self.next {
+            return None;
+        }
+
+        // an Instant can overflow, this send is the last one then
+        let interval = self.schedule.interval;
+        match self.next.checked_add(interval) {
+            Some(next) => {
+                self.next = next;
+                let too_late = interval
+                    .checked_mul(MAX_CATCH_UP)
+                    .and_then(|late| next.checked_add(late))
+                    .is_some_and(|late| now > late);
+                if too_late {
+                    // every planned send from `next` to `now`
+                    let missed = now.duration_since(next).as_nanos() / interval.as_nanos() + 1;
+                    self.skipped = self
+                        .skipped
+                        .saturating_add(u64::try_from(missed).unwrap_or(u64::MAX));
+                    self.next = now.checked_add(interval).unwrap_or(next);
+                }
+            }
+            None => self.done = true,
+        }
+        self.seq += 1;
+        if self.schedule.max_sends != 0 && self.seq >= self.schedule.max_sends {
+            self.done = true;
+        }
+        Some(self.seq)
+    }
+
+    /// how long until the next send, None once done
+    pub fn time_to_next(&self, now: Instant) -> Option<Duration> {
+        (!self.done).then(|| self.next.saturating_duration_since(now))
+    }
+
+    /// base payload + the stamp for send number `seq`
+    pub fn payload(&self, base: &[u8], seq: u64) -> Vec<u8> {
+        let stamp = match self.schedule.stamp {
+            Stamp::None => return base.to_vec(),
+            Stamp::Counter => seq.to_string(),
+            Stamp::Timestamp => chrono::Local::now()
+                .format("%Y-%m-%dT%H:%M:%S%.3f")
+                .to_string(),
+        };
+        [base, b" ", stamp.as_bytes()].concat()
+    }
+
+    /// count the outcome of one send
+    pub fn record(&mut self, ok: bool) {
+        if ok {
+            self.sent += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+
+    /// no more sends, the counters stay
+    pub fn stop(&mut self) {
+        self.done = true;
+    }
+
+    /// true after a stop() or once a limit is reached
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(max_sends: u64, max_duration: Option<Duration>) -> Schedule {
+        Schedule {
+            interval: Duration::from_millis(100),
+            max_sends,
+            max_duration,
+            stamp: Stamp::Counter,
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_interval_and_max_sends() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(schedule(3, None), t0).unwrap();
+        assert_eq!(s.poll(t0), Some(1));
+        assert_eq!(s.poll(t0), None);
+        assert_eq!(s.time_to_next(t0 + ms(40)), Some(ms(60)));
+        assert_eq!(s.poll(t0 + ms(99)), None);
+        assert_eq!(s.poll(t0 + ms(100)), Some(2));
+
+        // a late poll catches up on the missed send only
+        assert_eq!(s.poll(t0 + ms(250)), Some(3));
+        assert!(s.is_done());
+        assert_eq!(s.poll(t0 + ms(1000)), None);
+        assert_eq!(s.time_to_next(t0), None);
+    }
+
+    #[test]
+    fn test_max_duration_and_stop() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(schedule(0, Some(ms(250))), t0).unwrap();
+        let seqs: Vec<u64> = [0, 100, 200, 300]
+            .into_iter()
+            .filter_map(|t| s.poll(t0 + ms(t)))
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+        assert!(s.is_done());
+
+        let mut s = Scheduler::new(schedule(0, None), t0).unwrap();
+        s.poll(t0);
+        s.stop();
+        assert_eq!(s.poll(t0 + ms(100)), None);
+    }
+
+    #[test]
+    fn test_skips_after_a_long_pause() {
+        let t0 = Instant::now();
+        let mut s = Scheduler::new(schedule(0, None), t0).unwrap();
+        s.poll(t0);
+        let late = t0 + Duration::from_secs(60);
+        assert_eq!(s.poll(late), Some(2));
+        assert_eq!(s.poll(late), None);
+        assert_eq!(s.time_to_next(late), Some(ms(100)));
+        // the ones planned from 200 ms to 60 s
+        assert_eq!(s.skipped, 599);
+    }
+
+    #[test]
+    fn test_payload_and_counters() {
+        let s = Scheduler::new(schedule(0, None), Instant::now()).unwrap();
+        assert_eq!(s.payload(b"ping", 7), b"ping 7");
+
+        let mut s = Scheduler::new(
+            Schedule {
+                stamp: Stamp::Timestamp,
+                ..schedule(0, None)
+            },
+            Instant::now(),
+        )
+        .unwrap();
+        // "ping " + 23 chars of timestamp
+        assert_eq!(s.payload(b"ping", 1).len(), 5 + 23);
+
+        s.record(true);
+        s.record(false);
+        s.record(true);
+        assert_eq!((s.sent, s.failed), (2, 1));
+
+        assert!(
+            Scheduler::new(
+                Schedule {
+                    interval: Duration::ZERO,
+                    ..schedule(0, None)
+                },
+                Instant::now()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_interval_bounds() {
+        let with = |interval| {
+            Scheduler::new(
+                Schedule {
+                    interval,
+                    ..schedule(0, None)
+                },
+                Instant::now(),
+            )
+        };
+        assert!(with(MAX_INTERVAL).is_ok());
+        assert!(with(MAX_INTERVAL + ms(1)).is_err());
+        assert!(with(Duration::MAX).is_err());
+    }
+}
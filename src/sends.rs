@@ -0,0 +1,569 @@
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use udptcp::bridge::Bridge;
+use udptcp::replay::{Protocol, Replay};
+use udptcp::schedule::Scheduler;
+use udptcp::tcp::{TcpClient, TcpClientSender, TcpServer, TcpServerSender};
+use udptcp::udp::{Udp, UdpSender};
+
+use crate::gui::Targets;
+
+/*
+    sends that do not wait for the GUI
+
+    a minimized window gets few frames or none at all (up to the
+    OS), so the scheduler, the replay and the bridge run on threads
+    of their own and send through cloned engine handles, those
+    follow reconnects and rebinds by themselves, the GUI only locks
+    them for the counters
+
+    the bridge reads what the engines receive from their taps, a
+    copy of the events the GUI polls, so both see every message
+
+    what a send depends on in the GUI (the udp destination and the
+    peers ticked in the server column) is copied in by every frame,
+    those fields only change in a frame anyway
+
+    the sends that went out come back as engine events like any
+    other, the failed ones over a channel of their own for the stats
+*/
+
+/// a worker sleeps at most this long between checks for a stop
+const STEP: Duration = Duration::from_millis(50);
+
+/// which connection a send failed on
+pub enum Leg {
+    Udp,
+    TcpClient,
+    Peer(SocketAddr),
+}
+
+/// a failed send and when it failed, see Outlets::new()
+pub type Failure = (Leg, chrono::DateTime<chrono::Local>);
+
+/// the GUI fields the sends depend on, see Outlets::set_route()
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Route {
+    /// host:port of the udp sends
+    pub udp: String,
+    /// the ticked peers of the tcp server
+    pub peers: HashSet<SocketAddr>,
+    /// every peer of the tcp server instead of the ticked ones
+    pub all_peers: bool,
+}
+
+impl Route {
+    /// true if a send to the selected peers goes to `peer`
+    fn is_target(&self, peer: SocketAddr) -> bool {
+        self.all_peers || self.peers.contains(&peer)
+    }
+}
+
+/// the send handles of every engine, cheap to clone into a worker
+#[derive(Clone)]
+pub struct Outlets {
+    udp: UdpSender,
+    tcp_client: TcpClientSender,
+    tcp_server: TcpServerSender,
+    route: Arc<Mutex<Route>>,
+    failures: mpsc::Sender<Failure>,
+}
+
+impl Outlets {
+    /// the receiver gets every failed send, from whichever thread
+    pub fn new(
+        udp: UdpSender,
+        tcp_client: TcpClientSender,
+        tcp_server: TcpServerSender,
+    ) -> (Self, mpsc::Receiver<Failure>) {
+        let (failures, rx) = mpsc::channel();
+        let outlets = Outlets {
+            udp,
+            tcp_client,
+            tcp_server,
+            route: Arc::default(),
+            failures,
+        };
+        (outlets, rx)
+    }
+
+    /// called every frame, only copied when it changed
+    pub fn set_route(&self, route: Route) {
+        let mut current = self.route.lock().unwrap_or_else(PoisonError::into_inner);
+        if *current != route {
+            *current = route;
+        }
+    }
+
+    /// returns (ok, failed) sends, the engines log the failures
+    /// and the successful ones come back as events for the stats
+    pub fn send(&self, data: &[u8], targets: Targets) -> (u64, u64) {
+        let (udp_to, peers) = {
+            let route = self.route.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut peers = vec![];
+            if targets.tcp_server && self.tcp_server.is_up() {
+                peers = self.tcp_server.peers();
+                peers.retain(|peer| route.is_target(*peer));
+            }
+            (route.udp.clone(), peers)
+        };
+        let mut results = vec![];
+
+        /*** UDP send handling ***/
+        if targets.udp && self.udp.is_up() {
+            let result = self.udp.send_data_to(data, &udp_to);
+            self.count(Leg::Udp, &result);
+            results.push(result);
+        }
+
+        /* TCP client send handling */
+        if targets.tcp_client && self.tcp_client.is_up() {
+            let result = self.tcp_client.send_data(data);
+            self.count(Leg::TcpClient, &result);
+            results.push(result);
+        }
+
+        /* TCP host send handling */
+        for peer in peers {
+            results.push(self.send_to_peer(data, peer));
+        }
+
+        let ok = results.iter().filter(|r| r.is_ok()).count() as u64;
+        (ok, results.len() as u64 - ok)
+    }
+
+    /// None if the udp socket is down
+    fn send_udp_to(&self, data: &[u8], to: &str) -> Option<io::Result<()>> {
+        if !self.udp.is_up() {
+            return None;
+        }
+        let result = self.udp.send_data_to(data, to);
+        self.count(Leg::Udp, &result);
+        Some(result)
+    }
+
+    /// one tcp server client only
+    pub fn send_to_peer(&self, data: &[u8], peer: SocketAddr) -> io::Result<()> {
+        let result = self.tcp_server.send_data_to(data, peer);
+        self.count(Leg::Peer(peer), &result);
+        result
+    }
+
+    fn count(&self, leg: Leg, result: &io::Result<()>) {
+        if result.is_err() {
+            let _ = self.failures.send((leg, chrono::Local::now()));
+        }
+    }
+}
+
+/// a Scheduler (or the like) driven by a thread of its own, the GUI
+/// locks it for the counters or to stop it, dropping it stops the thread
+pub struct Worker<T> {
+    state: Arc<Mutex<T>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    /// `run` gets the state and a flag set when the worker is dropped
+    fn spawn(state: T, run: impl FnOnce(&Mutex<T>, &AtomicBool) + Send + 'static) -> Self {
+        let state = Arc::new(Mutex::new(state));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (state_clone, stop_clone) = (state.clone(), stop.clone());
+        thread::spawn(move || run(&state_clone, &stop_clone));
+        Worker { state, stop }
+    }
+}
+
+impl<T> Worker<T> {
+    /// held by the worker only between its sends, never during one
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// sends `payload` to `targets` whenever `scheduler` says so, until it
+/// is done or stopped, a send that reaches none of the targets fails
+pub fn schedule(
+    scheduler: Scheduler,
+    payload: Vec<u8>,
+    targets: Targets,
+    outlets: Outlets,
+) -> Worker<Scheduler> {
+    Worker::spawn(scheduler, move |scheduler, stop| {
+        let lock = || scheduler.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let mut s = lock();
+            // stopped from the GUI, which logs it
+            if s.is_done() {
+                return;
+            }
+            let skipped = s.skipped;
+            let Some(seq) = s.poll(Instant::now()) else {
+                match s.time_to_next(Instant::now()) {
+                    Some(wait) => {
+                        drop(s);
+                        thread::sleep(wait.min(STEP));
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+            if s.skipped > skipped {
+                log::warn!(
+                    "scheduler fell behind, {} sends skipped",
+                    s.skipped - skipped
+                );
+            }
+            let data = s.payload(&payload, seq);
+            drop(s);
+
+            let (ok, failed) = outlets.send(&data, targets);
+            // none of the targets is up (or no client selected)
+            let failed = if ok + failed == 0 { 1 } else { failed };
+            let mut s = lock();
+            (0..ok).for_each(|_| s.record(true));
+            (0..failed).for_each(|_| s.record(false));
+            if s.is_done() {
+                break;
+            }
+        }
+        let s = lock();
+        log::info!(
+            "scheduler done, {} sent, {} failed, {} skipped",
+            s.sent,
+            s.failed,
+            s.skipped
+        );
+    })
+}
+
+/// sends the messages of `replay` as they are due, udp ones to the
+/// udp destination and tcp ones through the tcp client
+pub fn replay(replay: Replay, outlets: Outlets) -> Worker<Replay> {
+    Worker::spawn(replay, move |replay, stop| {
+        let lock = || replay.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let mut r = lock();
+            // stopped from the GUI, which logs it
+            if r.is_done() {
+                return;
+            }
+            let Some(msg) = r.poll(Instant::now()) else {
+                match r.time_to_next(Instant::now()) {
+                    Some(wait) => {
+                        drop(r);
+                        thread::sleep(wait.min(STEP));
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+            drop(r);
+
+            let targets = Targets {
+                udp: msg.protocol == Protocol::Udp,
+                tcp_client: msg.protocol == Protocol::Tcp,
+                tcp_server: false,
+            };
+            let (ok, _) = outlets.send(&msg.data, targets);
+            let mut r = lock();
+            r.record(ok > 0);
+            if r.is_done() {
+                break;
+            }
+        }
+        let r = lock();
+        log::info!("replay done, {} sent, {} failed", r.sent, r.failed);
+    })
+}
+
+/// the next event of a tap, unless stopped or the tap was replaced
+fn next<E>(tap: &mpsc::Receiver<E>, stop: &AtomicBool) -> Option<E> {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        match tap.recv_timeout(STEP) {
+            Ok(ev) => return Some(ev),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// a bridge and the tcp ends it passes datagrams on to
+/// (the udp flag is unused), the GUI updates both while on
+pub struct Bridging {
+    pub bridge: Bridge,
+    pub targets: Targets,
+}
+
+/// forwards between `udp` and both tcp ends until dropped, the taps
+/// set here stay until replaced, see their set_tap()
+pub fn bridge(
+    bridging: Bridging,
+    udp: &Udp,
+    tcp_client: &TcpClient,
+    tcp_server: &TcpServer,
+    outlets: Outlets,
+) -> Worker<Bridging> {
+    let (udp_tap, from_udp) = mpsc::channel();
+    let (tcp_tap, from_tcp) = mpsc::channel();
+    udp.set_tap(Some(udp_tap));
+    tcp_client.set_tap(Some(tcp_tap.clone()));
+    tcp_server.set_tap(Some(tcp_tap));
+
+    Worker::spawn(bridging, move |bridging, stop| {
+        let lock = || bridging.lock().unwrap_or_else(PoisonError::into_inner);
+        let outlets = &outlets;
+        thread::scope(|s| {
+            s.spawn(move || {
+                while let Some(ev) = next(&from_udp, stop) {
+                    let (data, targets) = {
+                        let mut b = lock();
+                        let Some(data) = b.bridge.from_udp(&ev) else {
+                            continue;
+                        };
+                        (data.to_vec(), b.targets)
+                    };
+                    let targets = Targets {
+                        udp: false,
+                        ..targets
+                    };
+                    let (ok, failed) = outlets.send(&data, targets);
+                    if ok + failed == 0 {
+                        log::warn!("[BRIDGE] no TCP connection to pass the datagram on to");
+                    }
+                    lock().bridge.record(true, ok > 0 && failed == 0);
+                }
+            });
+
+            while let Some(ev) = next(&from_tcp, stop) {
+                let Some((dst, data)) = lock()
+                    .bridge
+                    .from_tcp(&ev)
+                    .map(|(dst, data)| (dst, data.to_vec()))
+                else {
+                    continue;
+                };
+                let ok = match outlets.send_udp_to(&data, &dst) {
+                    Some(result) => result.is_ok(),
+                    None => {
+                        log::warn!("[BRIDGE] UDP is not running, message for {dst} dropped");
+                        false
+                    }
+                };
+                lock().bridge.record(false, ok);
+            }
+        });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::net::UdpSocket;
+    use udptcp::bridge::UdpTarget;
+    use udptcp::replay::{Message, Timing};
+    use udptcp::schedule::{Schedule, Stamp};
+    use udptcp::tcp::{TcpClient, TcpServer};
+    use udptcp::udp::Udp;
+
+    const UDP: Targets = Targets {
+        udp: true,
+        tcp_client: false,
+        tcp_server: false,
+    };
+
+    /// a running udp engine and a socket its sends go to, nothing
+    /// polls the engines, the tcp ones are never started
+    struct Setup {
+        udp: Udp,
+        peer: UdpSocket,
+        outlets: Outlets,
+        failures: mpsc::Receiver<Failure>,
+    }
+
+    fn setup() -> Setup {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut udp = Udp::default();
+        udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+        let (client, server) = (TcpClient::default(), TcpServer::default());
+        let (outlets, failures) = Outlets::new(udp.sender(), client.sender(), server.sender());
+        outlets.set_route(Route {
+            udp: peer.local_addr().unwrap().to_string(),
+            ..Route::default()
+        });
+        Setup {
+            udp,
+            peer,
+            outlets,
+            failures,
+        }
+    }
+
+    fn recv(peer: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let (len, _) = peer.recv_from(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn scheduler(max_sends: u64) -> Scheduler {
+        let schedule = Schedule {
+            interval: Duration::from_millis(10),
+            max_sends,
+            max_duration: None,
+            stamp: Stamp::Counter,
+        };
+        Scheduler::new(schedule, Instant::now()).unwrap()
+    }
+
+    /// until `n` (sent, failed) are counted, the last
+    /// send is counted after the worker is done
+    fn wait_counted<T>(
+        worker: &Worker<T>,
+        n: u64,
+        counted: impl Fn(&T) -> (u64, u64),
+    ) -> (u64, u64) {
+        let until = Instant::now() + Duration::from_secs(2);
+        loop {
+            let (sent, failed) = counted(&worker.lock());
+            if sent + failed >= n || Instant::now() > until {
+                return (sent, failed);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_schedule_sends_without_the_gui() {
+        let Setup {
+            udp: _udp,
+            peer,
+            outlets,
+            failures,
+        } = setup();
+        let worker = schedule(scheduler(3), b"ping".to_vec(), UDP, outlets.clone());
+        for n in 1..=3 {
+            assert_eq!(recv(&peer), format!("ping {n}").as_bytes());
+        }
+        assert_eq!(wait_counted(&worker, 3, |s| (s.sent, s.failed)), (3, 0));
+
+        // none of the targets is up, then a udp send that fails
+        let none = Targets {
+            udp: false,
+            tcp_client: true,
+            tcp_server: true,
+        };
+        let worker = schedule(scheduler(2), b"ping".to_vec(), none, outlets.clone());
+        assert_eq!(wait_counted(&worker, 2, |s| (s.sent, s.failed)), (0, 2));
+        assert_eq!(failures.try_iter().count(), 0);
+
+        outlets.set_route(Route::default());
+        assert_eq!(outlets.send(b"ping", UDP), (0, 1));
+        assert!(matches!(failures.try_recv(), Ok((Leg::Udp, _))));
+    }
+
+    #[test]
+    fn test_replay_without_the_gui() {
+        let Setup {
+            udp: _udp,
+            peer,
+            outlets,
+            ..
+        } = setup();
+        let message = |protocol, data: &[u8]| Message {
+            protocol,
+            src: "10.0.0.1:1000".parse().unwrap(),
+            dst: "10.0.0.2:2000".parse().unwrap(),
+            time: Duration::ZERO,
+            data: data.to_vec(),
+        };
+        let messages = vec![
+            message(Protocol::Udp, b"a"),
+            message(Protocol::Tcp, b"b"),
+            message(Protocol::Udp, b"c"),
+        ];
+        let timing = Timing::Interval(Duration::from_millis(10));
+        let replay = Replay::new(messages, timing, Instant::now()).unwrap();
+
+        // the tcp client is down, its message fails
+        let worker = super::replay(replay, outlets);
+        assert_eq!(recv(&peer), b"a");
+        assert_eq!(recv(&peer), b"c");
+        assert_eq!(wait_counted(&worker, 3, |r| (r.sent, r.failed)), (2, 1));
+        assert!(worker.lock().is_done());
+    }
+
+    #[test]
+    fn test_bridge_without_the_gui() {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut udp = Udp::default();
+        let udp_port = udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+        let mut server = TcpServer::default();
+        let tcp_port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{tcp_port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let client = TcpClient::default();
+        let (outlets, _failures) = Outlets::new(udp.sender(), client.sender(), server.sender());
+        outlets.set_route(Route {
+            all_peers: true,
+            ..Route::default()
+        });
+        let bridging = Bridging {
+            bridge: Bridge::new(UdpTarget::LastSource),
+            targets: Targets {
+                udp: false,
+                tcp_client: true,
+                tcp_server: true,
+            },
+        };
+        let worker = bridge(bridging, &udp, &client, &server, outlets);
+
+        // the server may not have taken the connection in yet
+        let mut buf = [0; 64];
+        let mut len = 0;
+        for _ in 0..40 {
+            if !server.peers().is_empty() {
+                peer.send_to(b"ping", format!("127.0.0.1:{udp_port}"))
+                    .unwrap();
+                len = stream.read(&mut buf).unwrap();
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(&buf[..len], b"ping");
+
+        // back to the last udp source
+        stream.write_all(b"pong").unwrap();
+        assert_eq!(recv(&peer), b"pong");
+        let counted = |b: &Bridging| (b.bridge.to_tcp + b.bridge.to_udp, b.bridge.failed);
+        assert_eq!(wait_counted(&worker, 2, counted), (2, 0));
+        assert_eq!(worker.lock().bridge.to_udp, 1);
+    }
+}
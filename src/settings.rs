@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// eframe storage keys
+pub const KEY_SETTINGS: &str = "settings";
+pub const KEY_PROFILES: &str = "profiles";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SendMode {
+    #[default]
+    Text,
+    Hex,
+}
+
+/// what is remembered between launches, also the content of a profile
+///
+/// values are kept as the text typed in the GUI, an invalid one
+/// is reported when it's used, same as a freshly typed one
+/// `serde(default)` lets older saves load after fields are added
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// the netif is looked up by ip first, then by name
+    /// (addresses from dhcp come and go, names usually stay)
+    pub netif_name: String,
+    pub netif_ip: String,
+
+    pub local_port_udp: String,
+    pub broadcast_ip_manual_udp: String,
+    pub multicast_group_udp: String,
+    pub remote_ip_udp: String,
+    pub remote_port_udp: String,
+    pub udp_bc: bool,
+    pub local_port_tcp_server: String,
+    pub local_port_tcp_client: String,
+    pub remote_ip_tcpserver: String,
+    pub remote_port_tcpserver: String,
+
+    pub dark_mode: bool,
+    pub send_mode: SendMode,
+}
+
+/// named settings, eg "PLC lab", "sensor rig"
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    profiles: BTreeMap<String, Settings>,
+}
+
+impl Profiles {
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.profiles.keys()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Settings> {
+        self.profiles.get(name)
+    }
+
+    /// overwrites a profile with the same name
+    pub fn save(&mut self, name: &str, settings: Settings) -> Result<(), String> {
+        let name = Self::check_name(name)?;
+        self.profiles.insert(name, settings);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), String> {
+        let to = Self::check_name(to)?;
+        if from == to {
+            return Ok(());
+        }
+        if self.profiles.contains_key(&to) {
+            return Err(format!("profile {to:?} already exists"));
+        }
+        let settings = self
+            .profiles
+            .remove(from)
+            .ok_or_else(|| format!("no profile named {from:?}"))?;
+        self.profiles.insert(to, settings);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        self.profiles
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| format!("no profile named {name:?}"))
+    }
+
+    fn check_name(name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("profile name cannot be empty".to_string());
+        }
+        Ok(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(port: &str) -> Settings {
+        Settings {
+            local_port_udp: port.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_save_and_overwrite() {
+        let mut profiles = Profiles::default();
+        profiles.save(" PLC lab ", settings("1")).unwrap();
+        profiles.save("PLC lab", settings("2")).unwrap();
+        assert_eq!(profiles.names().count(), 1);
+        assert_eq!(profiles.get("PLC lab").unwrap().local_port_udp, "2");
+        assert!(profiles.save("  ", settings("3")).is_err());
+    }
+
+    #[test]
+    fn test_rename_and_delete() {
+        let mut profiles = Profiles::default();
+        profiles.save("a", settings("1")).unwrap();
+        profiles.save("b", settings("2")).unwrap();
+
+        assert!(profiles.rename("a", "b").is_err());
+        assert!(profiles.rename("x", "y").is_err());
+        profiles.rename("a", "sensor rig").unwrap();
+        assert!(profiles.get("a").is_none());
+        assert_eq!(profiles.get("sensor rig").unwrap().local_port_udp, "1");
+
+        profiles.delete("b").unwrap();
+        assert!(profiles.delete("b").is_err());
+        assert_eq!(profiles.names().collect::<Vec<_>>(), vec!["sensor rig"]);
+    }
+}
@@ -0,0 +1,268 @@
+//! socket options for both tcp ends, see [`SocketOptions`] and [`SocketInfo`]
+
+use std::fmt;
+use std::io;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+
+/*
+    set on every connection right after connect / accept, before a
+    tls handshake, and again on the live ones when changed, so that
+    eg nagle can be switched while watching the latency
+
+    SocketInfo reads them back from the socket, the os has the last
+    word, linux for one doubles SO_RCVBUF / SO_SNDBUF and clamps them
+    to rmem_max / wmem_max, keepalive idle / interval / count cannot
+    be read on windows and show as unknown there
+
+    the buffer sizes are set after the handshake of tcp itself, the
+    window scale was agreed with the defaults by then
+*/
+
+/// keepalive timing, all three are sent to the os together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keepalive {
+    /// quiet time before the first probe (TCP_KEEPIDLE)
+    pub idle: Duration,
+    /// between unanswered probes (TCP_KEEPINTVL)
+    pub interval: Duration,
+    /// unanswered probes before the connection is dropped (TCP_KEEPCNT)
+    pub count: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Keepalive {
+            idle: Duration::from_secs(60),
+            interval: Duration::from_secs(10),
+            count: 5,
+        }
+    }
+}
+
+/// what to set on a tcp socket, the default is what the os does anyway
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SocketOptions {
+    /// TCP_NODELAY, true sends small writes right away (no Nagle)
+    pub nodelay: bool,
+    /// SO_KEEPALIVE with its timing, None is off
+    pub keepalive: Option<Keepalive>,
+    /// SO_LINGER, Some(0) resets (RST) the connection on close,
+    /// None is the usual close in the background
+    pub linger: Option<Duration>,
+    /// SO_SNDBUF in bytes, None is the os default
+    pub send_buffer: Option<usize>,
+    /// SO_RCVBUF in bytes, None is the os default
+    pub recv_buffer: Option<usize>,
+    /// IP_TTL, or the unicast hops on ipv6, None is the os default
+    pub ttl: Option<u32>,
+}
+
+impl SocketOptions {
+    /// Err for values the os would refuse or that make no sense
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ka) = &self.keepalive {
+            if ka.idle < Duration::from_secs(1) || ka.interval < Duration::from_secs(1) {
+                return Err("keepalive idle and interval are whole seconds, at least 1".into());
+            }
+            if ka.count == 0 {
+                return Err("keepalive count must be at least 1".into());
+            }
+        }
+        if self.send_buffer == Some(0) || self.recv_buffer == Some(0) {
+            return Err("buffer sizes must be at least 1".into());
+        }
+        if let Some(ttl) = self.ttl
+            && !(1..=255).contains(&ttl)
+        {
+            return Err(format!("ttl must be within 1..=255, not {ttl}"));
+        }
+        Ok(())
+    }
+
+    /// sets every option on `tcp`, stops at the first the os refuses
+    pub fn apply(&self, tcp: &TcpStream) -> io::Result<()> {
+        self.validate().map_err(io::Error::other)?;
+        let sock = SockRef::from(tcp);
+        sock.set_tcp_nodelay(self.nodelay)?;
+        match &self.keepalive {
+            Some(ka) => sock.set_tcp_keepalive(
+                &TcpKeepalive::new()
+                    .with_time(ka.idle)
+                    .with_interval(ka.interval)
+                    .with_retries(ka.count),
+            )?,
+            None => sock.set_keepalive(false)?,
+        }
+        sock.set_linger(self.linger)?;
+        if let Some(size) = self.send_buffer {
+            sock.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            sock.set_recv_buffer_size(size)?;
+        }
+        if let Some(ttl) = self.ttl {
+            match tcp.local_addr()?.is_ipv4() {
+                true => sock.set_ttl_v4(ttl)?,
+                false => sock.set_unicast_hops_v6(ttl)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the options as the os has them on a socket
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocketInfo {
+    /// TCP_NODELAY
+    pub nodelay: bool,
+    /// SO_KEEPALIVE
+    pub keepalive: bool,
+    /// TCP_KEEPIDLE, None where it cannot be read
+    pub keepalive_idle: Option<Duration>,
+    /// TCP_KEEPINTVL, None where it cannot be read
+    pub keepalive_interval: Option<Duration>,
+    /// TCP_KEEPCNT, None where it cannot be read
+    pub keepalive_count: Option<u32>,
+    /// SO_LINGER, None is off
+    pub linger: Option<Duration>,
+    /// SO_SNDBUF in bytes
+    pub send_buffer: usize,
+    /// SO_RCVBUF in bytes
+    pub recv_buffer: usize,
+    /// IP_TTL or the unicast hops
+    pub ttl: u32,
+}
+
+impl SocketInfo {
+    /// every option read back from `tcp`
+    pub fn read(tcp: &TcpStream) -> io::Result<SocketInfo> {
+        let sock = SockRef::from(tcp);
+        #[cfg(not(target_os = "windows"))]
+        let (keepalive_idle, keepalive_interval, keepalive_count) = (
+            sock.tcp_keepalive_time().ok(),
+            sock.tcp_keepalive_interval().ok(),
+            sock.tcp_keepalive_retries().ok(),
+        );
+        #[cfg(target_os = "windows")]
+        let (keepalive_idle, keepalive_interval, keepalive_count) = (None, None, None);
+        Ok(SocketInfo {
+            nodelay: sock.tcp_nodelay()?,
+            keepalive: sock.keepalive()?,
+            keepalive_idle,
+            keepalive_interval,
+            keepalive_count,
+            linger: sock.linger()?,
+            send_buffer: sock.send_buffer_size()?,
+            recv_buffer: sock.recv_buffer_size()?,
+            ttl: match tcp.local_addr()?.is_ipv4() {
+                true => sock.ttl_v4()?,
+                false => sock.unicast_hops_v6()?,
+            },
+        })
+    }
+}
+
+/// one line, eg "nodelay on, keepalive 60s/10s x5, linger off, ..."
+impl fmt::Display for SocketInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        write!(f, "nodelay {}, keepalive ", on_off(self.nodelay))?;
+        match self.keepalive {
+            false => f.write_str("off")?,
+            true => {
+                let secs = |d: Option<Duration>| match d {
+                    Some(d) => format!("{}s", d.as_secs()),
+                    None => "?".to_string(),
+                };
+                let count = self.keepalive_count.map(|c| c.to_string());
+                write!(
+                    f,
+                    "{}/{} x{}",
+                    secs(self.keepalive_idle),
+                    secs(self.keepalive_interval),
+                    count.as_deref().unwrap_or("?")
+                )?;
+            }
+        }
+        match self.linger {
+            None => f.write_str(", linger off")?,
+            Some(Duration::ZERO) => f.write_str(", linger 0 (RST)")?,
+            Some(d) => write!(f, ", linger {}s", d.as_secs())?,
+        }
+        write!(
+            f,
+            ", sndbuf {}, rcvbuf {}, ttl {}",
+            self.send_buffer, self.recv_buffer, self.ttl
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_apply_and_read_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let before = SocketInfo::read(&tcp).unwrap();
+        assert!(!before.nodelay);
+        assert!(before.linger.is_none());
+
+        let opts = SocketOptions {
+            nodelay: true,
+            keepalive: Some(Keepalive {
+                idle: Duration::from_secs(30),
+                interval: Duration::from_secs(5),
+                count: 3,
+            }),
+            linger: Some(Duration::ZERO),
+            send_buffer: Some(64 * 1024),
+            recv_buffer: Some(64 * 1024),
+            ttl: Some(17),
+        };
+        opts.apply(&tcp).unwrap();
+        let info = SocketInfo::read(&tcp).unwrap();
+        assert!(info.nodelay);
+        assert!(info.keepalive);
+        assert_eq!(info.linger, Some(Duration::ZERO));
+        assert_eq!(info.ttl, 17);
+        // the os may round them up, never down
+        assert!(info.send_buffer >= 64 * 1024);
+        assert!(info.recv_buffer >= 64 * 1024);
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(info.keepalive_idle, Some(Duration::from_secs(30)));
+        assert!(info.to_string().starts_with("nodelay on, keepalive "));
+
+        // back to the defaults
+        SocketOptions::default().apply(&tcp).unwrap();
+        let info = SocketInfo::read(&tcp).unwrap();
+        assert!(!info.nodelay && !info.keepalive && info.linger.is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(SocketOptions::default().validate(), Ok(()));
+        let bad = |opts: SocketOptions| opts.validate().is_err();
+        assert!(bad(SocketOptions {
+            ttl: Some(0),
+            ..Default::default()
+        }));
+        assert!(bad(SocketOptions {
+            send_buffer: Some(0),
+            ..Default::default()
+        }));
+        assert!(bad(SocketOptions {
+            keepalive: Some(Keepalive {
+                count: 0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+    }
+}
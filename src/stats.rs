@@ -0,0 +1,269 @@
+//! traffic counters, fed with the engine events
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+
+use chrono::{DateTime, Local};
+
+use crate::tcp::TcpEvent;
+use crate::udp::UdpEvent;
+
+/// seconds of throughput history kept for the plot
+pub const THROUGHPUT_WINDOW: i64 = 300;
+
+/// packets and bytes in both directions for one socket or peer
+///
+/// bytes are payload bytes, without ip / udp / tcp headers or framing
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Counters {
+    /// messages received
+    pub rx_packets: u64,
+    /// payload bytes received
+    pub rx_bytes: u64,
+    /// messages sent
+    pub tx_packets: u64,
+    /// payload bytes sent
+    pub tx_bytes: u64,
+    /// receive errors and failed sends
+    pub errors: u64,
+    /// time of the first packet or error
+    pub first_seen: Option<DateTime<Local>>,
+    /// time of the latest packet or error
+    pub last_seen: Option<DateTime<Local>>,
+}
+
+impl Counters {
+    /// count one received message
+    pub fn add_rx(&mut self, bytes: usize, at: DateTime<Local>) {
+        self.rx_packets += 1;
+        self.rx_bytes += bytes as u64;
+        self.seen(at);
+    }
+
+    /// count one sent message
+    pub fn add_tx(&mut self, bytes: usize, at: DateTime<Local>) {
+        self.tx_packets += 1;
+        self.tx_bytes += bytes as u64;
+        self.seen(at);
+    }
+
+    /// count one error
+    pub fn add_error(&mut self, at: DateTime<Local>) {
+        self.errors += 1;
+        self.seen(at);
+    }
+
+    fn seen(&mut self, at: DateTime<Local>) {
+        self.first_seen.get_or_insert(at);
+        self.last_seen = Some(at);
+    }
+}
+
+/// bytes per second over the last THROUGHPUT_WINDOW seconds,
+/// all connections together
+#[derive(Debug, Default, Clone)]
+pub struct Throughput {
+    // (unix second, rx bytes, tx bytes), oldest first
+    buckets: VecDeque<(i64, u64, u64)>,
+}
+
+impl Throughput {
+    /// count received bytes
+    pub fn add_rx(&mut self, bytes: usize, at: DateTime<Local>) {
+        self.bucket(at).1 += bytes as u64;
+    }
+
+    /// count sent bytes
+    pub fn add_tx(&mut self, bytes: usize, at: DateTime<Local>) {
+        self.bucket(at).2 += bytes as u64;
+    }
+
+    fn bucket(&mut self, at: DateTime<Local>) -> &mut (i64, u64, u64) {
+        let sec = at.timestamp();
+        // events come in order, apart from a few from other threads
+        // that may be a second late, those go to the latest bucket
+        if self.buckets.back().is_none_or(|b| b.0 < sec) {
+            self.buckets.push_back((sec, 0, 0));
+        }
+        while self
+            .buckets
+            .front()
+            .is_some_and(|b| b.0 <= sec - THROUGHPUT_WINDOW)
+        {
+            self.buckets.pop_front();
+        }
+        self.buckets.back_mut().expect("just pushed")
+    }
+
+    /// ([x, rx bytes/s], [x, tx bytes/s]) for every second of the window,
+    /// x is in seconds relative to `now` so it goes from -WINDOW to 0
+    pub fn series(&self, now: DateTime<Local>) -> (Vec<[f64; 2]>, Vec<[f64; 2]>) {
+        let now = now.timestamp();
+        let mut rx = vec![];
+        let mut tx = vec![];
+        let mut buckets = self.buckets.iter().peekable();
+        for sec in now - THROUGHPUT_WINDOW + 1..=now {
+            while buckets.next_if(|b| b.0 < sec).is_some() {}
+            let (r, t) = match buckets.peek() {
+                Some(b) if b.0 == sec => (b.1, b.2),
+                _ => (0, 0),
+            };
+            let x = (sec - now) as f64;
+            rx.push([x, r as f64]);
+            tx.push([x, t as f64]);
+        }
+        (rx, tx)
+    }
+}
+
+/// everything the stats panel shows
+#[derive(Debug, Default, Clone)]
+pub struct Stats {
+    /// the udp socket as a whole
+    pub udp: Counters,
+    /// per remote address the udp socket talked to
+    pub udp_peers: BTreeMap<SocketAddr, Counters>,
+    /// the tcp client connection, across reconnects
+    pub tcp_client: Counters,
+    /// per client of the tcp server, kept after they disconnect
+    pub tcp_server_peers: BTreeMap<SocketAddr, Counters>,
+    /// all of the above over time
+    pub throughput: Throughput,
+}
+
+impl Stats {
+    /// count an event from Udp::poll_events()
+    pub fn on_udp(&mut self, ev: &UdpEvent) {
+        match ev {
+            UdpEvent::Packet {
+                src,
+                data,
+                timestamp,
+                ..
+            } => {
+                self.udp.add_rx(data.len(), *timestamp);
+                self.udp_peers
+                    .entry(*src)
+                    .or_default()
+                    .add_rx(data.len(), *timestamp);
+                self.throughput.add_rx(data.len(), *timestamp);
+            }
+            UdpEvent::Sent {
+                dst,
+                data,
+                timestamp,
+            } => {
+                self.udp.add_tx(data.len(), *timestamp);
+                self.udp_peers
+                    .entry(*dst)
+                    .or_default()
+                    .add_tx(data.len(), *timestamp);
+                self.throughput.add_tx(data.len(), *timestamp);
+            }
+            UdpEvent::Error(_) => self.udp.add_error(Local::now()),
+            UdpEvent::Stopped => {}
+        }
+    }
+
+    /// count an event from TcpClient::poll_events()
+    pub fn on_tcp_client(&mut self, ev: &TcpEvent) {
+        Self::on_tcp(&mut self.tcp_client, &mut self.throughput, ev);
+    }
+
+    /// count an event from TcpServer::poll_events()
+    pub fn on_tcp_server(&mut self, ev: &TcpEvent) {
+        let peer = match ev {
+            TcpEvent::Packet { peer, .. } | TcpEvent::Sent { peer, .. } => *peer,
+        };
+        let counters = self.tcp_server_peers.entry(peer).or_default();
+        Self::on_tcp(counters, &mut self.throughput, ev);
+    }
+
+    fn on_tcp(counters: &mut Counters, throughput: &mut Throughput, ev: &TcpEvent) {
+        match ev {
+            TcpEvent::Packet {
+                data, timestamp, ..
+            } => {
+                counters.add_rx(data.len(), *timestamp);
+                throughput.add_rx(data.len(), *timestamp);
+            }
+            TcpEvent::Sent {
+                data, timestamp, ..
+            } => {
+                counters.add_tx(data.len(), *timestamp);
+                throughput.add_tx(data.len(), *timestamp);
+            }
+        }
+    }
+
+    /// back to zero, peers are forgotten
+    pub fn reset(&mut self) {
+        *self = Stats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeDelta;
+
+    use crate::tcp::fixtures::addr;
+
+    #[test]
+    fn test_counters_per_peer() {
+        let t0 = Local::now();
+        let t1 = t0 + TimeDelta::seconds(1);
+        let mut stats = Stats::default();
+        stats.on_udp(&UdpEvent::Packet {
+            src: addr(1),
+            data: vec![0; 10],
+            timestamp: t0,
+            truncated: None,
+        });
+        stats.on_udp(&UdpEvent::Sent {
+            dst: addr(2),
+            data: vec![0; 3],
+            timestamp: t1,
+        });
+        stats.on_tcp_server(&TcpEvent::Packet {
+            peer: addr(3),
+            local: addr(4),
+            data: vec![0; 5],
+            timestamp: t1,
+        });
+
+        assert_eq!(stats.udp.rx_bytes, 10);
+        assert_eq!(stats.udp.tx_packets, 1);
+        assert_eq!(stats.udp.first_seen, Some(t0));
+        assert_eq!(stats.udp.last_seen, Some(t1));
+        assert_eq!(stats.udp_peers.len(), 2);
+        assert_eq!(stats.udp_peers[&addr(2)].tx_bytes, 3);
+        assert_eq!(stats.udp_peers[&addr(2)].rx_packets, 0);
+        assert_eq!(stats.tcp_server_peers[&addr(3)].rx_bytes, 5);
+        assert_eq!(stats.tcp_client, Counters::default());
+
+        stats.reset();
+        assert!(stats.udp_peers.is_empty());
+        assert_eq!(stats.udp.rx_packets, 0);
+    }
+
+    #[test]
+    fn test_throughput_series() {
+        let now = Local::now();
+        let mut tp = Throughput::default();
+        tp.add_rx(100, now - TimeDelta::seconds(2));
+        tp.add_rx(50, now - TimeDelta::seconds(2));
+        tp.add_tx(7, now);
+        // older than the window
+        let mut old = Throughput::default();
+        old.add_rx(1, now - TimeDelta::seconds(THROUGHPUT_WINDOW + 5));
+
+        let (rx, tx) = tp.series(now);
+        assert_eq!(rx.len(), THROUGHPUT_WINDOW as usize);
+        assert_eq!(rx[rx.len() - 3], [-2.0, 150.0]);
+        assert_eq!(rx[rx.len() - 1], [0.0, 0.0]);
+        assert_eq!(tx[tx.len() - 1], [0.0, 7.0]);
+        assert!(old.series(now).0.iter().all(|p| p[1] == 0.0));
+    }
+}
@@ -1,17 +1,333 @@
+//! tcp server and client, see [`TcpServer`] and [`TcpClient`]
+
+use std::collections::VecDeque;
+use std::fmt;
 use std::io::{self, Read, Write};
-use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, mpsc};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, mpsc};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::echo::{Echo, Replies};
+use crate::framing::{Decoder, Framing};
+use crate::network::Cidr;
+use crate::sockopt::{SocketInfo, SocketOptions};
+use crate::tls::{ClientTls, ServerTls, Stream, TlsClient, TlsServer};
+
+/// size of a single read() unless changed with set_read_buffer_size()
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 1024;
+
+/// so that a client connecting in its worker never hangs on a SYN
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// upper bound of the read buffer, unlike udp nothing is ever lost
+/// with a small buffer, a bigger one just means fewer but larger reads
+pub const MAX_READ_BUFFER_SIZE: usize = 1 << 20;
+
+/// framing can only be changed while stopped, every reading
+/// thread owns a decoder built from it when it starts
+fn check_framing(framing: &Framing, running: bool) -> io::Result<()> {
+    if running {
+        return Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            "stop the connection before changing the framing",
+        ));
+    }
+    framing.validate()
+}
+
+fn check_tls(running: bool) -> io::Result<()> {
+    match running {
+        true => Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            "stop the connection before changing tls",
+        )),
+        false => Ok(()),
+    }
+}
+
+/// sets `opts` on a new connection, the result is logged
+/// unless the options are the defaults
+fn apply_options(opts: &Mutex<SocketOptions>, tcp: &TcpStream, peer: SocketAddr) {
+    let opts = opts.lock().unwrap_or_else(PoisonError::into_inner).clone();
+    if let Err(e) = opts.apply(tcp) {
+        log::error!("cannot set the socket options of [{peer}], {e}");
+    } else if opts != SocketOptions::default()
+        && let Ok(info) = SocketInfo::read(tcp)
+    {
+        log::info!("[TCP] [{peer}] {info}");
+    }
+}
+
+/// the options of the live connections follow a change
+fn check_options(opts: &SocketOptions) -> io::Result<()> {
+    opts.validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn check_read_buffer_size(size: usize) -> io::Result<()> {
+    if size == 0 || size > MAX_READ_BUFFER_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("read buffer must be within 1..={MAX_READ_BUFFER_SIZE}"),
+        ));
+    }
+    Ok(())
+}
+
+/// connect to `remote`, optionally from a fixed local address
+///
+/// TcpStream::connect always picks the local address / ephemeral
+/// port by itself, binding before connecting needs socket2
+/// SO_REUSEADDR is set so that reconnecting from the same fixed port
+/// does not have to wait for the previous connection's TIME_WAIT
+///
+/// an unspecified local ip of the other family (eg 0.0.0.0 for a v6
+/// remote) is swapped for the matching one so that only the port counts
+///
+/// `timeout` None blocks as long as the OS lets connect() block
+pub fn connect_from(
+    remote: &str,
+    local: Option<SocketAddr>,
+    timeout: Option<Duration>,
+) -> io::Result<TcpStream> {
+    let Some(local) = local else {
+        let Some(timeout) = timeout else {
+            return TcpStream::connect(remote);
+        };
+
+        // connect_timeout only takes a resolved address, try them in order
+        let mut last_err = None;
+        for addr in remote.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        return Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{remote} resolves to nothing"),
+            )
+        }));
+    };
+
+    let remotes: Vec<SocketAddr> = remote.to_socket_addrs()?.collect();
+    let remote = remotes
+        .iter()
+        .find(|r| r.is_ipv4() == local.is_ipv4())
+        .or_else(|| remotes.first().filter(|_| local.ip().is_unspecified()))
+        .copied()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("no address of {remote} matches local {local}"),
+            )
+        })?;
+
+    let local = match (local.ip(), remote.ip()) {
+        (IpAddr::V4(ip), IpAddr::V6(_)) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), local.port())
+        }
+        (IpAddr::V6(ip), IpAddr::V4(_)) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), local.port())
+        }
+        _ => local,
+    };
+
+    let socket = Socket::new(
+        Domain::for_address(remote),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&local.into())?;
+    match timeout {
+        Some(timeout) => socket.connect_timeout(&remote.into(), timeout)?,
+        None => socket.connect(&remote.into())?,
+    }
+    Ok(socket.into())
+}
+
+/// what the stream reading threads hand over to the owner,
+/// same idea as udp::UdpEvent, the GUI decides how to show it
+#[derive(Debug, Clone)]
+pub enum TcpEvent {
+    /// one read() worth of bytes, or one frame with a framing set
+    Packet {
+        /// the other end of the connection
+        peer: SocketAddr,
+        /// our end of the connection
+        local: SocketAddr,
+        /// received payload, framing already removed
+        data: Vec<u8>,
+        /// when the worker got it
+        timestamp: chrono::DateTime<chrono::Local>,
+    },
+
+    /// one message went out, from send_data() or the echo
+    Sent {
+        /// the other end of the connection
+        peer: SocketAddr,
+        /// our end of the connection
+        local: SocketAddr,
+        /// payload, before framing
+        data: Vec<u8>,
+        /// when it was written
+        timestamp: chrono::DateTime<chrono::Local>,
+    },
+}
+
+/// a second reader of the received messages, see TcpServer::set_tap()
+type Tap = Arc<Mutex<Option<mpsc::Sender<TcpEvent>>>>;
+
+/// hands a copy of `ev` to the tap if one is set
+fn send_tap(tap: &Mutex<Option<mpsc::Sender<TcpEvent>>>, ev: &TcpEvent) {
+    if let Some(tap) = &*tap.lock().unwrap_or_else(PoisonError::into_inner) {
+        let _ = tap.send(ev.clone());
+    }
+}
+
+/// who the server takes and how many at once
+///
+/// checked at accept time, the deny list wins over the allow list,
+/// an empty allow list allows everyone
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Limits {
+    /// clients served at once, 0 is unlimited
+    pub max_clients: usize,
+    /// when full, hold up to this many new connections until a
+    /// client leaves, 0 closes them right away, so do those beyond
+    pub queue: usize,
+    /// only these may connect, empty allows everyone
+    pub allow: Vec<Cidr>,
+    /// never these
+    pub deny: Vec<Cidr>,
+}
+
+impl Limits {
+    /// Err(reason) if `ip` is not let in
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), String> {
+        if let Some(net) = self.deny.iter().find(|net| net.contains(ip)) {
+            return Err(format!("denied by {net}"));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|net| net.contains(ip)) {
+            return Err("not in the allow list".to_string());
+        }
+        Ok(())
+    }
+
+    /// true when `active` clients leave no room for another
+    pub fn is_full(&self, active: usize) -> bool {
+        self.max_clients > 0 && active >= self.max_clients
+    }
+}
+
+/// one client counted as served while alive
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(active: &Arc<AtomicUsize>) -> Self {
+        active.fetch_add(1, Ordering::Relaxed);
+        Slot(active.clone())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
-/// the purpose of this enum is for tcplistener thread to be
-/// able to emmit stuff to the GUI
-enum TcpServerEvent {
-    AddClient(Arc<TcpStream>),
-    DelClient(SocketAddr),
+/// what a TcpServerSender writes with, the client threads add
+/// and remove themselves, the framing changes with the server
+struct ServerOutlet {
+    clients: Vec<Arc<Stream>>,
+    framing: Framing,
 }
 
+/// sends to the clients of a [`TcpServer`] from any thread, a clone
+/// sees clients come and go without poll_events(), see TcpServer::sender()
+#[derive(Clone)]
+pub struct TcpServerSender {
+    outlet: Arc<Mutex<ServerOutlet>>,
+    is_running: Arc<AtomicBool>,
+    event_tx: mpsc::Sender<TcpEvent>,
+}
+
+impl TcpServerSender {
+    fn outlet(&self) -> MutexGuard<'_, ServerOutlet> {
+        self.outlet.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// same as TcpServer::is_up()
+    pub fn is_up(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// addresses of the connected clients
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.outlet()
+            .clients
+            .iter()
+            .filter_map(|s| s.peer_addr().ok())
+            .collect()
+    }
+
+    /// same as TcpServer::send_data_to()
+    pub fn send_data_to(&self, data: &[u8], peer: SocketAddr) -> io::Result<()> {
+        let stream = self
+            .outlet()
+            .clients
+            .iter()
+            .find(|s| s.peer_addr().ok() == Some(peer))
+            .cloned();
+        let Some(stream) = stream else {
+            log::error!("error sending data, [{peer}] is not connected");
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("[{peer}] is not connected"),
+            ));
+        };
+        self.send_data(data, &stream)
+    }
+
+    /// same as TcpServer::send_data()
+    pub fn send_data(&self, data: &[u8], stream: &Arc<Stream>) -> io::Result<()> {
+        let mut stream: &Stream = stream;
+        let msg = String::from_utf8_lossy(data);
+
+        let framing = self.outlet().framing.clone();
+        let frame = framing.encode(data).inspect_err(|e| {
+            log::error!("cannot frame {msg:?} as {framing}, {e}");
+        })?;
+
+        stream
+            .write_all(&frame)
+            .inspect(|()| {
+                log::info!("[TCP SEND] {msg:?}");
+                if let Ok(peer) = stream.peer_addr()
+                    && let Ok(local) = stream.local_addr()
+                {
+                    let _ = self.event_tx.send(TcpEvent::Sent {
+                        peer,
+                        local,
+                        data: data.to_vec(),
+                        timestamp: chrono::Local::now(),
+                    });
+                }
+            })
+            .inspect_err(|e| log::error!("error sending data via stream: {e}"))
+    }
+}
+
+/// listens on one address and serves any number of clients,
+/// one reading thread per client
 pub struct TcpServer {
     listener: Option<Arc<TcpListener>>,
     is_running: Arc<AtomicBool>,
@@ -19,23 +335,56 @@ pub struct TcpServer {
 
     // these 2 are needed for inner thread to send
     // stuff out without using arc<mutex>
-    event_tx: mpsc::Sender<TcpServerEvent>,
-    event_rx: mpsc::Receiver<TcpServerEvent>,
+    event_tx: mpsc::Sender<TcpEvent>,
+    event_rx: mpsc::Receiver<TcpEvent>,
+
+    // shared with every client thread, changes apply on the next read
+    read_buffer_size: Arc<AtomicUsize>,
+
+    // None is off, read by the client threads for every message
+    echo: Arc<Mutex<Option<Echo>>>,
+
+    // gets a copy of every message besides the events, see set_tap()
+    tap: Tap,
 
-    pub clients: Vec<Arc<TcpStream>>,
+    // None is plain tcp, every accepted client shakes hands with this
+    tls: Option<ServerTls>,
+
+    // read by the listener thread for every accepted connection
+    limits: Arc<Mutex<Limits>>,
+    options: Arc<Mutex<SocketOptions>>,
+
+    // the connected clients and the framing, every client
+    // gets its own decoder built from the framing
+    sender: TcpServerSender,
 }
 
 impl Default for TcpServer {
     fn default() -> Self {
         let (event_tx, event_rx) = mpsc::channel();
+        let is_running = Arc::new(AtomicBool::new(false));
+        let sender = TcpServerSender {
+            outlet: Arc::new(Mutex::new(ServerOutlet {
+                clients: vec![],
+                framing: Framing::None,
+            })),
+            is_running: is_running.clone(),
+            event_tx: event_tx.clone(),
+        };
 
         TcpServer {
             listener: None,
-            is_running: Arc::new(AtomicBool::new(false)),
+            is_running,
             worker: None,
             event_tx,
             event_rx,
-            clients: vec![],
+            read_buffer_size: Arc::new(AtomicUsize::new(DEFAULT_READ_BUFFER_SIZE)),
+            echo: Arc::new(Mutex::new(None)),
+            tap: Arc::new(Mutex::new(None)),
+            tls: None,
+            limits: Arc::new(Mutex::new(Limits::default())),
+            options: Arc::new(Mutex::new(SocketOptions::default())),
+            sender,
         }
     }
 }
@@ -48,10 +397,12 @@ impl Drop for TcpServer {
 }
 
 impl TcpServer {
+    /// true while the accepting thread runs
     pub fn is_up(&self) -> bool {
         self.is_running.load(Ordering::Relaxed)
     }
 
+    /// true while a listener is bound
     pub fn is_connected(&self) -> bool {
         self.listener.is_some()
     }
@@ -112,10 +463,140 @@ impl TcpServer {
             return;
         };
         let event_tx = self.event_tx.clone();
+        let read_buffer_size = self.read_buffer_size.clone();
+        let framing = self.sender.outlet().framing.clone();
+        let outlet = self.sender.outlet.clone();
+        let echo = self.echo.clone();
+        let tap = self.tap.clone();
+        let tls = self.tls.clone();
+        let limits = self.limits.clone();
+        let options = self.options.clone();
+        // clients being served, queued ones not included
+        let active = Arc::new(AtomicUsize::new(0));
 
         // spawn thread, outer listener thread
         self.is_running.store(true, Ordering::Relaxed);
         let handle = thread::spawn(move || {
+            // starts the reading thread of an accepted client
+            let serve = |stream: TcpStream,
+                         peer_sockaddr: SocketAddr,
+                         local_sockaddr: SocketAddr| {
+                let slot = Slot::take(&active);
+                // make another clone to be used in inner thread
+                let event_tx_clone = event_tx.clone();
+                let read_buffer_size = read_buffer_size.clone();
+                let mut decoder = Decoder::new(framing.clone());
+                let framing = framing.clone();
+                let echo = echo.clone();
+                let tap = tap.clone();
+                let tls = tls.clone();
+                let options = options.clone();
+                let outlet = outlet.clone();
+
+                // this thread should be closed automatically
+                // if the connection is terminated, to be tested
+                thread::spawn(move || {
+                    let _slot = slot; // freed whichever way the thread ends
+                    apply_options(&options, &stream, peer_sockaddr);
+                    // the handshake runs here so a slow client
+                    // does not hold up the accepting thread
+                    let stream = match &tls {
+                        Some(tls) => match tls.accept(stream) {
+                            Ok(stream) => stream,
+                            Err(e) => {
+                                log::error!("[TLS] handshake with [{peer_sockaddr}] failed, {e}");
+                                return;
+                            }
+                        },
+                        None => Stream::from(stream),
+                    };
+                    let stream_clone = Arc::new(stream);
+
+                    // in the list for the senders while it is served
+                    outlet
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clients
+                        .push(stream_clone.clone());
+
+                    let mut buffer = vec![];
+                    let mut stream_ref: &Stream = &stream_clone;
+                    let mut replies = Replies::default();
+
+                    loop {
+                        buffer.resize(read_buffer_size.load(Ordering::Relaxed), 0);
+                        match stream_ref.read(&mut buffer) {
+                            // this gets called when both side terminates the connection
+                            // when EOF sent by client, the peer_addr is still available
+                            // in stream, but the connection is already closed when reached here
+                            // if the EOF is initiated by server the event is called first
+                            // then here, when reached here the peer_addr is no longer available
+                            Ok(0) => {
+                                log::info!("peer connection [{}] closed", peer_sockaddr);
+                                if decoder.pending() > 0 {
+                                    log::warn!(
+                                        "[{peer_sockaddr}] closed with {} bytes of an incomplete frame",
+                                        decoder.pending()
+                                    );
+                                }
+                                break;
+                            }
+                            Ok(n) => {
+                                // log::info!("received {n} bytes from [{peer_sockaddr}]");
+                                for frame in decoder.push(&buffer[..n]) {
+                                    match frame {
+                                        Ok(data) => {
+                                            let packet = TcpEvent::Packet {
+                                                peer: peer_sockaddr,
+                                                local: local_sockaddr,
+                                                data: data.clone(),
+                                                timestamp: chrono::Local::now(),
+                                            };
+                                            send_tap(&tap, &packet);
+                                            let _ = event_tx_clone.send(packet);
+                                            Self::send_echo(
+                                                &echo,
+                                                &mut replies,
+                                                &framing,
+                                                &stream_clone,
+                                                &event_tx_clone,
+                                                &data,
+                                            );
+                                        }
+                                        Err(e) => {
+                                            log::error!("framing error from [{peer_sockaddr}], {e}")
+                                        }
+                                    }
+                                }
+                            }
+
+                            Err(e)
+                                if e.kind() == std::io::ErrorKind::TimedOut
+                                    || e.kind() == std::io::ErrorKind::WouldBlock =>
+                            {
+                                thread::sleep(Duration::from_millis(100));
+                            }
+
+                            // other errors might indicate broken or disconnected stream
+                            // so we need to break to end the client handling thread
+                            Err(e) => {
+                                log::error!("reading from stream {:?} error, {}", stream_ref, e);
+                                break;
+                            }
+                        }
+                    }
+                    outlet
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .clients
+                        .retain(|s| !Arc::ptr_eq(s, &stream_clone));
+                    log::debug!("server's handling thread for [{peer_sockaddr}] is ended");
+                });
+            };
+
+            // held while full, served in order as clients leave
+            let mut queue = VecDeque::new();
+
             for stream_result in listener.incoming() {
                 // this provides the escape of the for loop
                 // for this to work tcplistener should run in nonblocking mode
@@ -123,75 +604,64 @@ impl TcpServer {
                     break;
                 }
 
+                while !queue.is_empty()
+                    && !limits
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .is_full(active.load(Ordering::Relaxed))
+                    && let Some((stream, peer, local)) = queue.pop_front()
+                {
+                    log::info!("[TCP] [{peer}] leaves the queue, {} waiting", queue.len());
+                    serve(stream, peer, local);
+                }
+
                 match stream_result {
                     Ok(stream) => {
                         // i don't think sockaddr::peer_addr() would fail very often
                         // but in case it did fail we would need to abort because
                         // sockaddr is also used for removing the client from server end
-                        let peer_sockaddr = match stream.peer_addr() {
-                            Ok(sockaddr) => sockaddr,
+                        let (peer_sockaddr, local_sockaddr) = match stream
+                            .peer_addr()
+                            .and_then(|p| Ok((p, stream.local_addr()?)))
+                        {
+                            Ok(addrs) => addrs,
                             Err(e) => {
                                 log::error!("unable to get incoming stream peer address: {e}");
                                 break; // this will end the server's handing thread
                             }
                         };
-                        log::info!("incoming client stream connected: {stream:?}");
-
-                        let stream = Arc::new(stream);
-                        let stream_clone = stream.clone();
-
-                        // send the tcpstream to vec via channel
-                        let _ = event_tx.send(TcpServerEvent::AddClient(stream));
-
-                        // make another clone to be used in inner thread
-                        let event_tx_clone = event_tx.clone();
-
-                        // this thread should be closed automatically
-                        // if the connection is terminated, to be tested
-                        thread::spawn(move || {
-                            let mut buffer = [0u8; 1024];
-                            let mut stream_ref: &TcpStream = &stream_clone;
-
-                            loop {
-                                match stream_ref.read(&mut buffer) {
-                                    // this gets called when both side terminates the connection
-                                    // when EOF sent by client, the peer_addr is still available
-                                    // in stream, but the connection is already closed when reached here
-                                    // if the EOF is initiated by server the event is called first
-                                    // then here, when reached here the peer_addr is no longer available
-                                    Ok(0) => {
-                                        log::info!("peer connection [{}] closed", peer_sockaddr);
-                                        break;
-                                    }
-                                    Ok(n) => {
-                                        let bytes = &buffer[..n];
-                                        let msg = String::from_utf8_lossy(bytes);
-                                        // log::info!("received {n} bytes from [{peer_sockaddr}]");
-                                        log::info!("[TCP RECV] {:?} from {:?}", msg, peer_sockaddr);
-                                    }
-
-                                    Err(e)
-                                        if e.kind() == std::io::ErrorKind::TimedOut
-                                            || e.kind() == std::io::ErrorKind::WouldBlock =>
-                                    {
-                                        thread::sleep(Duration::from_millis(100));
-                                    }
-
-                                    // other errors might indicate broken or disconnected stream
-                                    // so we need to break to end the client handling thread
-                                    Err(e) => {
-                                        log::error!(
-                                            "reading from stream {:?} error, {}",
-                                            stream_ref,
-                                            e
-                                        );
-                                        break;
-                                    }
-                                }
+                        let limits = limits
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .clone();
+                        if let Err(reason) = limits.check_ip(peer_sockaddr.ip()) {
+                            log::warn!("[TCP] rejected [{peer_sockaddr}], {reason}");
+                            continue; // dropping the stream closes it
+                        }
+                        if limits.is_full(active.load(Ordering::Relaxed)) {
+                            if queue.len() < limits.queue {
+                                queue.push_back((stream, peer_sockaddr, local_sockaddr));
+                                log::info!(
+                                    "[TCP] queued [{peer_sockaddr}], {} clients max, {} waiting",
+                                    limits.max_clients,
+                                    queue.len()
+                                );
+                            } else if limits.queue > 0 {
+                                log::warn!(
+                                    "[TCP] rejected [{peer_sockaddr}], {} clients max and {} waiting",
+                                    limits.max_clients,
+                                    queue.len()
+                                );
+                            } else {
+                                log::warn!(
+                                    "[TCP] rejected [{peer_sockaddr}], {} clients max reached",
+                                    limits.max_clients
+                                );
                             }
-                            let _ = event_tx_clone.send(TcpServerEvent::DelClient(peer_sockaddr));
-                            log::debug!("server's handling thread for [{peer_sockaddr}] is ended");
-                        });
+                            continue;
+                        }
+                        log::info!("incoming client stream connected: {stream:?}");
+                        serve(stream, peer_sockaddr, local_sockaddr);
                     }
 
                     Err(e)
@@ -213,15 +683,61 @@ impl TcpServer {
         self.worker = Some(handle);
     }
 
+    /// sends `data` back on `stream` if echo is on,
+    /// called from the client threads
+    fn send_echo(
+        echo: &Mutex<Option<Echo>>,
+        replies: &mut Replies,
+        framing: &Framing,
+        stream: &Arc<Stream>,
+        event_tx: &mpsc::Sender<TcpEvent>,
+        data: &[u8],
+    ) {
+        let Some(echo) = echo.lock().unwrap_or_else(PoisonError::into_inner).clone() else {
+            return;
+        };
+        let reply = echo.reply(data);
+        let msg = String::from_utf8_lossy(&reply).to_string();
+        let frame = match framing.encode(&reply) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::error!("cannot frame echo {msg:?} as {framing}, {e}");
+                return;
+            }
+        };
+        let stream = stream.clone();
+        let event_tx = event_tx.clone();
+        replies.schedule(echo.delay, move || {
+            let peer = stream.peer_addr();
+            match (&*stream).write_all(&frame) {
+                Ok(()) => {
+                    log::info!("[TCP ECHO] {msg:?} to {peer:?}");
+                    if let Ok(peer) = peer
+                        && let Ok(local) = stream.local_addr()
+                    {
+                        let _ = event_tx.send(TcpEvent::Sent {
+                            peer,
+                            local,
+                            data: reply,
+                            timestamp: chrono::Local::now(),
+                        });
+                    }
+                }
+                Err(e) => log::error!("echo to {peer:?} failed, {e}"),
+            }
+        });
+    }
+
     /// stopping the thread
     fn stop_worker(&mut self) {
         self.is_running.store(false, Ordering::Relaxed);
 
-        if !self.clients.is_empty() {
+        let clients: Vec<_> = self.sender.outlet().clients.drain(..).collect();
+        if !clients.is_empty() {
             log::info!(">>> shutting down connected clients");
-            log::debug!("clients connected = {:?}", self.clients);
+            log::debug!("clients connected = {:?}", clients);
 
-            for stream in self.clients.drain(..) {
+            for stream in clients {
                 if let Err(e) = stream.shutdown(Shutdown::Both) {
                     log::error!("error shutting down stream {:?}, {e}", stream);
                 }
@@ -242,47 +758,112 @@ impl TcpServer {
         self.listener.take();
     }
 
-    pub fn poll_events(&mut self) {
-        for ev in self.event_rx.try_iter() {
-            match ev {
-                TcpServerEvent::AddClient(s) => {
-                    self.clients.push(s);
-                }
-                TcpServerEvent::DelClient(sock) => {
-                    self.clients
-                        .retain(|s| s.peer_addr().map_or(false, |a| a != sock));
-
-                    // with this approach, the shutdown returns error
-                    // because when we get the signal to delete the stream
-                    // the stream is already disconnected,
-                    // if let Some(idx) = self
-                    //     .clients
-                    //     .iter()
-                    //     .position(|s| s.peer_addr().ok() == Some(sock))
-                    // {
-                    //     // pop the stream from vec
-                    //     let stream = self.clients.remove(idx);
-                    //     log::debug!("stream to remove = {:?}", stream);
-
-                    //     // shutdown
-                    //     match stream.shutdown(Shutdown::Both) {
-                    //         Ok(()) => log::debug!("{:?} removed and shuts down", stream),
-                    //         Err(e) => log::error!("unable to shutdown {:?}, {e}", stream),
-                    //     }
-
-                    //     log::debug!("stream after shutdown  = {:?}", stream);
-                    // }
-                }
-            }
-        }
+    /// the data events for the caller to show, the client
+    /// list is kept by the client threads themselves
+    pub fn poll_events(&mut self) -> Vec<TcpEvent> {
+        self.event_rx.try_iter().collect()
+    }
+
+    /// applies to connections accepted after the next start,
+    /// refused while the server is running
+    pub fn set_framing(&mut self, framing: Framing) -> io::Result<()> {
+        check_framing(&framing, self.is_up())?;
+        self.sender.outlet().framing = framing;
+        Ok(())
+    }
+
+    /// None is plain tcp, files are read here so that errors show
+    /// up front, refused while the server is running
+    pub fn set_tls(&mut self, tls: Option<&TlsServer>) -> io::Result<()> {
+        check_tls(self.is_up())?;
+        self.tls = tls.map(TlsServer::load).transpose()?;
+        Ok(())
+    }
+
+    /// applies from the next accepted connection, clients already
+    /// served stay, queued ones are served as soon as there is room
+    pub fn set_limits(&self, limits: Limits) {
+        *self.limits.lock().unwrap_or_else(PoisonError::into_inner) = limits;
+    }
+
+    /// see set_limits()
+    pub fn limits(&self) -> Limits {
+        self.limits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// applies to every connected client now and to the next ones,
+    /// Err if the os refused an option on a connected client
+    pub fn set_socket_options(&self, opts: SocketOptions) -> io::Result<()> {
+        check_options(&opts)?;
+        *self.options.lock().unwrap_or_else(PoisonError::into_inner) = opts.clone();
+        self.clients().iter().try_for_each(|s| opts.apply(s.tcp()))
+    }
+
+    /// see set_socket_options()
+    pub fn socket_options(&self) -> SocketOptions {
+        self.options
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// the options as the os has them on the connection from `peer`
+    pub fn socket_info(&self, peer: SocketAddr) -> io::Result<SocketInfo> {
+        let stream = self
+            .clients()
+            .into_iter()
+            .find(|s| s.peer_addr().ok() == Some(peer))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("[{peer}] is not connected"),
+                )
+            })?;
+        SocketInfo::read(stream.tcp())
+    }
+
+    /// None turns echo off, applies to every client from its next message
+    pub fn set_echo(&self, echo: Option<Echo>) {
+        *self.echo.lock().unwrap_or_else(PoisonError::into_inner) = echo;
+    }
+
+    /// see set_echo()
+    pub fn echo(&self) -> Option<Echo> {
+        self.echo
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// every message received from a client also goes to `tap` from now
+    /// on, as a Packet event, for a second reader that does not wait for
+    /// poll_events(), the same tap can be given to a TcpClient too
+    pub fn set_tap(&self, tap: Option<mpsc::Sender<TcpEvent>>) {
+        *self.tap.lock().unwrap_or_else(PoisonError::into_inner) = tap;
     }
 
+    /// size of a single read() on client streams
+    pub fn set_read_buffer_size(&mut self, size: usize) -> io::Result<()> {
+        check_read_buffer_size(size)?;
+        self.read_buffer_size.store(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// see set_read_buffer_size()
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// shuts down the connection to `peer`, its thread
+    /// takes it off the client list when the read fails
     pub fn close_client(&self, peer: SocketAddr) {
         // solution offered by ai
         // iter through the vec, find the stream then shutdown the stream
-        // but it does not remove it instantly from the vec
         if let Some(s) = self
-            .clients
+            .clients()
             .iter()
             .find(|s| s.peer_addr().ok() == Some(peer))
         {
@@ -291,104 +872,424 @@ impl TcpServer {
                 Err(e) => log::info!("unable to close peer connection [{peer}], {e}"),
             }
         }
+    }
+
+    /// the connected clients
+    pub fn clients(&self) -> Vec<Arc<Stream>> {
+        self.sender.outlet().clients.clone()
+    }
+
+    /// addresses of the connected clients
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.sender.peers()
+    }
+
+    /// same as send_data() to the client connected from `peer`
+    pub fn send_data_to(&self, data: &[u8], peer: SocketAddr) -> io::Result<()> {
+        self.sender.send_data_to(data, peer)
+    }
+
+    /// frames `data` with the server's framing and writes it to one client,
+    /// errors are logged as well as returned
+    pub fn send_data(&self, data: &[u8], stream: &Arc<Stream>) -> io::Result<()> {
+        self.sender.send_data(data, stream)
+    }
+
+    /// a handle for sending from other threads, see TcpServerSender
+    pub fn sender(&self) -> TcpServerSender {
+        self.sender.clone()
+    }
+}
+
+/// auto reconnect settings for the tcp client
+///
+/// the n-th retry (starting at 1) waits
+///     initial_delay * multiplier ^ (n - 1)
+/// capped at max_delay, then randomly moved by up to +- jitter
+/// so that a room full of clients does not retry in lock step
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// wait before the first retry
+    pub initial_delay: Duration,
+    /// the delay never grows past this (before jitter)
+    pub max_delay: Duration,
+    /// growth per retry, values below 1.0 are taken as 1.0
+    pub multiplier: f64,
+
+    /// 0 retries forever
+    pub max_attempts: u32,
+
+    /// fraction of the delay, 0.0 ..= 1.0
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_attempts: 0,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// `random` is expected within 0.0 .. 1.0, passed in to keep this testable
+    pub fn delay(&self, attempt: u32, random: f64) -> Duration {
+        let exp = attempt.saturating_sub(1).min(64) as i32;
+        let base = (self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exp))
+            .min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * (2.0 * random - 1.0);
+        Duration::from_secs_f64((base * (1.0 + jitter)).max(0.0))
+    }
+
+    /// true if no retry should follow retry number `attempt`
+    pub fn gives_up_after(&self, attempt: u32) -> bool {
+        self.max_attempts != 0 && attempt >= self.max_attempts
+    }
+}
+
+/// connection state of a TcpClient, see TcpClient::state()
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClientState {
+    /// not started, stopped, or lost without auto reconnect
+    #[default]
+    Disconnected,
+    /// a connect is in progress
+    Connecting,
+    /// connected, data flows
+    Connected,
+
+    /// waiting before retry number n
+    Backoff(u32),
+    /// auto reconnect ran out of attempts
+    GivenUp,
+}
+
+impl fmt::Display for ClientState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientState::Disconnected => write!(f, "Disconnected"),
+            ClientState::Connecting => write!(f, "Connecting"),
+            ClientState::Connected => write!(f, "Connected"),
+            ClientState::Backoff(n) => write!(f, "Backoff ({n})"),
+            ClientState::GivenUp => write!(f, "Given up"),
+        }
+    }
+}
 
-        // alternative approach, use the event channel we have
-        let _ = self.event_tx.send(TcpServerEvent::DelClient(peer));
+/// the worker's data plus its state changes,
+/// the owner picks them up in poll_events()
+enum TcpClientEvent {
+    Data(TcpEvent),
+    State(ClientState),
+}
+
+/// what a TcpClientSender writes with, the worker swaps the stream
+/// on reconnect, the rest changes with the client
+struct ClientOutlet {
+    stream: Option<Arc<Stream>>,
+    framing: Framing,
+    is_running: Arc<AtomicBool>,
+    event_tx: mpsc::Sender<TcpClientEvent>,
+}
+
+/// sends on the connection of a [`TcpClient`] from any thread, a clone
+/// follows reconnects and restarts, see TcpClient::sender()
+#[derive(Clone)]
+pub struct TcpClientSender {
+    outlet: Arc<Mutex<ClientOutlet>>,
+}
+
+impl TcpClientSender {
+    fn outlet(&self) -> MutexGuard<'_, ClientOutlet> {
+        self.outlet.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    pub fn send_data(&self, msg: &str, stream: &Arc<TcpStream>) {
-        let mut stream: &TcpStream = &stream;
-        let data = msg.as_bytes();
+    /// same as TcpClient::is_up()
+    pub fn is_up(&self) -> bool {
+        self.outlet().is_running.load(Ordering::Relaxed)
+    }
+
+    /// same as TcpClient::send_data()
+    pub fn send_data(&self, data: &[u8]) -> io::Result<()> {
+        let (stream, framing, event_tx) = {
+            let outlet = self.outlet();
+            let stream = outlet.stream.clone();
+            (stream, outlet.framing.clone(), outlet.event_tx.clone())
+        };
+        let Some(ref stream_ref) = stream else {
+            log::error!("error sending data, no stream available");
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "no stream available",
+            ));
+        };
+        let mut stream: &Stream = stream_ref;
+        let msg = String::from_utf8_lossy(data);
+
+        let frame = framing.encode(data).inspect_err(|e| {
+            log::error!("cannot frame {msg:?} as {framing}, {e}");
+        })?;
+
+        stream
+            .write_all(&frame)
+            .inspect(|()| {
+                log::info!("[TCP SEND] {:?} to {:?}", msg, stream_ref.peer_addr());
+                if let Ok(peer) = stream_ref.peer_addr()
+                    && let Ok(local) = stream_ref.local_addr()
+                {
+                    let _ = event_tx.send(TcpClientEvent::Data(TcpEvent::Sent {
+                        peer,
+                        local,
+                        data: data.to_vec(),
+                        timestamp: chrono::Local::now(),
+                    }));
+                }
+            })
+            .inspect_err(|e| log::error!("error sending data via stream: {e}"))
+    }
 
-        match stream.write_all(&data) {
-            Ok(()) => log::info!("[TCP SEND] {msg:?}"),
-            Err(e) => log::error!("error sending data via stream: {e}"),
+    /// the worker's stream, unless that worker was stopped
+    /// meanwhile (checked under the lock, see stop_worker())
+    fn set_stream(
+        outlet: &Mutex<ClientOutlet>,
+        is_running: &AtomicBool,
+        stream: Option<Arc<Stream>>,
+    ) {
+        let mut outlet = outlet.lock().unwrap_or_else(PoisonError::into_inner);
+        if is_running.load(Ordering::Relaxed) {
+            outlet.stream = stream;
         }
     }
 }
 
+/// a single outgoing connection with optional auto reconnect
 pub struct TcpClient {
-    stream: Option<Arc<TcpStream>>,
     is_running: Arc<AtomicBool>,
     worker: Option<JoinHandle<()>>,
+
+    event_tx: mpsc::Sender<TcpClientEvent>,
+    event_rx: mpsc::Receiver<TcpClientEvent>,
+
+    read_buffer_size: Arc<AtomicUsize>,
+
+    // None means no auto reconnect
+    reconnect: Option<ReconnectPolicy>,
+    state: ClientState,
+
+    // None is plain tcp, reconnects shake hands again
+    tls: Option<ClientTls>,
+
+    // shared with the worker for reconnects
+    options: Arc<Mutex<SocketOptions>>,
+
+    // same as TcpServer
+    tap: Tap,
+
+    // kept from begin() for the worker to reconnect with
+    remote: String,
+    local: Option<SocketAddr>,
+
+    // the stream and the framing, flag and channel in use
+    sender: TcpClientSender,
 }
 
 impl Default for TcpClient {
     fn default() -> Self {
+        let (event_tx, event_rx) = mpsc::channel();
+        let is_running = Arc::new(AtomicBool::new(false));
+        let sender = TcpClientSender {
+            outlet: Arc::new(Mutex::new(ClientOutlet {
+                stream: None,
+                framing: Framing::None,
+                is_running: is_running.clone(),
+                event_tx: event_tx.clone(),
+            })),
+        };
+
         Self {
-            stream: None,
-            is_running: Arc::new(AtomicBool::new(false)),
+            is_running,
             worker: None,
+            event_tx,
+            event_rx,
+            read_buffer_size: Arc::new(AtomicUsize::new(DEFAULT_READ_BUFFER_SIZE)),
+            reconnect: None,
+            state: ClientState::Disconnected,
+            tls: None,
+            options: Arc::new(Mutex::new(SocketOptions::default())),
+            tap: Arc::new(Mutex::new(None)),
+            remote: String::default(),
+            local: None,
+            sender,
         }
     }
 }
 
 impl TcpClient {
+    /// true while the reading (and reconnecting) thread runs
     pub fn is_up(&self) -> bool {
         self.is_running.load(Ordering::Relaxed)
     }
 
-    /// notice the local port cannot be manually assigned
-    /// TcpStream::connect always uses ephemeral local port unless
-    /// you find first, to bind we need to use other crates like socket2
-    fn connect_to_server(&mut self, sockaddr: &str) -> io::Result<SocketAddr> {
-        let stream = TcpStream::connect(sockaddr)?;
-        stream.set_nonblocking(true)?;
+    /// as of the last poll_events()
+    pub fn state(&self) -> ClientState {
+        self.state
+    }
+
+    /// `local` binds the client side before connecting, see connect_from()
+    fn connect_to_server(
+        &mut self,
+        sockaddr: &str,
+        local: Option<SocketAddr>,
+    ) -> io::Result<SocketAddr> {
+        let stream = connect_from(sockaddr, local, None)?;
+        let stream = Self::wrap(self.tls.as_ref(), &self.options, stream, sockaddr)?;
         let sockaddr = stream.local_addr()?;
         log::info!("connected to server, {:?}", stream);
-        self.stream = Some(Arc::new(stream));
+        self.sender.outlet().stream = Some(Arc::new(stream));
         Ok(sockaddr)
     }
 
-    pub fn begin(&mut self, sockaddr: &str) -> Option<SocketAddr> {
-        let sockaddr = match self.connect_to_server(sockaddr) {
+    /// sets the socket options and handshakes if tls is on,
+    /// the worker reads nonblocking
+    fn wrap(
+        tls: Option<&ClientTls>,
+        options: &Mutex<SocketOptions>,
+        tcp: TcpStream,
+        remote: &str,
+    ) -> io::Result<Stream> {
+        apply_options(options, &tcp, tcp.peer_addr()?);
+        let stream = match tls {
+            Some(tls) => tls.connect(tcp, remote)?,
+            None => Stream::from(tcp),
+        };
+        stream.set_nonblocking(true)?;
+        Ok(stream)
+    }
+
+    /// `local` as None lets the OS pick the local address and port,
+    /// blocks until connected, see begin_background() for a GUI
+    pub fn begin(&mut self, sockaddr: &str, local: Option<SocketAddr>) -> Option<SocketAddr> {
+        self.state = ClientState::Connecting;
+        let sockaddr_local = match self.connect_to_server(sockaddr, local) {
             Ok(sockaddr) => sockaddr,
             Err(e) => {
                 log::error!("error connecting to TCP server {sockaddr}, {e}");
+                self.state = ClientState::Disconnected;
                 return None;
             }
         };
 
+        self.state = ClientState::Connected;
+        self.remote = sockaddr.to_string();
+        self.local = local;
         self.start_worker();
-        Some(sockaddr)
+        Some(sockaddr_local)
     }
 
+    /// like begin() but returns right away, the connect and the tls
+    /// handshake run in the worker so that a dead host does not hold
+    /// up the caller, state() is Connecting until poll_events() says
+    /// otherwise, a failed first connect is not retried either way
+    pub fn begin_background(&mut self, sockaddr: &str, local: Option<SocketAddr>) {
+        if self.is_up() {
+            self.stop_worker();
+        }
+        self.sender.outlet().stream = None;
+        self.state = ClientState::Connecting;
+        self.remote = sockaddr.to_string();
+        self.local = local;
+        self.start_worker();
+    }
+
+    /// the worker connects first if there is no stream yet
     fn start_worker(&mut self) {
         if self.is_up() {
             self.stop_worker();
         }
 
         // prepare clones for thread
-        let Some(stream) = self.stream.as_ref().cloned() else {
-            log::error!("cannot start client thread, no stream found");
-            return;
-        };
+        let stream = self.stream();
         let is_running = self.is_running.clone();
+        let event_tx = self.event_tx.clone();
+        let read_buffer_size = self.read_buffer_size.clone();
+        let framing = self.sender.outlet().framing.clone();
+        let outlet = self.sender.outlet.clone();
+        let reconnect = self.reconnect.clone();
+        let remote = self.remote.clone();
+        let local = self.local;
+        let tls = self.tls.clone();
+        let options = self.options.clone();
+        let tap = self.tap.clone();
 
         // set state right before thread starts
         is_running.store(true, Ordering::Relaxed);
         let handle = thread::spawn(move || {
-            let mut buffer = [0u8; 1024];
-            let mut stream_ref: &TcpStream = &stream;
-            while is_running.load(Ordering::Relaxed) {
-                match stream_ref.read(&mut buffer) {
-                    Ok(0) => {
-                        log::info!("connection closed by server end (EOF)");
-                        break;
+            let mut stream = match stream {
+                Some(stream) => stream,
+                None => {
+                    let result = connect_from(&remote, local, Some(CONNECT_TIMEOUT))
+                        .and_then(|s| Self::wrap(tls.as_ref(), &options, s, &remote));
+                    // stopped meanwhile, the stream is dropped
+                    if !is_running.load(Ordering::Relaxed) {
+                        return;
                     }
-                    Ok(n) => {
-                        let bytes = &buffer[..n];
-                        let msg = String::from_utf8_lossy(bytes);
-                        log::info!("[TCP RECV] {:?} from {:?}", msg, stream.peer_addr());
+                    match result {
+                        Ok(stream) => {
+                            log::info!("connected to server, {:?}", stream);
+                            let stream = Arc::new(stream);
+                            TcpClientSender::set_stream(&outlet, &is_running, Some(stream.clone()));
+                            let _ = event_tx.send(TcpClientEvent::State(ClientState::Connected));
+                            stream
+                        }
+                        Err(e) => {
+                            log::error!("error connecting to TCP server {remote}, {e}");
+                            let _ = event_tx.send(TcpClientEvent::State(ClientState::Disconnected));
+                            is_running.store(false, Ordering::Relaxed);
+                            return;
+                        }
                     }
-                    Err(e)
-                        if e.kind() == std::io::ErrorKind::TimedOut
-                            || e.kind() == std::io::ErrorKind::WouldBlock =>
-                    {
-                        thread::sleep(Duration::from_millis(100));
+                }
+            };
+            let mut end_state = ClientState::Disconnected;
+
+            loop {
+                let lost = Self::read_stream(
+                    &stream,
+                    Decoder::new(framing.clone()),
+                    &is_running,
+                    &read_buffer_size,
+                    &event_tx,
+                    &tap,
+                );
+                // stopped from our side, not a lost connection
+                if !lost {
+                    break;
+                }
+                TcpClientSender::set_stream(&outlet, &is_running, None);
+
+                let Some(policy) = &reconnect else {
+                    break;
+                };
+                match Self::reconnect(
+                    &remote,
+                    local,
+                    tls.as_ref(),
+                    &options,
+                    policy,
+                    &is_running,
+                    &event_tx,
+                ) {
+                    Some(new_stream) => {
+                        stream = new_stream;
+                        TcpClientSender::set_stream(&outlet, &is_running, Some(stream.clone()));
+                        let _ = event_tx.send(TcpClientEvent::State(ClientState::Connected));
                     }
-                    Err(e) => {
-                        log::error!("tcp client receiving error, {}", e);
+                    None => {
+                        end_state = ClientState::GivenUp;
                         break;
                     }
                 }
@@ -397,16 +1298,135 @@ impl TcpClient {
             // todo we need a manual repaint here because at
             // this moment the client is considered no_up
             log::debug!("client thread's while loop ended");
+            if is_running.load(Ordering::Relaxed) {
+                let _ = event_tx.send(TcpClientEvent::State(end_state));
+            }
             is_running.store(false, Ordering::Relaxed);
         });
         log::info!("client thread started: {:?}", handle.thread());
         self.worker = Some(handle);
     }
 
+    /// read until the connection is gone (true)
+    /// or the worker is asked to stop (false)
+    fn read_stream(
+        stream: &Arc<Stream>,
+        mut decoder: Decoder,
+        is_running: &AtomicBool,
+        read_buffer_size: &AtomicUsize,
+        event_tx: &mpsc::Sender<TcpClientEvent>,
+        tap: &Mutex<Option<mpsc::Sender<TcpEvent>>>,
+    ) -> bool {
+        let mut buffer = vec![];
+        let mut stream_ref: &Stream = stream;
+        let (Ok(peer), Ok(local)) = (stream.peer_addr(), stream.local_addr()) else {
+            log::error!("unable to get server address from {stream:?}");
+            return true;
+        };
+        while is_running.load(Ordering::Relaxed) {
+            buffer.resize(read_buffer_size.load(Ordering::Relaxed), 0);
+            match stream_ref.read(&mut buffer) {
+                Ok(0) => {
+                    log::info!("connection closed by server end (EOF)");
+                    if decoder.pending() > 0 {
+                        log::warn!(
+                            "[{peer}] closed with {} bytes of an incomplete frame",
+                            decoder.pending()
+                        );
+                    }
+                    return true;
+                }
+                Ok(n) => {
+                    for frame in decoder.push(&buffer[..n]) {
+                        match frame {
+                            Ok(data) => {
+                                let packet = TcpEvent::Packet {
+                                    peer,
+                                    local,
+                                    data,
+                                    timestamp: chrono::Local::now(),
+                                };
+                                send_tap(tap, &packet);
+                                let _ = event_tx.send(TcpClientEvent::Data(packet));
+                            }
+                            Err(e) => log::error!("framing error from [{peer}], {e}"),
+                        }
+                    }
+                }
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::TimedOut
+                        || e.kind() == std::io::ErrorKind::WouldBlock =>
+                {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    log::error!("tcp client receiving error, {}", e);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// retry with backoff until connected, given up or stopped (None)
+    fn reconnect(
+        remote: &str,
+        local: Option<SocketAddr>,
+        tls: Option<&ClientTls>,
+        options: &Mutex<SocketOptions>,
+        policy: &ReconnectPolicy,
+        is_running: &AtomicBool,
+        event_tx: &mpsc::Sender<TcpClientEvent>,
+    ) -> Option<Arc<Stream>> {
+        for attempt in 1.. {
+            let delay = policy.delay(attempt, rand::random());
+            let _ = event_tx.send(TcpClientEvent::State(ClientState::Backoff(attempt)));
+            log::info!("reconnecting to {remote} in {delay:?}, attempt {attempt}");
+
+            // sleep in small steps so that stopping stays responsive
+            let until = Instant::now() + delay;
+            while Instant::now() < until {
+                if !is_running.load(Ordering::Relaxed) {
+                    return None;
+                }
+                thread::sleep(Duration::from_millis(50).min(until - Instant::now()));
+            }
+
+            let _ = event_tx.send(TcpClientEvent::State(ClientState::Connecting));
+            let result = connect_from(remote, local, Some(CONNECT_TIMEOUT))
+                .and_then(|s| Self::wrap(tls, options, s, remote));
+            match result {
+                Ok(stream) => {
+                    log::info!("reconnected to server, {:?}", stream);
+                    return Some(Arc::new(stream));
+                }
+                Err(e) => log::warn!("reconnect attempt {attempt} to {remote} failed, {e}"),
+            }
+
+            if policy.gives_up_after(attempt) || !is_running.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+        log::error!("gave up reconnecting to {remote}");
+        None
+    }
+
     fn stop_worker(&mut self) {
         // set flag to stop the thread
         self.is_running.store(false, Ordering::Relaxed);
 
+        // a connect in progress can take a while, that worker is left to
+        // end by itself, cut off from the flag and events of the next one
+        if self.state == ClientState::Connecting && self.worker.take().is_some() {
+            self.is_running = Arc::new(AtomicBool::new(false));
+            (self.event_tx, self.event_rx) = mpsc::channel();
+            let mut outlet = self.sender.outlet();
+            outlet.is_running = self.is_running.clone();
+            outlet.event_tx = self.event_tx.clone();
+            log::info!("client thread left to finish its connect");
+            return;
+        }
+
         // make sure worker thread is terminated
         if let Some(worker) = self.worker.take() {
             match worker.join() {
@@ -416,28 +1436,632 @@ impl TcpClient {
         }
     }
 
+    /// stops the worker and closes the connection
     pub fn disconnect(&mut self) {
         self.stop_worker();
+        self.state = ClientState::Disconnected;
 
         // drop the stream so that the server can close the connection
-        if let Some(stream) = self.stream.take() {
-            if let Err(e) = stream.shutdown(Shutdown::Both) {
-                log::error!("failed to shutdown tcpclient stream: {e}");
+        let stream = self.sender.outlet().stream.take();
+        if let Some(stream) = stream
+            && let Err(e) = stream.shutdown(Shutdown::Both)
+            && e.kind() != io::ErrorKind::NotConnected
+        {
+            log::error!("failed to shutdown tcpclient stream: {e}");
+        }
+    }
+
+    /// keeps the state up to date with the worker
+    /// and returns the data events for the caller to show
+    pub fn poll_events(&mut self) -> Vec<TcpEvent> {
+        let mut out = vec![];
+        for ev in self.event_rx.try_iter() {
+            match ev {
+                TcpClientEvent::Data(ev) => out.push(ev),
+                TcpClientEvent::State(state) => self.state = state,
             }
         }
+        out
     }
 
-    pub fn send_data(&self, msg: &str) {
-        if let Some(ref stream_ref) = self.stream {
-            let mut stream: &TcpStream = &stream_ref;
-            let data = msg.as_bytes();
+    /// size of a single read(), changes apply on the next read
+    pub fn set_read_buffer_size(&mut self, size: usize) -> io::Result<()> {
+        check_read_buffer_size(size)?;
+        self.read_buffer_size.store(size, Ordering::Relaxed);
+        Ok(())
+    }
 
-            match stream.write_all(&data) {
-                Ok(()) => log::info!("[TCP SEND] {:?} to {:?}", msg, stream_ref.peer_addr()),
-                Err(e) => log::error!("error sending data via stream: {e}"),
+    /// see set_read_buffer_size()
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// applies from the next connect, refused while connected
+    pub fn set_framing(&mut self, framing: Framing) -> io::Result<()> {
+        check_framing(&framing, self.is_up())?;
+        self.sender.outlet().framing = framing;
+        Ok(())
+    }
+
+    /// None is plain tcp, files are read here so that errors show
+    /// up front, refused while connected
+    pub fn set_tls(&mut self, tls: Option<&TlsClient>) -> io::Result<()> {
+        check_tls(self.is_up())?;
+        self.tls = tls.map(TlsClient::load).transpose()?;
+        Ok(())
+    }
+
+    /// the negotiated tls version and cipher suite, None for plain tcp
+    pub fn tls_info(&self) -> Option<String> {
+        self.stream()?.tls_info()
+    }
+
+    /// the client end of the connection, None while not connected
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.stream()?.local_addr().ok()
+    }
+
+    /// applies to the connection now and to reconnects,
+    /// Err if the os refused an option
+    pub fn set_socket_options(&self, opts: SocketOptions) -> io::Result<()> {
+        check_options(&opts)?;
+        *self.options.lock().unwrap_or_else(PoisonError::into_inner) = opts.clone();
+        match self.stream() {
+            Some(stream) => opts.apply(stream.tcp()),
+            None => Ok(()),
+        }
+    }
+
+    /// see set_socket_options()
+    pub fn socket_options(&self) -> SocketOptions {
+        self.options
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// the options as the os has them on the connection
+    pub fn socket_info(&self) -> io::Result<SocketInfo> {
+        let stream = self
+            .stream()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "no stream available"))?;
+        SocketInfo::read(stream.tcp())
+    }
+
+    /// None turns auto reconnect off, applies from the next connect
+    pub fn set_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect = policy;
+    }
+
+    /// frames `data` with the client's framing and writes it,
+    /// errors are logged as well as returned
+    pub fn send_data(&self, data: &[u8]) -> io::Result<()> {
+        self.sender.send_data(data)
+    }
+
+    /// a handle for sending from other threads, see TcpClientSender
+    pub fn sender(&self) -> TcpClientSender {
+        self.sender.clone()
+    }
+
+    /// same as TcpServer::set_tap(), for the messages from the server
+    pub fn set_tap(&self, tap: Option<mpsc::Sender<TcpEvent>>) {
+        *self.tap.lock().unwrap_or_else(PoisonError::into_inner) = tap;
+    }
+
+    fn stream(&self) -> Option<Arc<Stream>> {
+        self.sender.outlet().stream.clone()
+    }
+}
+
+/// events made up for the tests of the modules they are fed to
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// 127.0.0.1:`port`
+    pub fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    /// `data` sent to (`sent`) or received from 127.0.0.1:`port`
+    pub fn event(sent: bool, port: u16, data: &[u8]) -> TcpEvent {
+        let (peer, local, data, timestamp) =
+            (addr(port), addr(7000), data.to_vec(), chrono::Local::now());
+        match sent {
+            true => TcpEvent::Sent {
+                peer,
+                local,
+                data,
+                timestamp,
+            },
+            false => TcpEvent::Packet {
+                peer,
+                local,
+                data,
+                timestamp,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// poll until `n` packets arrived or give up after ~2s
+    fn wait_packets(mut poll: impl FnMut() -> Vec<TcpEvent>, n: usize) -> Vec<Vec<u8>> {
+        let mut out = vec![];
+        for _ in 0..40 {
+            for ev in poll() {
+                if let TcpEvent::Packet { data, .. } = ev {
+                    out.push(data);
+                }
             }
-        } else {
-            log::error!("error sending data, no stream available");
+            if out.len() >= n {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
         }
+        out
+    }
+
+    /// the state once the client is done connecting
+    fn wait_state(client: &mut TcpClient) -> ClientState {
+        for _ in 0..40 {
+            client.poll_events();
+            if client.state() != ClientState::Connecting {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        client.state()
+    }
+
+    #[test]
+    fn test_begin_background() {
+        let mut server = TcpServer::default();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+
+        let mut client = TcpClient::default();
+        client.begin_background(&format!("127.0.0.1:{port}"), None);
+        assert!(client.is_up());
+        assert_eq!(wait_state(&mut client), ClientState::Connected);
+        assert!(client.local_addr().is_some());
+        client.send_data(b"hi").unwrap();
+        assert_eq!(
+            wait_packets(|| server.poll_events(), 1),
+            vec![b"hi".to_vec()]
+        );
+        client.disconnect();
+        server.disconnect();
+
+        // nobody listening anymore, the worker gives up by itself
+        client.begin_background(&format!("127.0.0.1:{port}"), None);
+        assert_eq!(wait_state(&mut client), ClientState::Disconnected);
+        assert!(client.local_addr().is_none());
+    }
+
+    #[test]
+    fn test_senders_without_polling() {
+        let mut server = TcpServer::default();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        let mut client = TcpClient::default();
+        // taken before the connection exists
+        let (server_tx, client_tx) = (server.sender(), client.sender());
+        client.begin_background(&format!("127.0.0.1:{port}"), None);
+
+        // neither end is polled until the sends went out
+        let mut peers = vec![];
+        for _ in 0..40 {
+            peers = server_tx.peers();
+            if !peers.is_empty() && client_tx.send_data(b"up").is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(peers.len(), 1);
+        server_tx.send_data_to(b"down", peers[0]).unwrap();
+        assert_eq!(
+            wait_packets(|| server.poll_events(), 1),
+            vec![b"up".to_vec()]
+        );
+        assert_eq!(
+            wait_packets(|| client.poll_events(), 1),
+            vec![b"down".to_vec()]
+        );
+
+        client.disconnect();
+        assert!(client_tx.send_data(b"up").is_err());
+        for _ in 0..40 {
+            if server_tx.peers().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(server_tx.peers().is_empty());
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_framed_loopback() {
+        let mut server = TcpServer::default();
+        server
+            .set_framing(Framing::Delimiter(b"\n".to_vec()))
+            .unwrap();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        assert!(server.set_framing(Framing::Slip).is_err());
+
+        let mut client = TcpClient::default();
+        client
+            .set_framing(Framing::Delimiter(b"\n".to_vec()))
+            .unwrap();
+        client.begin(&format!("127.0.0.1:{port}"), None).unwrap();
+
+        client.send_data(b"one").unwrap();
+        client.send_data(b"two").unwrap();
+        let frames = wait_packets(|| server.poll_events(), 2);
+        assert_eq!(frames, vec![b"one".to_vec(), b"two".to_vec()]);
+
+        // and back to the client through the server side encoder
+        let stream = server.clients()[0].clone();
+        server.send_data(b"three", &stream).unwrap();
+        let frames = wait_packets(|| client.poll_events(), 1);
+        assert_eq!(frames, vec![b"three".to_vec()]);
+
+        // or by the peer address
+        let peer = server.peers()[0];
+        server.send_data_to(b"four", peer).unwrap();
+        let frames = wait_packets(|| client.poll_events(), 1);
+        assert_eq!(frames, vec![b"four".to_vec()]);
+        let gone = SocketAddr::from(([127, 0, 0, 1], 1));
+        assert!(server.send_data_to(b"five", gone).is_err());
+
+        client.disconnect();
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_echo_with_delay() {
+        let mut server = TcpServer::default();
+        server.set_framing(Framing::Cobs).unwrap();
+        server.set_echo(Some(Echo {
+            transform: crate::echo::Transform::Prefix(b"re: ".to_vec()),
+            delay: Duration::from_millis(100),
+        }));
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+
+        let mut client = TcpClient::default();
+        client.set_framing(Framing::Cobs).unwrap();
+        client.begin(&format!("127.0.0.1:{port}"), None).unwrap();
+
+        let sent = Instant::now();
+        client.send_data(b"ping").unwrap();
+        client.send_data(b"pong").unwrap();
+        let frames = wait_packets(|| client.poll_events(), 2);
+        assert_eq!(frames, vec![b"re: ping".to_vec(), b"re: pong".to_vec()]);
+        assert!(sent.elapsed() >= Duration::from_millis(100));
+
+        // what was received comes before the echo it caused
+        let kinds: Vec<bool> = server
+            .poll_events()
+            .iter()
+            .map(|ev| matches!(ev, TcpEvent::Sent { .. }))
+            .collect();
+        assert_eq!(kinds, vec![false, false, true, true]);
+
+        client.disconnect();
+        server.disconnect();
+    }
+
+    /// a tls client and server talking over loopback, both ways
+    fn tls_loopback(server_tls: &TlsServer, client_tls: &TlsClient) {
+        let mut server = TcpServer::default();
+        server.set_framing(Framing::Cobs).unwrap();
+        server.set_tls(Some(server_tls)).unwrap();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        assert!(server.set_tls(None).is_err());
+
+        let mut client = TcpClient::default();
+        client.set_framing(Framing::Cobs).unwrap();
+        client.set_tls(Some(client_tls)).unwrap();
+        // the handshake runs in the worker
+        client.begin_background(&format!("127.0.0.1:{port}"), None);
+        assert_eq!(wait_state(&mut client), ClientState::Connected);
+
+        client.send_data(b"hello").unwrap();
+        let frames = wait_packets(|| server.poll_events(), 1);
+        assert_eq!(frames, vec![b"hello".to_vec()]);
+        assert!(server.clients()[0].tls_info().unwrap().contains("TLS"));
+
+        let stream = server.clients()[0].clone();
+        server.send_data(b"world", &stream).unwrap();
+        let frames = wait_packets(|| client.poll_events(), 1);
+        assert_eq!(frames, vec![b"world".to_vec()]);
+
+        client.disconnect();
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_tls_self_signed() {
+        let client_tls = TlsClient {
+            accept_invalid: true,
+            ..Default::default()
+        };
+        tls_loopback(&TlsServer::SelfSigned, &client_tls);
+
+        // a verifying client refuses the self signed certificate
+        let mut server = TcpServer::default();
+        server.set_tls(Some(&TlsServer::SelfSigned)).unwrap();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        let mut client = TcpClient::default();
+        client.set_tls(Some(&TlsClient::default())).unwrap();
+        assert!(client.begin(&format!("127.0.0.1:{port}"), None).is_none());
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_tls_pem_files() {
+        let dir = std::env::temp_dir().join(format!("udptcp_tls_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let (cert_file, key_file) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_file, generated.cert.pem()).unwrap();
+        std::fs::write(&key_file, generated.signing_key.serialize_pem()).unwrap();
+
+        // the certificate is its own CA, and names localhost not 127.0.0.1
+        let server_tls = TlsServer::Pem {
+            cert_file: cert_file.clone(),
+            key_file,
+        };
+        let client_tls = TlsClient {
+            server_name: Some("localhost".to_string()),
+            ca_file: Some(cert_file),
+            ..Default::default()
+        };
+        tls_loopback(&server_tls, &client_tls);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_limits_check_ip() {
+        let limits = Limits {
+            allow: Cidr::parse_list("10.0.0.0/8, 192.168.1.5").unwrap(),
+            deny: Cidr::parse_list("10.0.0.13").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(limits.check_ip("10.1.2.3".parse().unwrap()), Ok(()));
+        assert_eq!(limits.check_ip("192.168.1.5".parse().unwrap()), Ok(()));
+        assert_eq!(
+            limits.check_ip("10.0.0.13".parse().unwrap()),
+            Err("denied by 10.0.0.13/32".to_string())
+        );
+        assert!(limits.check_ip("192.168.1.6".parse().unwrap()).is_err());
+        assert_eq!(
+            Limits::default().check_ip("8.8.8.8".parse().unwrap()),
+            Ok(())
+        );
+
+        assert!(!Limits::default().is_full(1000));
+        let one = Limits {
+            max_clients: 1,
+            ..Default::default()
+        };
+        assert!(!one.is_full(0));
+        assert!(one.is_full(1));
+    }
+
+    /// the server closed `stream`, no answer within 2s fails
+    fn assert_closed(mut stream: &TcpStream) {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let r = stream.read(&mut [0; 8]);
+        assert!(
+            matches!(r, Ok(0))
+                || matches!(&r, Err(e) if e.kind() == io::ErrorKind::ConnectionReset),
+            "not closed, {r:?}"
+        );
+    }
+
+    /// poll until the server has `n` clients or give up after ~2s
+    fn wait_clients(server: &mut TcpServer, n: usize) -> usize {
+        for _ in 0..40 {
+            server.poll_events();
+            if server.clients().len() == n {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        server.clients().len()
+    }
+
+    #[test]
+    fn test_max_clients_reject_and_queue() {
+        let mut server = TcpServer::default();
+        server.set_limits(Limits {
+            max_clients: 1,
+            ..Default::default()
+        });
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+        let remote = format!("127.0.0.1:{port}");
+
+        let mut first = TcpClient::default();
+        first.begin(&remote, None).unwrap();
+        assert_eq!(wait_clients(&mut server, 1), 1);
+
+        // rejected, closed by the server right away
+        assert_closed(&TcpStream::connect(&remote).unwrap());
+        assert_eq!(wait_clients(&mut server, 1), 1);
+
+        // queued, served once the first one leaves
+        server.set_limits(Limits {
+            max_clients: 1,
+            queue: 1,
+            ..Default::default()
+        });
+        let mut third = TcpClient::default();
+        third.begin(&remote, None).unwrap();
+        third.send_data(b"waited").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(server.clients().len(), 1);
+        assert!(wait_packets(|| server.poll_events(), 1).is_empty());
+
+        // the queue is full, closed right away
+        assert_closed(&TcpStream::connect(&remote).unwrap());
+
+        first.disconnect();
+        let frames = wait_packets(|| server.poll_events(), 1);
+        assert_eq!(frames, vec![b"waited".to_vec()]);
+        assert_eq!(wait_clients(&mut server, 1), 1);
+
+        third.disconnect();
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_socket_options_both_ends() {
+        let mut server = TcpServer::default();
+        server
+            .set_socket_options(SocketOptions {
+                linger: Some(Duration::ZERO),
+                ttl: Some(33),
+                ..Default::default()
+            })
+            .unwrap();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+
+        let mut client = TcpClient::default();
+        client
+            .set_socket_options(SocketOptions {
+                nodelay: true,
+                ..Default::default()
+            })
+            .unwrap();
+        client.begin(&format!("127.0.0.1:{port}"), None).unwrap();
+        assert!(client.socket_info().unwrap().nodelay);
+
+        assert_eq!(wait_clients(&mut server, 1), 1);
+        let peer = server.peers()[0];
+        let info = server.socket_info(peer).unwrap();
+        assert_eq!((info.linger, info.ttl), (Some(Duration::ZERO), 33));
+
+        // live connections follow a change
+        server
+            .set_socket_options(SocketOptions {
+                nodelay: true,
+                ..Default::default()
+            })
+            .unwrap();
+        let info = server.socket_info(peer).unwrap();
+        assert!(info.nodelay && info.linger.is_none());
+        assert!(
+            server
+                .set_socket_options(SocketOptions {
+                    ttl: Some(0),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+
+        client.disconnect();
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_reconnect_policy_delay() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            multiplier: 2.0,
+            max_attempts: 3,
+            jitter: 0.5,
+        };
+
+        // random = 0.5 means no jitter
+        assert_eq!(policy.delay(1, 0.5), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, 0.5), Duration::from_millis(200));
+        assert_eq!(policy.delay(4, 0.5), Duration::from_millis(800));
+        assert_eq!(policy.delay(5, 0.5), Duration::from_millis(1000));
+        assert_eq!(policy.delay(100, 0.5), Duration::from_millis(1000));
+
+        // +- 50%
+        assert_eq!(policy.delay(1, 0.0), Duration::from_millis(50));
+        assert_eq!(policy.delay(1, 1.0), Duration::from_millis(150));
+
+        assert!(!policy.gives_up_after(2));
+        assert!(policy.gives_up_after(3));
+        assert!(!ReconnectPolicy::default().gives_up_after(1000));
+    }
+
+    #[test]
+    fn test_client_reconnects() {
+        let mut server = TcpServer::default();
+        let port = server.begin("127.0.0.1:0".to_string()).unwrap();
+
+        let mut client = TcpClient::default();
+        client.set_reconnect(Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(50),
+            jitter: 0.0,
+            ..Default::default()
+        }));
+        client.begin(&format!("127.0.0.1:{port}"), None).unwrap();
+        assert_eq!(client.state(), ClientState::Connected);
+
+        // wait for the server to register the client then kick it
+        let mut peer = None;
+        for _ in 0..40 {
+            server.poll_events();
+            if let Some(s) = server.clients().first() {
+                peer = s.peer_addr().ok();
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        server.close_client(peer.unwrap());
+
+        // the client should come back on its own with a new local port
+        let mut states = vec![];
+        for _ in 0..40 {
+            client.poll_events();
+            states.push(client.state());
+            server.poll_events();
+            if client.state() == ClientState::Connected
+                && server.clients().iter().any(|s| s.peer_addr().ok() != peer)
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(
+            states.contains(&ClientState::Backoff(1)) || states.contains(&ClientState::Connecting)
+        );
+        assert_eq!(client.state(), ClientState::Connected);
+        assert!(client.is_up());
+
+        client.disconnect();
+        assert_eq!(client.state(), ClientState::Disconnected);
+        server.disconnect();
+    }
+
+    #[test]
+    fn test_connect_from_fixed_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let remote = listener.local_addr().unwrap().to_string();
+
+        // grab a free port then release it for the client to use
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let local: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+
+        let stream = connect_from(&remote, Some(local), None).unwrap();
+        assert_eq!(stream.local_addr().unwrap(), local);
+
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer, local);
     }
 }
@@ -0,0 +1,463 @@
+//! optional tls for the tcp engines, see [`TlsClient`], [`TlsServer`] and [`Stream`]
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore};
+use rustls::{ServerConfig, ServerConnection, SignatureScheme};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+
+/*
+    rustls does no io itself, a Stream keeps the tcp socket and the
+    tls state next to each other
+
+        read    the socket is read without the lock, so that a
+                blocking read never holds up a write, the bytes are
+                then fed to rustls under the lock, the buffer for
+                them is kept, only the reading thread uses it
+        write   plaintext in, records out under the lock, which is
+                let go while a nonblocking socket is full
+
+    the engines share a stream between the reading thread and
+    send_data() as before, only the type changed, a plain stream
+    reads and writes the socket directly
+
+    the handshake runs right after connect / accept with a timeout,
+    the negotiated version and cipher suite are logged
+*/
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// big enough for one full tls record
+const RECORD_SIZE: usize = 16 * 1024 + 256;
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid_data(format!("cannot read certificates from {path:?}, {e}")))?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!("no certificate in {path:?}")));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| invalid_data(format!("cannot read a private key from {path:?}, {e}")))
+}
+
+/// client side settings, the default checks the server against
+/// the usual public CAs and the host name it was connected with
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TlsClient {
+    /// the name sent as SNI and checked against the certificate,
+    /// None uses the host of the address connected to
+    pub server_name: Option<String>,
+    /// PEM file of the CAs to trust instead of the public ones
+    pub ca_file: Option<PathBuf>,
+    /// PEM certificate chain to present to a server asking for one
+    pub cert_file: Option<PathBuf>,
+    /// PEM private key of `cert_file`
+    pub key_file: Option<PathBuf>,
+    /// skip every certificate check, for lab gear with whatever cert
+    pub accept_invalid: bool,
+}
+
+/// server side certificate
+#[derive(Debug, Clone, PartialEq)]
+pub enum TlsServer {
+    /// generated on start for "localhost", clients have to accept it
+    SelfSigned,
+    /// PEM certificate chain and private key
+    Pem {
+        /// the certificate chain, the server's own first
+        cert_file: PathBuf,
+        /// its private key
+        key_file: PathBuf,
+    },
+}
+
+/// a TlsClient loaded and ready to connect with
+#[derive(Clone)]
+pub(crate) struct ClientTls {
+    config: Arc<ClientConfig>,
+    server_name: Option<String>,
+}
+
+/// a TlsServer loaded and ready to accept with
+#[derive(Clone)]
+pub(crate) struct ServerTls {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsClient {
+    /// reads the files, errors name the file at fault
+    pub(crate) fn load(&self) -> io::Result<ClientTls> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let builder = if self.accept_invalid {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(
+                    provider().signature_verification_algorithms,
+                )))
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &self.ca_file {
+                Some(path) => {
+                    for cert in load_certs(path)? {
+                        roots.add(cert).map_err(|e| {
+                            invalid_data(format!("invalid CA certificate in {path:?}, {e}"))
+                        })?;
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            builder.with_root_certificates(roots)
+        };
+        let config = match (&self.cert_file, &self.key_file) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(|e| invalid_data(format!("client certificate / key mismatch, {e}")))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "a client certificate needs both the certificate and the key file",
+                ));
+            }
+        };
+        Ok(ClientTls {
+            config: Arc::new(config),
+            server_name: self.server_name.clone(),
+        })
+    }
+}
+
+impl TlsServer {
+    /// reads the files or generates the certificate
+    pub(crate) fn load(&self) -> io::Result<ServerTls> {
+        let (certs, key) = match self {
+            TlsServer::SelfSigned => {
+                let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+                    .map_err(io::Error::other)?;
+                log::info!("[TLS] generated a self-signed certificate for localhost");
+                let key = PrivateKeyDer::Pkcs8(generated.signing_key.serialize_der().into());
+                (vec![generated.cert.der().clone()], key)
+            }
+            TlsServer::Pem {
+                cert_file,
+                key_file,
+            } => (load_certs(cert_file)?, load_key(key_file)?),
+        };
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid_data(format!("certificate / key mismatch, {e}")))?;
+        Ok(ServerTls {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl ClientTls {
+    /// handshakes over a freshly connected `tcp`, `remote` is the
+    /// address it was connected with, for the default server name
+    pub(crate) fn connect(&self, tcp: TcpStream, remote: &str) -> io::Result<Stream> {
+        let name = match &self.server_name {
+            Some(name) => name.trim(),
+            None => host_of(remote),
+        };
+        let name = ServerName::try_from(name.to_string())
+            .map_err(|e| invalid_data(format!("invalid server name {name:?}, {e}")))?;
+        let conn = ClientConnection::new(self.config.clone(), name).map_err(io::Error::other)?;
+        Stream::handshake(tcp, conn.into())
+    }
+}
+
+impl ServerTls {
+    /// handshakes over a freshly accepted `tcp`
+    pub(crate) fn accept(&self, tcp: TcpStream) -> io::Result<Stream> {
+        let conn = ServerConnection::new(self.config.clone()).map_err(io::Error::other)?;
+        Stream::handshake(tcp, conn.into())
+    }
+}
+
+/// "host" of "host:port" / "[v6]:port"
+fn host_of(remote: &str) -> &str {
+    let host = remote.rsplit_once(':').map_or(remote, |(host, _)| host);
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .split('%')
+        .next()
+        .unwrap_or_default()
+}
+
+/// for accept_invalid, signatures are still checked so that
+/// the handshake itself stays sound
+#[derive(Debug)]
+struct AcceptAnyCert(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+/// a tcp connection, plain or tls, shared as `Arc<Stream>` between
+/// the reading thread and the senders, see the module notes
+///
+/// `&Stream` implements Read and Write like `&TcpStream` does
+pub struct Stream {
+    tcp: TcpStream,
+    tls: Option<Mutex<rustls::Connection>>,
+    // raw bytes read from the socket, empty for plain tcp
+    raw: Mutex<Vec<u8>>,
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tls {
+            None => self.tcp.fmt(f),
+            Some(_) => write!(f, "TLS {:?}", self.tcp),
+        }
+    }
+}
+
+impl From<TcpStream> for Stream {
+    fn from(tcp: TcpStream) -> Self {
+        Stream {
+            tcp,
+            tls: None,
+            raw: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl Stream {
+    fn handshake(tcp: TcpStream, mut conn: rustls::Connection) -> io::Result<Stream> {
+        let read_timeout = tcp.read_timeout()?;
+        tcp.set_nonblocking(false)?;
+        tcp.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        tcp.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut &tcp)?;
+        }
+        tcp.set_read_timeout(read_timeout)?;
+        tcp.set_write_timeout(None)?;
+
+        let stream = Stream {
+            tcp,
+            tls: Some(Mutex::new(conn)),
+            raw: Mutex::new(vec![0; RECORD_SIZE]),
+        };
+        log::info!(
+            "[TLS] {:?} with {}",
+            stream.peer_addr(),
+            stream.tls_info().unwrap_or_default()
+        );
+        Ok(stream)
+    }
+
+    fn lock(tls: &Mutex<rustls::Connection>) -> MutexGuard<'_, rustls::Connection> {
+        tls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// the negotiated version and cipher suite, None for plain tcp
+    pub fn tls_info(&self) -> Option<String> {
+        let conn = Self::lock(self.tls.as_ref()?);
+        let version = conn
+            .protocol_version()
+            .map(|v| format!("{v:?}"))
+            .unwrap_or("?".to_string());
+        let suite = conn
+            .negotiated_cipher_suite()
+            .map(|s| format!("{:?}", s.suite()))
+            .unwrap_or("?".to_string());
+        Some(format!("{version} {suite}"))
+    }
+
+    /// the socket underneath, eg for socket options
+    pub fn tcp(&self) -> &TcpStream {
+        &self.tcp
+    }
+
+    /// same as TcpStream::peer_addr()
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.peer_addr()
+    }
+
+    /// same as TcpStream::local_addr()
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+
+    /// same as TcpStream::set_nonblocking()
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.tcp.set_nonblocking(nonblocking)
+    }
+
+    /// a tls stream says goodbye (close_notify) first when writing stops
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if let Some(tls) = &self.tls
+            && how != Shutdown::Read
+        {
+            Self::lock(tls).send_close_notify();
+            let _ = Self::flush_tls(tls, &self.tcp);
+        }
+        self.tcp.shutdown(how)
+    }
+
+    fn flush_tls(tls: &Mutex<rustls::Connection>, mut tcp: &TcpStream) -> io::Result<()> {
+        loop {
+            let mut conn = Self::lock(tls);
+            while conn.wants_write() {
+                match conn.write_tls(&mut tcp) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            if !conn.wants_write() {
+                return Ok(());
+            }
+            // a full nonblocking socket, the plaintext is taken already
+            // so the records have to go out before returning, the other
+            // thread may read meanwhile, records stay in order either way
+            drop(conn);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(tls) = &self.tls else {
+            return (&self.tcp).read(buf);
+        };
+        let mut raw = self.raw.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            // whatever was decrypted already comes first
+            match Stream::lock(tls).reader().read(buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            // no lock while waiting, errors like WouldBlock go to the caller
+            let n = (&self.tcp).read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut conn = Stream::lock(tls);
+            let mut rest = &raw[..n];
+            while !rest.is_empty() {
+                conn.read_tls(&mut rest)?;
+                conn.process_new_packets().map_err(invalid_data_from)?;
+            }
+            drop(conn);
+            // eg a key update answer
+            Stream::flush_tls(tls, &self.tcp)?;
+        }
+    }
+}
+
+fn invalid_data_from(e: rustls::Error) -> io::Error {
+    invalid_data(e.to_string())
+}
+
+impl Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(tls) = &self.tls else {
+            return (&self.tcp).write(buf);
+        };
+        let n = Stream::lock(tls).writer().write(buf)?;
+        Stream::flush_tls(tls, &self.tcp)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.tls {
+            None => (&self.tcp).flush(),
+            Some(tls) => Stream::flush_tls(tls, &self.tcp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(host_of("device.lan:443"), "device.lan");
+        assert_eq!(host_of("10.0.0.5:502"), "10.0.0.5");
+        assert_eq!(host_of("[fe80::1%eth0]:8443"), "fe80::1");
+        assert_eq!(host_of("[::1]:443"), "::1");
+    }
+
+    #[test]
+    fn test_bad_files() {
+        let missing = TlsClient {
+            ca_file: Some("/nonexistent/ca.pem".into()),
+            ..TlsClient::default()
+        };
+        assert!(missing.load().is_err());
+        let half = TlsClient {
+            cert_file: Some("/nonexistent/client.pem".into()),
+            ..TlsClient::default()
+        };
+        assert_eq!(
+            half.load().err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+        assert!(TlsServer::SelfSigned.load().is_ok());
+    }
+}
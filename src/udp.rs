@@ -1,10 +1,17 @@
+//! udp socket with a receiving thread, see [`Udp`]
+
 use std::io;
-use std::net::UdpSocket;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::mem::MaybeUninit;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+use socket2::{SockAddr, SockRef};
+
+use crate::echo::{Echo, Replies};
+
 /*
     udp runs in blocking mode
 
@@ -19,18 +26,125 @@ use std::time::Duration;
         efram::egui
             uses ctx.repaint instead of sending messages via channel
 
+    receive size
+
+        the worker reads into a buffer of recv_buffer_size() bytes,
+        what does not fit is dropped by the OS like it would be for
+        any application reading with that size, to still tell the
+        real length of a cut datagram linux and android get it back
+        from recvfrom() with MSG_TRUNC, elsewhere (windows errors with
+        WSAEMSGSIZE, macos has no MSG_TRUNC on input) the datagram is
+        peeked into a buffer big enough for anything first
+
+    tap
+
+        a second channel for the received datagrams, for a reader
+        on another thread (the app's bridge) that cannot wait for
+        whoever polls the events, set_tap(None) ends it
+
 */
 
 const READ_TIMEOUT: Duration = Duration::from_millis(100);
 
+/// the biggest payload an IPv4 datagram can carry,
+/// 65535 - 20 (ip header) - 8 (udp header)
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// the peek buffer where there is no MSG_TRUNC, see the notes
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const SCRATCH_SIZE: usize = u16::MAX as usize + 1;
+
 /// this is used when stuff happening inside worker thread
 /// GUI is unable to update ontime because we cannot call
 /// ctx.repaint() here, this is also for better decoupling
-// pub enum UpdWorkerEvent {
-//     Packet { src: SocketAddr, data: Vec<u8> },
-//     Error(io::Error),
-// }
+///
+/// the worker only emits, what to do with the data (logging,
+/// tables, filters...) is up to whoever calls poll_events()
+#[derive(Debug)]
+pub enum UdpEvent {
+    /// one datagram
+    Packet {
+        /// sender address
+        src: SocketAddr,
+        /// payload, at most recv_buffer_size() bytes
+        data: Vec<u8>,
+        /// when the worker got it
+        timestamp: chrono::DateTime<chrono::Local>,
+
+        /// Some(original length) if the datagram was bigger
+        /// than the receive buffer and `data` got cut
+        truncated: Option<usize>,
+    },
+
+    /// one datagram went out, from send_data_to() or the echo
+    Sent {
+        /// destination address
+        dst: SocketAddr,
+        /// payload
+        data: Vec<u8>,
+        /// when it was handed to the OS
+        timestamp: chrono::DateTime<chrono::Local>,
+    },
+
+    /// a receive error, the worker keeps going
+    Error(io::Error),
+
+    /// the worker loop has ended, sent once per start()
+    Stopped,
+}
+
+/// sends through the socket of a [`Udp`] from any thread, a clone
+/// follows the socket across rebinds, see Udp::sender()
+#[derive(Clone)]
+pub struct UdpSender {
+    // set by the owner on bind and on disconnect
+    socket: Arc<Mutex<Option<Arc<UdpSocket>>>>,
+    is_running: Arc<AtomicBool>,
+    event_tx: mpsc::Sender<UdpEvent>,
+}
 
+impl UdpSender {
+    /// same as Udp::is_up()
+    pub fn is_up(&self) -> bool {
+        self.is_running.load(Ordering::Relaxed)
+    }
+
+    /// same as Udp::send_data_to()
+    pub fn send_data_to(&self, data: &[u8], to: &str) -> io::Result<()> {
+        let msg = String::from_utf8_lossy(data);
+
+        let socket = self
+            .socket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let Some(sock) = socket else {
+            log::error!("UDP socket not initialized");
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "UDP socket not initialized",
+            ));
+        };
+        match Udp::resolve(&sock, to).and_then(|dst| sock.send_to(data, dst).map(|_| dst)) {
+            Ok(dst) => {
+                log::info!("[UDP SEND] {:?} to {}", msg, to);
+                let _ = self.event_tx.send(UdpEvent::Sent {
+                    dst,
+                    data: data.to_vec(),
+                    timestamp: chrono::Local::now(),
+                });
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("error sending {:?} to {to}, {e}", msg);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// one bound udp socket, sending happens on the caller's
+/// thread and receiving on a worker thread
 pub struct Udp {
     // arc is for socket to be used in thread
     // the option, is a complicated topic, it is needed anyways
@@ -45,18 +159,57 @@ pub struct Udp {
     // this is not actual state but desired value from GUI
     bc: bool,
 
-    // event_tx: Sender<UpdWorkerEvent>,
-    // event_rx: Receiver<UpdWorkerEvent>,
+    // same as bc, desired values applied again on every bind
+    mc_ttl: u32,
+    mc_loop: bool,
+
+    // shared with the worker so changes apply to the next datagram
+    recv_buffer_size: Arc<AtomicUsize>,
+
+    // (group, interface) pairs joined on the current socket
+    // they are left automatically when the socket is dropped
+    mc_groups: Vec<(Ipv4Addr, Ipv4Addr)>,
+
+    // None is off, read by the worker for every datagram
+    echo: Arc<Mutex<Option<Echo>>>,
+
+    // gets a copy of every datagram besides the events, see set_tap()
+    tap: Arc<Mutex<Option<mpsc::Sender<UdpEvent>>>>,
+
+    // same as TcpServer, the worker thread emits through
+    // the channel and the owner drains it with poll_events()
+    event_tx: mpsc::Sender<UdpEvent>,
+    event_rx: mpsc::Receiver<UdpEvent>,
     worker: Option<JoinHandle<()>>,
+
+    // shares the socket with threads that send, kept in step with it
+    sender: UdpSender,
 }
 
 impl Default for Udp {
     fn default() -> Self {
+        let (event_tx, event_rx) = mpsc::channel();
+        let is_running = Arc::new(AtomicBool::new(false));
+        let sender = UdpSender {
+            socket: Arc::new(Mutex::new(None)),
+            is_running: is_running.clone(),
+            event_tx: event_tx.clone(),
+        };
+
         Udp {
             socket: None,
-            is_running: Arc::new(AtomicBool::new(false)),
+            is_running,
             bc: false,
+            mc_ttl: 1,
+            mc_loop: true,
+            recv_buffer_size: Arc::new(AtomicUsize::new(MAX_DATAGRAM_SIZE)),
+            mc_groups: vec![],
+            echo: Arc::new(Mutex::new(None)),
+            tap: Arc::new(Mutex::new(None)),
+            event_tx,
+            event_rx,
             worker: None,
+            sender,
         }
     }
 }
@@ -78,10 +231,13 @@ impl Udp {
         })
     }
 
+    /// true while the receiving thread runs
     pub fn is_up(&self) -> bool {
         self.is_running.load(Ordering::Relaxed)
     }
 
+    /// binds `sockaddr` (eg "0.0.0.0:0") and starts receiving,
+    /// returns the port actually bound
     pub fn connect_and_start(&mut self, sockaddr: String) -> io::Result<String> {
         let port = self.connect(sockaddr)?;
         self.start()?;
@@ -94,10 +250,17 @@ impl Udp {
         socket.set_broadcast(self.bc)?;
         socket.set_read_timeout(Some(READ_TIMEOUT))?;
 
+        // the multicast options here are the v4 ones
+        // setting them on a v6 socket is an error
+        if socket.local_addr()?.is_ipv4() {
+            socket.set_multicast_ttl_v4(self.mc_ttl)?;
+            socket.set_multicast_loop_v4(self.mc_loop)?;
+        }
+
         let port = socket.local_addr()?.port().to_string();
 
         let socket = Arc::new(socket);
-        self.socket = Some(socket);
+        self.set_socket(Some(socket));
 
         log::info!("UDP socket bound: {:?}", self.socket);
         Ok(port)
@@ -106,7 +269,8 @@ impl Udp {
     /// terminate the worker and drop the binding
     pub fn disconnect(&mut self) {
         self.stop();
-        self.socket.take();
+        self.set_socket(None);
+        self.mc_groups.clear();
         log::debug!("UDP disconnected, socket = {:?}", self.socket);
     }
 
@@ -125,6 +289,10 @@ impl Udp {
             return Err(io::Error::new(io::ErrorKind::NotConnected, "UDP not bound"));
         };
         let is_running = self.is_running.clone();
+        let event_tx = self.event_tx.clone();
+        let recv_buffer_size = self.recv_buffer_size.clone();
+        let echo = self.echo.clone();
+        let tap = self.tap.clone();
 
         // set state
         self.is_running.store(true, Ordering::Relaxed);
@@ -132,13 +300,49 @@ impl Udp {
         // spawn workder thread
         // this runs forever until the flag has been set
         let handle = thread::spawn(move || {
-            let mut buf = [0u8; 1024];
+            let mut buf = vec![];
+            let mut scratch = vec![];
+            let mut replies = Replies::default();
             while is_running.load(Ordering::Relaxed) {
-                match socket.recv_from(&mut buf) {
+                // a new size applies from the next datagram
+                buf.resize(recv_buffer_size.load(Ordering::Relaxed), 0);
+                match recv_from(&socket, &mut buf, &mut scratch) {
                     Ok((n, src)) => {
-                        let bytes = buf[..n].to_vec();
-                        let msg = String::from_utf8_lossy(&bytes);
-                        log::info!("[UDP RECV] {:?} from {}", msg, src);
+                        let data = &buf[..n.min(buf.len())];
+                        let packet = || UdpEvent::Packet {
+                            src,
+                            data: data.to_vec(),
+                            timestamp: chrono::Local::now(),
+                            truncated: (n > buf.len()).then_some(n),
+                        };
+                        let _ = event_tx.send(packet());
+                        if let Some(tap) = &*tap.lock().unwrap_or_else(PoisonError::into_inner) {
+                            let _ = tap.send(packet());
+                        }
+
+                        // what was received, a cut datagram goes back cut
+                        let echo = echo.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                        if let Some(echo) = echo {
+                            let reply = echo.reply(data);
+                            let socket = socket.clone();
+                            let event_tx = event_tx.clone();
+                            replies.schedule(echo.delay, move || {
+                                match socket.send_to(&reply, src) {
+                                    Ok(_) => {
+                                        log::info!(
+                                            "[UDP ECHO] {:?} to {src}",
+                                            String::from_utf8_lossy(&reply)
+                                        );
+                                        let _ = event_tx.send(UdpEvent::Sent {
+                                            dst: src,
+                                            data: reply,
+                                            timestamp: chrono::Local::now(),
+                                        });
+                                    }
+                                    Err(e) => log::error!("echo to {src} failed, {e}"),
+                                }
+                            });
+                        }
                     }
                     Err(e)
                         if e.kind() == io::ErrorKind::WouldBlock
@@ -152,7 +356,7 @@ impl Udp {
                     // An existing connection was forcibly closed by the remote host. (os error 10054)
                     // also, no need to exit the thread in case of receiving error
                     Err(e) => {
-                        log::error!("receiving error: {e}");
+                        let _ = event_tx.send(UdpEvent::Error(e));
                     }
                 }
             }
@@ -162,6 +366,7 @@ impl Udp {
             // if somehow this line is reached then we have a
             // problem of not releasing the socket
             log::debug!("UDP worker loop ended");
+            let _ = event_tx.send(UdpEvent::Stopped);
         });
 
         log::info!("UDP worker thread started: {:?}", handle.thread());
@@ -169,6 +374,7 @@ impl Udp {
         Ok(())
     }
 
+    /// ends the receiving thread but keeps the socket
     pub fn stop(&mut self) {
         self.is_running.store(false, Ordering::Relaxed);
 
@@ -190,12 +396,32 @@ impl Udp {
         }
 
         // if not bound, user can also flip the option
+        self.bc = flag;
         Ok(())
 
         // // simulating a setting failure
         // Err(io::Error::new(io::ErrorKind::Other, "simulated"))
     }
 
+    /// how many bytes of each datagram are read, the OS drops anything
+    /// beyond and the packet is marked as truncated with its real
+    /// length, applies from the next datagram, also on a running worker
+    pub fn set_recv_buffer_size(&mut self, size: usize) -> io::Result<()> {
+        if size == 0 || size > MAX_DATAGRAM_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("receive buffer must be within 1..={MAX_DATAGRAM_SIZE}"),
+            ));
+        }
+        self.recv_buffer_size.store(size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// see set_recv_buffer_size()
+    pub fn recv_buffer_size(&self) -> usize {
+        self.recv_buffer_size.load(Ordering::Relaxed)
+    }
+
     /// get the value of the SO_BROADCAST
     #[allow(dead_code)]
     #[deprecated = "might be useful if the state is controlled within"]
@@ -206,24 +432,287 @@ impl Udp {
         false
     }
 
-    pub fn send_data_to(&self, msg: &str, to: &str) {
-        let data = msg.as_bytes();
+    /// join an IPv4 multicast group on the given local interface,
+    /// UNSPECIFIED lets the OS pick the interface
+    ///
+    /// the interface is also set as the outgoing one (IP_MULTICAST_IF)
+    /// so that sending to the group leaves from the same netif,
+    /// std does not expose that option hence socket2
+    pub fn join_multicast(&mut self, group: Ipv4Addr, iface: Ipv4Addr) -> io::Result<()> {
+        if !group.is_multicast() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{group} is not a multicast address"),
+            ));
+        }
+
+        let Some(sock) = &self.socket else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "UDP not bound"));
+        };
+
+        if self.mc_groups.contains(&(group, iface)) {
+            return Ok(());
+        }
+
+        sock.join_multicast_v4(&group, &iface)?;
+        if !iface.is_unspecified() {
+            socket2::SockRef::from(sock.as_ref()).set_multicast_if_v4(&iface)?;
+        }
+        self.mc_groups.push((group, iface));
+
+        log::info!("joined multicast group {group} on {iface}");
+        Ok(())
+    }
+
+    /// undo join_multicast()
+    pub fn leave_multicast(&mut self, group: Ipv4Addr, iface: Ipv4Addr) -> io::Result<()> {
+        let Some(sock) = &self.socket else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "UDP not bound"));
+        };
+
+        sock.leave_multicast_v4(&group, &iface)?;
+        self.mc_groups.retain(|g| *g != (group, iface));
+
+        log::info!("left multicast group {group} on {iface}");
+        Ok(())
+    }
+
+    /// groups joined on the current binding, as (group, interface)
+    pub fn multicast_groups(&self) -> &[(Ipv4Addr, Ipv4Addr)] {
+        &self.mc_groups
+    }
+
+    /// same idea as toggle_broadcast, applied now if bound
+    /// and remembered for the next bind anyways
+    pub fn set_multicast_ttl(&mut self, ttl: u32) -> io::Result<()> {
+        if let Some(sock) = self.socket.as_ref().filter(|s| Self::is_v4(s)) {
+            sock.set_multicast_ttl_v4(ttl)?;
+        }
+        self.mc_ttl = ttl;
+        Ok(())
+    }
+
+    /// whether our own multicast sends are looped back to us, same rules as the ttl
+    pub fn set_multicast_loop(&mut self, flag: bool) -> io::Result<()> {
+        if let Some(sock) = self.socket.as_ref().filter(|s| Self::is_v4(s)) {
+            sock.set_multicast_loop_v4(flag)?;
+        }
+        self.mc_loop = flag;
+        Ok(())
+    }
+
+    fn is_v4(sock: &UdpSocket) -> bool {
+        sock.local_addr().is_ok_and(|a| a.is_ipv4())
+    }
+
+    /// the bound address, None while not bound
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.as_ref().and_then(|s| s.local_addr().ok())
+    }
+
+    /// None turns echo off, applies from the next datagram
+    pub fn set_echo(&self, echo: Option<Echo>) {
+        *self.echo.lock().unwrap_or_else(PoisonError::into_inner) = echo;
+    }
+
+    /// see set_echo()
+    pub fn echo(&self) -> Option<Echo> {
+        self.echo
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// every received datagram also goes to `tap` from now on, as
+    /// a Packet event, for a second reader that does not wait for
+    /// poll_events(), None stops it and ends the receiver's iterator
+    pub fn set_tap(&self, tap: Option<mpsc::Sender<UdpEvent>>) {
+        *self.tap.lock().unwrap_or_else(PoisonError::into_inner) = tap;
+    }
+
+    /// raw bytes so that both text and hex mode go through here,
+    /// errors are logged as well as returned for callers that count them
+    pub fn send_data_to(&self, data: &[u8], to: &str) -> io::Result<()> {
+        self.sender.send_data_to(data, to)
+    }
+
+    /// a handle for sending from other threads, see UdpSender
+    pub fn sender(&self) -> UdpSender {
+        self.sender.clone()
+    }
+
+    fn set_socket(&mut self, socket: Option<Arc<UdpSocket>>) {
+        *self
+            .sender
+            .socket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = socket.clone();
+        self.socket = socket;
+    }
 
-        if let Some(ref sock) = self.socket {
-            match sock.send_to(&data, to) {
-                Ok(_) => log::info!("[UDP SEND] {:?} to {}", msg, to),
-                Err(e) => log::error!("error sending {:?} to {to}, {e}", msg),
+    /// `to` as a socket address, a host name may resolve to both
+    /// families so the one of our socket is preferred
+    fn resolve(sock: &UdpSocket, to: &str) -> io::Result<SocketAddr> {
+        let v4 = Self::is_v4(sock);
+        let addrs: Vec<SocketAddr> = to.to_socket_addrs()?.collect();
+        addrs
+            .iter()
+            .find(|a| a.is_ipv4() == v4)
+            .or(addrs.first())
+            .copied()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("cannot resolve {to}"))
+            })
+    }
+
+    /// drain everything the worker has emitted since the last call
+    pub fn poll_events(&self) -> Vec<UdpEvent> {
+        self.event_rx.try_iter().collect()
+    }
+}
+
+/// receives one datagram into `buf`, returns its real length, above
+/// buf.len() if it was cut, see the module notes
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    _scratch: &mut Vec<u8>,
+) -> io::Result<(usize, SocketAddr)> {
+    let (n, src) = SockRef::from(socket).recv_from_with_flags(uninit(buf), libc::MSG_TRUNC)?;
+    Ok((n, socket_addr(src)?))
+}
+
+/// receives one datagram into `buf`, returns its real length, above
+/// buf.len() if it was cut, see the module notes
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    scratch: &mut Vec<u8>,
+) -> io::Result<(usize, SocketAddr)> {
+    let sock = SockRef::from(socket);
+    scratch.resize(SCRATCH_SIZE, 0);
+    let (len, _) = sock.peek_from(uninit(scratch))?;
+    // a cut datagram is not an error here, unlike with recv_from() on windows
+    let (n, _, src) =
+        sock.recv_from_vectored(&mut [socket2::MaybeUninitSlice::new(uninit(buf))])?;
+    Ok((len.max(n), socket_addr(src)?))
+}
+
+/// socket2 reads into possibly uninitialized memory, an initialized buffer is fine too
+fn uninit(buf: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    // SAFETY: same layout, and the OS only ever writes initialized bytes into it
+    unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) }
+}
+
+fn socket_addr(addr: SockAddr) -> io::Result<SocketAddr> {
+    addr.as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "sender is not an ip address"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// everything up to the first packet, give the
+    /// worker a few read timeouts to pick it up
+    fn wait_packet(udp: &Udp) -> Vec<UdpEvent> {
+        let mut events = vec![];
+        for _ in 0..20 {
+            events.extend(udp.poll_events());
+            if events
+                .iter()
+                .any(|ev| matches!(ev, UdpEvent::Packet { .. }))
+            {
+                break;
             }
-        } else {
-            log::error!("UDP socket not initialized");
+            thread::sleep(Duration::from_millis(50));
+        }
+        events
+    }
+
+    #[test]
+    fn test_packet_event_on_loopback() {
+        let mut udp = Udp::default();
+        let port = udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+        udp.send_data_to(b"hello", &format!("127.0.0.1:{port}"))
+            .unwrap();
+
+        // the send is reported before anything can come back
+        let events = wait_packet(&udp);
+        match events.first() {
+            Some(UdpEvent::Sent { dst, data, .. }) => {
+                assert_eq!(data, b"hello");
+                assert_eq!(dst.to_string(), format!("127.0.0.1:{port}"));
+            }
+            other => panic!("expected a sent event, got {other:?}"),
+        }
+
+        match events.get(1) {
+            Some(UdpEvent::Packet { src, data, .. }) => {
+                assert_eq!(data, b"hello");
+                assert_eq!(src.port().to_string(), port);
+            }
+            other => panic!("expected a packet event, got {other:?}"),
         }
+
+        udp.disconnect();
+        assert!(matches!(udp.poll_events().last(), Some(UdpEvent::Stopped)));
     }
 
-    // pub fn poll_events(&self) -> Vec<UpdWorkerEvent> {
-    //     let mut out = Vec::new();
-    //     while let Ok(ev) = self.event_rx.try_recv() {
-    //         out.push(ev);
-    //     }
-    //     out
-    // }
+    #[test]
+    fn test_echo() {
+        let mut udp = Udp::default();
+        udp.set_echo(Some(Echo {
+            transform: crate::echo::Transform::Uppercase,
+            delay: Duration::ZERO,
+        }));
+        let port = udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        peer.send_to(b"hi there", format!("127.0.0.1:{port}"))
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        let (n, _) = peer.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"HI THERE");
+
+        udp.set_echo(None);
+        assert_eq!(udp.echo(), None);
+    }
+
+    #[test]
+    fn test_truncated_packet() {
+        let mut udp = Udp::default();
+        udp.set_recv_buffer_size(4).unwrap();
+        assert!(udp.set_recv_buffer_size(MAX_DATAGRAM_SIZE + 1).is_err());
+
+        let port = udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+        udp.send_data_to(b"0123456789", &format!("127.0.0.1:{port}"))
+            .unwrap();
+
+        match wait_packet(&udp).last() {
+            Some(UdpEvent::Packet {
+                data, truncated, ..
+            }) => {
+                assert_eq!(data, b"0123");
+                assert_eq!(*truncated, Some(10));
+            }
+            other => panic!("expected a packet event, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_join_multicast_rejects_unicast() {
+        let mut udp = Udp::default();
+        udp.connect_and_start("127.0.0.1:0".to_string()).unwrap();
+
+        let err = udp
+            .join_multicast(Ipv4Addr::new(192, 168, 0, 1), Ipv4Addr::UNSPECIFIED)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(udp.multicast_groups().is_empty());
+    }
 }
@@ -1,5 +1,8 @@
+//! `log` backend that formats lines and hands them over a channel
+
 use std::sync::mpsc;
 
+/// see init()
 pub struct Xlogger {
     tx: mpsc::Sender<String>,
 }
@@ -55,7 +58,7 @@ impl Xlogger {
 ///     - ERR
 ///
 /// examples
-/// ```
+/// ```text
 /// log_line!("SYS", "INFO", "--- new session ---");
 /// log_line!("CONN", format!("{:?}", self.connection));
 /// ```